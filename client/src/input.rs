@@ -5,13 +5,15 @@ pub struct InputState {
     rotating_left: bool,
     rotating_right: bool,
     spin_timer: f32,
+    shoot_pending: bool,
 }
 
-#[derive(Debug, Clone)]
-pub enum PlayerAction {
-    RotateLeft,
-    RotateRight,
-    Shoot,
+/// Input state sampled once per frame and sent to the server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameInput {
+    pub left: bool,
+    pub right: bool,
+    pub shoot: bool,
 }
 
 impl InputState {
@@ -25,22 +27,12 @@ impl InputState {
         }
     }
 
-    /// Collect continuous rotation states and resets pending shoot
-    pub fn consume_actions(&mut self) -> Option<Vec<PlayerAction>> {
-        let mut actions = vec![];
-
-        // Continuous rotation
-        if self.rotating_left {
-            actions.push(PlayerAction::RotateLeft);
-        }
-        if self.rotating_right {
-            actions.push(PlayerAction::RotateRight);
-        }
-
-        if actions.is_empty() {
-            None
-        } else {
-            Some(actions)
+    /// Samples the rotation keys and consumes a pending shot
+    pub fn take_frame_input(&mut self) -> FrameInput {
+        FrameInput {
+            left: self.rotating_left,
+            right: self.rotating_right,
+            shoot: std::mem::take(&mut self.shoot_pending),
         }
     }
 
@@ -62,21 +54,29 @@ impl InputState {
         }
     }
 
-    pub fn process_key_up(&mut self, key: KeyCode) -> Option<PlayerAction> {
+    /// Lets go of the rotation keys without shooting, e.g. when the chat takes over the keyboard.
+    pub fn release_all(&mut self) {
+        self.rotating_left = false;
+        self.rotating_right = false;
+        self.spin_timer = 0.0;
+    }
+
+    /// Releasing a rotation key shoots
+    pub fn process_key_up(&mut self, key: KeyCode) {
         match key {
             KeyCode::ArrowLeft if self.rotating_left => {
                 self.rotating_left = false;
                 self.spin_timer = 0.0;
-                Some(PlayerAction::Shoot)
+                self.shoot_pending = true;
             }
 
             KeyCode::ArrowRight if self.rotating_right => {
                 self.rotating_right = false;
                 self.spin_timer = 0.0;
-                Some(PlayerAction::Shoot)
+                self.shoot_pending = true;
             }
 
-            _ => None,
+            _ => {}
         }
     }
 }
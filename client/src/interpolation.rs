@@ -0,0 +1,221 @@
+use spin_snowball_shared::*;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// How far behind the estimated server time remote entities are rendered.
+/// Three ticks at 30 Hz, so one or two late packets still leave a snapshot to
+/// interpolate towards.
+const INTERPOLATION_DELAY: f64 = 0.1;
+/// How long entities keep moving along their last velocity once the buffer runs dry.
+const MAX_EXTRAPOLATION: f64 = 0.25;
+/// Upper bound on buffered snapshots, in case rendering stalls.
+const MAX_BUFFERED: usize = 32;
+/// A clock offset that jumps by more than this (e.g. after switching rooms)
+/// is taken over immediately instead of being smoothed.
+const CLOCK_RESYNC_THRESHOLD: f64 = 0.5;
+/// Fraction of each clock offset sample folded into the running estimate.
+const CLOCK_SMOOTHING: f64 = 0.1;
+
+/// The moving parts of a snapshot, stamped with its server time.
+struct TimedSnapshot {
+    server_time: f64,
+    players: Vec<PlayerState>,
+    snowballs: Vec<SnowballState>,
+    ball: Option<BallState>,
+}
+
+impl From<&WorldState> for TimedSnapshot {
+    fn from(world: &WorldState) -> Self {
+        Self {
+            server_time: world.server_time,
+            players: world.players.clone(),
+            snowballs: world.snowballs.clone(),
+            ball: world.ball.clone(),
+        }
+    }
+}
+
+/// Remote entities at the render time.
+pub struct Sample {
+    pub players: Vec<PlayerState>,
+    pub snowballs: Vec<SnowballState>,
+    pub ball: Option<BallState>,
+}
+
+/// Jitter buffer for remote players, snowballs and the ball.
+///
+/// Snapshots are rendered `INTERPOLATION_DELAY` behind the estimated server
+/// time by interpolating between the two snapshots around that time. When no
+/// newer snapshot has arrived the newest one is extrapolated for at most
+/// `MAX_EXTRAPOLATION` seconds and then held.
+pub struct Interpolator {
+    started: Instant,
+    buffer: VecDeque<TimedSnapshot>,
+    /// Estimated `server_time - local_time`.
+    clock_offset: Option<f64>,
+}
+
+impl Default for Interpolator {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            buffer: VecDeque::new(),
+            clock_offset: None,
+        }
+    }
+}
+
+impl Interpolator {
+    fn local_time(&self) -> f64 {
+        self.started.elapsed().as_secs_f64()
+    }
+
+    pub fn push(&mut self, world: &WorldState) {
+        let offset = world.server_time - self.local_time();
+        let resync = match self.clock_offset {
+            Some(current) => (offset - current).abs() > CLOCK_RESYNC_THRESHOLD,
+            None => true,
+        };
+        if resync {
+            // a different room or a restarted server: old snapshots are meaningless
+            self.buffer.clear();
+            self.clock_offset = Some(offset);
+        } else if let Some(current) = &mut self.clock_offset {
+            *current += (offset - *current) * CLOCK_SMOOTHING;
+        }
+
+        // snapshots can be resent or overtaken; keep the buffer ordered and unique
+        if self
+            .buffer
+            .back()
+            .is_some_and(|last| last.server_time >= world.server_time)
+        {
+            return;
+        }
+
+        self.buffer.push_back(TimedSnapshot::from(world));
+        if self.buffer.len() > MAX_BUFFERED {
+            self.buffer.pop_front();
+        }
+    }
+
+    /// Interpolated entities for the current frame, or `None` before the first snapshot.
+    pub fn sample(&mut self) -> Option<Sample> {
+        let render_time = self.local_time() + self.clock_offset? - INTERPOLATION_DELAY;
+
+        // drop snapshots that can no longer bracket the render time
+        while self.buffer.len() > 2 && self.buffer[1].server_time <= render_time {
+            self.buffer.pop_front();
+        }
+
+        let newest = self.buffer.back()?;
+        if render_time >= newest.server_time {
+            let ahead = (render_time - newest.server_time).min(MAX_EXTRAPOLATION) as f32;
+            return Some(extrapolate(newest, ahead));
+        }
+
+        let from = self.buffer.front()?;
+        if render_time <= from.server_time || self.buffer.len() < 2 {
+            return Some(extrapolate(from, 0.0));
+        }
+
+        let to = &self.buffer[1];
+        let t = ((render_time - from.server_time) / (to.server_time - from.server_time)) as f32;
+        Some(interpolate(from, to, t))
+    }
+}
+
+/// Blends the moving parts of two snapshots; everything else is taken from `to`.
+pub fn blend(from: &WorldState, to: &WorldState, t: f32) -> WorldState {
+    let sample = interpolate(&TimedSnapshot::from(from), &TimedSnapshot::from(to), t);
+    WorldState {
+        players: sample.players,
+        snowballs: sample.snowballs,
+        ball: sample.ball,
+        ..to.clone()
+    }
+}
+
+fn lerp(a: [f32; 2], b: [f32; 2], t: f32) -> [f32; 2] {
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t]
+}
+
+fn advance(pos: [f32; 2], vel: [f32; 2], dt: f32) -> [f32; 2] {
+    [pos[0] + vel[0] * dt, pos[1] + vel[1] * dt]
+}
+
+/// Interpolates along the shorter way around the circle.
+fn lerp_angle_deg(a: f32, b: f32, t: f32) -> f32 {
+    let diff = (b - a + 180.0).rem_euclid(360.0) - 180.0;
+    a + diff * t
+}
+
+/// Entities follow the newer snapshot: those that only exist there pop in at
+/// its position, those that disappeared are dropped.
+fn interpolate(from: &TimedSnapshot, to: &TimedSnapshot, t: f32) -> Sample {
+    let players = to
+        .players
+        .iter()
+        .map(|p| {
+            let mut p = p.clone();
+            if let Some(old) = from.players.iter().find(|o| o.id == p.id) {
+                p.pos = lerp(old.pos, p.pos, t);
+                p.vel = lerp(old.vel, p.vel, t);
+                p.rot_deg = lerp_angle_deg(old.rot_deg, p.rot_deg, t);
+            }
+            p
+        })
+        .collect();
+
+    let snowballs = to
+        .snowballs
+        .iter()
+        .map(|s| {
+            let mut s = s.clone();
+            if let Some(old) = from.snowballs.iter().find(|o| o.id == s.id) {
+                s.pos = lerp(old.pos, s.pos, t);
+                s.vel = lerp(old.vel, s.vel, t);
+            }
+            s
+        })
+        .collect();
+
+    let ball = to.ball.clone().map(|mut b| {
+        if let Some(old) = &from.ball {
+            b.pos = lerp(old.pos, b.pos, t);
+            b.vel = lerp(old.vel, b.vel, t);
+        }
+        b
+    });
+
+    Sample {
+        players,
+        snowballs,
+        ball,
+    }
+}
+
+fn extrapolate(snapshot: &TimedSnapshot, dt: f32) -> Sample {
+    Sample {
+        players: snapshot
+            .players
+            .iter()
+            .map(|p| PlayerState {
+                pos: advance(p.pos, p.vel, dt),
+                ..p.clone()
+            })
+            .collect(),
+        snowballs: snapshot
+            .snowballs
+            .iter()
+            .map(|s| SnowballState {
+                pos: advance(s.pos, s.vel, dt),
+                ..s.clone()
+            })
+            .collect(),
+        ball: snapshot.ball.clone().map(|b| BallState {
+            pos: advance(b.pos, b.vel, dt),
+            ..b
+        }),
+    }
+}
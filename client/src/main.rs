@@ -1,27 +1,31 @@
 use ggez::event::{self, EventHandler};
-use ggez::input::keyboard::KeyInput;
+use ggez::glam::Vec2;
+use ggez::input::keyboard::{KeyCode, KeyInput};
 use ggez::winit::keyboard::PhysicalKey;
 use ggez::{Context, ContextBuilder, GameError, GameResult};
+use spin_snowball_shared::validation::validate_map;
+use spin_snowball_shared::wire::WireFormat;
 use spin_snowball_shared::*;
 use std::{env, fs};
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::channel;
 
 mod input;
+mod interpolation;
 mod network;
-mod physics;
+mod prediction;
 mod rendering;
+mod replay;
 mod state;
 mod text_input_workaround;
 mod ui;
 
-use input::InputState;
+use input::{FrameInput, InputState};
 use network::NetworkClient;
-use physics::update_physics;
-use rendering::Renderer;
+use rendering::{Camera, Renderer};
+use replay::ReplayViewer;
 use state::GameState;
 
-use crate::input::PlayerAction;
 use crate::text_input_workaround::CharInput;
 use crate::ui::{UIMessage, UiState};
 
@@ -33,16 +37,26 @@ struct MainState {
     ui: UiState,
     ui_events_rx: Receiver<UIMessage>,
     char_input: CharInput,
+    /// Set while a recorded match is shown instead of the live game.
+    replay: Option<ReplayViewer>,
+    /// Last input sent to the server and the seconds since it was sent.
+    sent_input: Option<(FrameInput, f32)>,
 }
 
 impl MainState {
-    fn new(server_addr: &str, mut ctx: &mut Context) -> GameResult<Self> {
+    fn new(server_addr: &str, wire_format: WireFormat, mut ctx: &mut Context) -> GameResult<Self> {
         // Load map
         let map_data = std::fs::read_to_string("default_map.json")?;
         let map: GameMap = serde_json::from_str(&map_data).unwrap();
-        let network = NetworkClient::new(server_addr);
+        let network = NetworkClient::new(server_addr, wire_format);
         network.send(ClientMessage::Command {
-            cmd: Command::JoinAsPlayer { team: Team::Team1 },
+            cmd: Command::JoinAsPlayer { team: None },
+        });
+        network.send(ClientMessage::Command {
+            cmd: Command::ListRooms,
+        });
+        network.send(ClientMessage::Command {
+            cmd: Command::ListMaps,
         });
         let (tx, rx) = channel();
         Ok(Self {
@@ -53,6 +67,8 @@ impl MainState {
             ui: UiState::new(&mut ctx, tx),
             ui_events_rx: rx,
             char_input: CharInput::new(),
+            replay: None,
+            sent_input: None,
         })
     }
 
@@ -77,19 +93,44 @@ impl MainState {
                 UIMessage::Resume => self.network.send(ClientMessage::Command {
                     cmd: Command::Resume,
                 }),
-                UIMessage::LoadMap { path } => {
-                    let data = std::fs::read_to_string(path).unwrap();
-                    self.game.map = serde_json::from_str(&data).unwrap();
-                    self.network.send(ClientMessage::Command {
-                        cmd: Command::LoadMap { data },
-                    });
+                UIMessage::LoadMap { path } => match std::fs::read_to_string(&path) {
+                    Ok(data) => {
+                        // errors come back from the server, which refuses such maps;
+                        // warnings are only found here
+                        if let Ok(map) = serde_json::from_str::<GameMap>(&data) {
+                            for warning in validate_map(&map).warnings() {
+                                self.ui.push_error(format!("{}: {}", path, warning));
+                            }
+                        }
+                        self.network.send(ClientMessage::Command {
+                            cmd: Command::LoadMap { data },
+                        });
+                    }
+                    Err(e) => self.ui.push_error(format!("Cannot read map {}: {}", path, e)),
+                },
+                UIMessage::LoadScript { path: None } => self.network.send(ClientMessage::Command {
+                    cmd: Command::SetScript { source: None },
+                }),
+                UIMessage::LoadScript { path: Some(path) } => {
+                    match std::fs::read_to_string(&path) {
+                        Ok(source) => self.network.send(ClientMessage::Command {
+                            cmd: Command::SetScript {
+                                source: Some(source),
+                            },
+                        }),
+                        Err(e) => self
+                            .ui
+                            .push_error(format!("Cannot read script {}: {}", path, e)),
+                    }
                 }
                 UIMessage::JoinTeam { player_id, status } => {
                     if let Some(own_id) = &self.game.player.id {
                         if player_id == *own_id {
                             let cmd = match status {
                                 PlayerStatus::Spectator => Command::JoinAsSpectator,
-                                PlayerStatus::Playing(team) => Command::JoinAsPlayer { team },
+                                PlayerStatus::Playing(team) => {
+                                    Command::JoinAsPlayer { team: Some(team) }
+                                }
                             };
                             self.network.send(ClientMessage::Command { cmd });
                         }
@@ -115,6 +156,105 @@ impl MainState {
                         cmd: Command::SetGameMode { game_mode, action_target_time }
                     });
                 },
+                UIMessage::ListRooms => {
+                    self.network.send(ClientMessage::Command {
+                        cmd: Command::ListRooms,
+                    });
+                }
+                UIMessage::CreateRoom {
+                    name,
+                    host_password,
+                } => {
+                    self.network.send(ClientMessage::Command {
+                        cmd: Command::CreateRoom {
+                            name,
+                            host_password,
+                        },
+                    });
+                }
+                UIMessage::JoinRoom { name } => {
+                    self.network.send(ClientMessage::Command {
+                        cmd: Command::JoinRoom { name },
+                    });
+                }
+                UIMessage::LeaveRoom => {
+                    self.network.send(ClientMessage::Command {
+                        cmd: Command::LeaveRoom,
+                    });
+                }
+                UIMessage::ClaimHost { password } => {
+                    self.network.send(ClientMessage::Command {
+                        cmd: Command::ClaimHost { password },
+                    });
+                }
+                UIMessage::SetAdmin { player_id, admin } => {
+                    self.network.send(ClientMessage::Command {
+                        cmd: Command::SetAdmin { player_id, admin },
+                    });
+                }
+                UIMessage::AddBot { team, difficulty } => {
+                    self.network.send(ClientMessage::Command {
+                        cmd: Command::AddBot { team, difficulty },
+                    });
+                }
+                UIMessage::RemoveBot { player_id } => {
+                    self.network.send(ClientMessage::Command {
+                        cmd: Command::RemoveBot { player_id },
+                    });
+                }
+                UIMessage::ListMaps => {
+                    self.network.send(ClientMessage::Command {
+                        cmd: Command::ListMaps,
+                    });
+                }
+                UIMessage::JoinSmallerTeam => {
+                    self.network.send(ClientMessage::Command {
+                        cmd: Command::JoinAsPlayer { team: None },
+                    });
+                }
+                UIMessage::ShuffleTeams { by_rating } => {
+                    self.network.send(ClientMessage::Command {
+                        cmd: Command::ShuffleTeams { by_rating },
+                    });
+                }
+                UIMessage::GetLeaderboard => {
+                    self.network.send(ClientMessage::Command {
+                        cmd: Command::GetLeaderboard,
+                    });
+                }
+                UIMessage::SelectMap { name } => {
+                    self.network.send(ClientMessage::Command {
+                        cmd: Command::SelectMap { name },
+                    });
+                }
+                UIMessage::Chat { channel, text } => {
+                    self.network.send(ClientMessage::Chat { channel, text });
+                }
+                UIMessage::OpenReplay { path } => match ReplayViewer::open(&path) {
+                    Ok(replay) => self.replay = Some(replay),
+                    Err(e) => self.ui.push_error(e),
+                },
+                UIMessage::CloseReplay => self.replay = None,
+                UIMessage::ToggleReplayPlayback => {
+                    if let Some(replay) = &mut self.replay {
+                        replay.toggle_playback();
+                    }
+                }
+                UIMessage::SeekReplay { time } => {
+                    if let Some(replay) = &mut self.replay {
+                        replay.seek(time);
+                    }
+                }
+                UIMessage::SetReplaySpeed { speed } => {
+                    if let Some(replay) = &mut self.replay {
+                        replay.set_speed(speed);
+                    }
+                }
+                UIMessage::ResetReplayCamera => {
+                    if let Some(replay) = &mut self.replay {
+                        replay.camera = Camera::default();
+                    }
+                }
             }
         }
     }
@@ -125,66 +265,111 @@ impl EventHandler for MainState {
         let dt = ctx.time.delta().as_secs_f32();
 
         self.input.update(dt);
-        self.ui.update(&self.game, ctx);
-        if let Some(actions) = self.input.consume_actions() {
-            for action in actions {
-                match action {
-                    PlayerAction::RotateLeft => {
-                        self.network.send(ClientMessage::Input {
-                            left: true,
-                            right: false,
-                            shoot: false,
-                        });
-                    }
-                    PlayerAction::RotateRight => {
-                        self.network.send(ClientMessage::Input {
-                            left: false,
-                            right: true,
-                            shoot: false,
-                        });
-                    }
-                    _ => {}
+        if let Some(replay) = &mut self.replay {
+            replay.update(dt);
+        }
+        self.ui.update(&self.game, self.replay.as_ref(), ctx);
+        // Every frame gets a numbered input, which is applied locally right away and
+        // replayed on top of each snapshot until the server has processed it. The
+        // server keeps the last input it got, so one is only sent on a shot, when it
+        // changes or once per server tick; its number covers the unsent frames before it
+        let frame = self.input.take_frame_input();
+        if self.game.is_controlling() {
+            let seq = self
+                .game
+                .predict_input(frame.left, frame.right, frame.shoot, dt);
+            let due = match &mut self.sent_input {
+                Some((sent, since)) => {
+                    *since += dt;
+                    frame.shoot
+                        || *sent != frame
+                        || *since >= self.game.prediction.tick_interval()
                 }
+                None => true,
+            };
+            if due {
+                self.sent_input = Some((frame, 0.0));
+                self.network.send(ClientMessage::Input {
+                    seq,
+                    left: frame.left,
+                    right: frame.right,
+                    shoot: frame.shoot,
+                });
             }
+        } else {
+            self.sent_input = None;
         }
 
         // Handle incoming network state
-        if let Some(msg) = self.network.poll() {
+        while let Some(msg) = self.network.poll() {
             match msg {
-                ServerMessage::AssignId { id } => {
+                ServerMessage::AssignId { id, .. } => {
                     self.game.player.id = Some(id);
                 }
+                ServerMessage::ServerInfo { name, tick_rate } => {
+                    self.game.server_name = Some(name);
+                    self.game.prediction.set_tick_rate(tick_rate);
+                }
                 ServerMessage::WorldState {
                     world
                 } => {
-                    let scores = [(Team::Team1, world.scores_team1), (Team::Team2, world.scores_team2)].into();
-                    self.game.apply_world_state(
-                        world.players,
-                        world.snowballs,
-                        world.ball,
-                        scores,
-                        world.phase,
-                        world.time_elapsed,
-                        world.paused,
-                        world.team1_color,
-                        world.team2_color,
-                        world.player_with_active_action,
-                        world.game_mode,
-                        world.action_target_time
-                    );
+                    let seq = self.game.receive_world(world);
+                    self.network.send(ClientMessage::Ack { seq });
+                }
+                ServerMessage::WorldDelta { delta } => {
+                    if let Some(seq) = self.game.receive_delta(&delta) {
+                        self.network.send(ClientMessage::Ack { seq });
+                    }
                 }
                 ServerMessage::Pong { .. } => {}
                 ServerMessage::PhysicsSettings { settings } => {
-                    self.game.map.physics = settings;
+                    self.game.set_physics(settings);
                 },
                 ServerMessage::Map { map } => {
-                    self.game.map = map;
+                    self.game.reset_snapshots();
+                    self.game.set_map(map);
                 },
+                ServerMessage::RoomList { rooms } => {
+                    self.game.rooms = rooms;
+                }
+                ServerMessage::RoomJoined { name } => {
+                    self.game.reset_snapshots();
+                    self.game.room = Some(name);
+                }
+                ServerMessage::MapList { maps } => {
+                    self.game.maps = maps;
+                }
+                ServerMessage::Leaderboard { entries } => {
+                    self.game.leaderboard = entries;
+                }
+                ServerMessage::Chat {
+                    nick,
+                    channel,
+                    text,
+                    ..
+                } => {
+                    self.ui.push_chat(nick, channel, text);
+                }
+                ServerMessage::GameEvent { event } => {
+                    self.ui.push_event(&event, &self.game);
+                }
+                ServerMessage::MatchSummary { summary } => {
+                    self.ui.show_summary(summary);
+                    // the match just changed the ratings
+                    self.network.send(ClientMessage::Command {
+                        cmd: Command::GetLeaderboard,
+                    });
+                }
+                ServerMessage::Error { code, message } => {
+                    println!("Server rejected a request ({:?}): {}", code, message);
+                    self.ui.push_error(message);
+                }
+                ServerMessage::CommandAck { .. } => {}
             }
         }
 
-        // Update physics
-        update_physics(&mut self.game, dt);
+        // Remote entities are drawn slightly in the past, between two snapshots
+        self.game.interpolate_remote();
 
         // Optional: ping server for latency measurements
         if ctx.time.ticks() % 300 == 0 {
@@ -199,39 +384,66 @@ impl EventHandler for MainState {
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        self.renderer
-            .draw(ctx, &self.game, self.input.spin_timer())?;
+        match &self.replay {
+            Some(replay) => self
+                .renderer
+                .draw(ctx, replay.game(), 0.0, Some(&replay.camera))?,
+            None => self
+                .renderer
+                .draw(ctx, &self.game, self.input.spin_timer(), None)?,
+        }
         self.ui.render(ctx);
         Ok(())
     }
 
     fn key_down_event(
         &mut self,
-        _ctx: &mut Context,
+        ctx: &mut Context,
         input: KeyInput,
         _repeat: bool,
     ) -> Result<(), GameError> {
         if let PhysicalKey::Code(keycode) = input.event.physical_key {
-            self.input.process_key_down(keycode);
+            if self.ui.is_chat_open() {
+                match keycode {
+                    KeyCode::Enter | KeyCode::NumpadEnter => self.ui.submit_chat(),
+                    KeyCode::Escape => self.ui.close_chat(),
+                    _ => {}
+                }
+                return Ok(());
+            }
+
+            match &mut self.replay {
+                Some(replay) => replay.process_key_down(keycode, screen_size(ctx)),
+                None if matches!(keycode, KeyCode::Enter | KeyCode::NumpadEnter) => {
+                    self.input.release_all();
+                    self.ui.open_chat();
+                }
+                None => self.input.process_key_down(keycode),
+            }
         }
         Ok(())
     }
 
     fn key_up_event(&mut self, _ctx: &mut Context, input: KeyInput) -> Result<(), GameError> {
+        if self.ui.is_chat_open() {
+            return Ok(());
+        }
         if let PhysicalKey::Code(keycode) = input.event.physical_key {
-            if let Some(action) = self.input.process_key_up(keycode) {
-                if let PlayerAction::Shoot = action {
-                    self.network.send(ClientMessage::Input {
-                        left: false,
-                        right: false,
-                        shoot: true,
-                    });
-                }
+            match &mut self.replay {
+                Some(replay) => replay.process_key_up(keycode),
+                None => self.input.process_key_up(keycode),
             }
         }
         Ok(())
     }
 
+    fn mouse_wheel_event(&mut self, ctx: &mut Context, _x: f32, y: f32) -> Result<(), GameError> {
+        if let Some(replay) = &mut self.replay {
+            replay.zoom(y, screen_size(ctx));
+        }
+        Ok(())
+    }
+
     //doesnt trigger for some reason - maybe it will be fixed one day
     // fn text_input_event(&mut self, ctx: &mut Context, character: char) -> Result<(), GameError> {
     //     self.ui.text_input_event(ctx, character);
@@ -239,9 +451,23 @@ impl EventHandler for MainState {
     // }
 }
 
+fn screen_size(ctx: &Context) -> Vec2 {
+    let (w, h) = ctx.gfx.drawable_size();
+    Vec2::new(w, h)
+}
+
 pub fn main() -> GameResult {
     let default_addr = "127.0.0.1:9001".to_string();
-    let mut addr = env::args().nth(1).unwrap_or(default_addr);
+    let mut addr = env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with("--"))
+        .unwrap_or(default_addr);
+    // JSON is easier to inspect while debugging; binary is much smaller on the wire
+    let wire_format = if env::args().any(|arg| arg == "--json") {
+        WireFormat::Json
+    } else {
+        WireFormat::Binary
+    };
     if let Ok(contents) = fs::read_to_string("server.txt") {
         let trimmed = contents.trim();
         if !trimmed.is_empty() {
@@ -253,6 +479,6 @@ pub fn main() -> GameResult {
         .window_setup(ggez::conf::WindowSetup::default().title("Snowball Spin - Client"))
         .window_mode(ggez::conf::WindowMode::default().dimensions(1200.0, 800.0))
         .build()?;
-    let client = MainState::new(&addr, &mut ctx)?;
+    let client = MainState::new(&addr, wire_format, &mut ctx)?;
     event::run(ctx, event_loop, client)
 }
@@ -1,3 +1,4 @@
+use spin_snowball_shared::wire::{BinaryMessage, RESUME_QUERY_KEY, WIRE_QUERY_KEY, WireFormat};
 use spin_snowball_shared::*;
 use std::sync::mpsc::{Receiver, Sender, TryRecvError, channel};
 use std::thread;
@@ -5,79 +6,165 @@ use std::time::Duration;
 use tungstenite::{Message, connect};
 use url::Url;
 
-pub fn spawn_network_thread(server_addr: &str) -> (Sender<ClientMessage>, Receiver<ServerMessage>) {
+fn encode_message(msg: &ClientMessage, format: WireFormat) -> Option<Message> {
+    match format {
+        WireFormat::Json => serde_json::to_string(msg)
+            .ok()
+            .map(|txt| Message::Text(txt.into())),
+        WireFormat::Binary => msg
+            .to_binary()
+            .ok()
+            .map(|bytes| Message::Binary(bytes.into())),
+    }
+}
+
+/// Attempts to get back into the session after the connection dropped, one per second.
+const RECONNECT_ATTEMPTS: u32 = 30;
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+type Socket = tungstenite::WebSocket<tungstenite::stream::MaybeTlsStream<std::net::TcpStream>>;
+
+/// Why a connection ended.
+enum Closed {
+    /// The client side went away; there is nobody left to reconnect for.
+    Shutdown,
+    /// The connection was lost and may be resumed.
+    Lost,
+}
+
+pub fn spawn_network_thread(
+    server_addr: &str,
+    format: WireFormat,
+) -> (Sender<ClientMessage>, Receiver<ServerMessage>) {
     let (to_net_tx, to_net_rx) = channel::<ClientMessage>();
     let (from_net_tx, from_net_rx) = channel::<ServerMessage>();
     let server = server_addr.to_string();
 
     thread::spawn(move || {
-        let url = Url::parse(&format!("ws://{}", server)).expect("Invalid WebSocket URL");
-        println!("Connecting to {}", url);
-
-        let (mut socket, _response) = match connect(url.to_string()) {
-            Ok(pair) => pair,
-            Err(e) => {
-                eprintln!("WebSocket connect error: {}", e);
-                return;
-            }
-        };
-
-        // Optional: set read timeout so thread doesn’t block forever
-        // if let Some(underlying) = socket.get_mut().get_mut() {
-        //     let _ = underlying.set_read_timeout(Some(Duration::from_millis(10)));
-        // }
+        let url = Url::parse(&format!(
+            "ws://{}/?{}={}",
+            server,
+            WIRE_QUERY_KEY,
+            format.as_str()
+        ))
+        .expect("Invalid WebSocket URL");
 
+        // the token of the current session, learned from `AssignId`
+        let mut token: Option<String> = None;
+        let mut attempts = 0;
         loop {
-            // 1. Send all pending outbound messages
-            while let Ok(msg) = to_net_rx.try_recv() {
-                if let Ok(txt) = serde_json::to_string(&msg) {
-                    if socket.send(Message::Text(txt.into())).is_err() {
-                        eprintln!("Write error, closing network thread");
+            let mut url = url.clone();
+            if let Some(token) = &token {
+                url.query_pairs_mut().append_pair(RESUME_QUERY_KEY, token);
+            }
+            println!("Connecting to {}", url);
+            let mut socket = match connect(url.to_string()) {
+                Ok((socket, _response)) => socket,
+                Err(e) => {
+                    eprintln!("WebSocket connect error: {}", e);
+                    if token.is_none() || attempts >= RECONNECT_ATTEMPTS {
                         return;
                     }
+                    attempts += 1;
+                    thread::sleep(RECONNECT_DELAY);
+                    continue;
                 }
+            };
+
+            if token.is_some() {
+                // whatever was sent while offline is stale by now
+                while to_net_rx.try_recv().is_ok() {}
             }
+            attempts = 0;
 
-            // 2. Try to read one incoming message (blocking up to 10 ms)
-            match socket.read() {
-                Ok(Message::Text(txt)) => {
-                    if let Ok(sm) = serde_json::from_str::<ServerMessage>(&txt) {
-                        let _ = from_net_tx.send(sm);
-                    }
-                }
-                Err(tungstenite::Error::Io(ref e))
-                    if e.kind() == std::io::ErrorKind::WouldBlock
-                        || e.kind() == std::io::ErrorKind::TimedOut =>
-                {
-                    // just timeout, no problem
-                }
-                Err(tungstenite::Error::ConnectionClosed) => {
-                    println!("Server closed connection");
-                    return;
-                }
-                Err(e) => {
-                    eprintln!("Read error: {}", e);
-                    return;
+            match run_connection(&mut socket, format, &to_net_rx, &from_net_tx, &mut token) {
+                Closed::Shutdown => return,
+                Closed::Lost if token.is_some() => {
+                    println!("Connection lost, trying to resume the session");
+                    thread::sleep(RECONNECT_DELAY);
                 }
-                _ => {}
+                Closed::Lost => return,
             }
-
-            // Small sleep to avoid busy loop
-            thread::sleep(Duration::from_millis(2));
         }
     });
 
     (to_net_tx, from_net_rx)
 }
 
+fn run_connection(
+    socket: &mut Socket,
+    format: WireFormat,
+    to_net_rx: &Receiver<ClientMessage>,
+    from_net_tx: &Sender<ServerMessage>,
+    token: &mut Option<String>,
+) -> Closed {
+    // Optional: set read timeout so thread doesn’t block forever
+    // if let Some(underlying) = socket.get_mut().get_mut() {
+    //     let _ = underlying.set_read_timeout(Some(Duration::from_millis(10)));
+    // }
+
+    loop {
+        // 1. Send all pending outbound messages
+        loop {
+            let msg = match to_net_rx.try_recv() {
+                Ok(msg) => msg,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Closed::Shutdown,
+            };
+            if let Some(msg) = encode_message(&msg, format) {
+                if socket.send(msg).is_err() {
+                    eprintln!("Write error, connection lost");
+                    return Closed::Lost;
+                }
+            }
+        }
+
+        // 2. Try to read one incoming message (blocking up to 10 ms)
+        let received = match socket.read() {
+            Ok(Message::Text(txt)) => serde_json::from_str::<ServerMessage>(&txt).ok(),
+            Ok(Message::Binary(bytes)) => ServerMessage::from_binary(&bytes).ok(),
+            Err(tungstenite::Error::Io(ref e))
+                if e.kind() == std::io::ErrorKind::WouldBlock
+                    || e.kind() == std::io::ErrorKind::TimedOut =>
+            {
+                // just timeout, no problem
+                None
+            }
+            Err(tungstenite::Error::ConnectionClosed) => {
+                println!("Server closed connection");
+                return Closed::Lost;
+            }
+            Err(e) => {
+                eprintln!("Read error: {}", e);
+                return Closed::Lost;
+            }
+            _ => None,
+        };
+        if let Some(sm) = received {
+            if let ServerMessage::AssignId {
+                token: new_token, ..
+            } = &sm
+            {
+                *token = Some(new_token.clone());
+            }
+            if from_net_tx.send(sm).is_err() {
+                return Closed::Shutdown;
+            }
+        }
+
+        // Small sleep to avoid busy loop
+        thread::sleep(Duration::from_millis(2));
+    }
+}
+
 pub struct NetworkClient {
     tx: Sender<ClientMessage>,
     rx: Receiver<ServerMessage>,
 }
 
 impl NetworkClient {
-    pub fn new(server_addr: &str) -> Self {
-        let (tx, rx) = spawn_network_thread(server_addr);
+    pub fn new(server_addr: &str, format: WireFormat) -> Self {
+        let (tx, rx) = spawn_network_thread(server_addr, format);
         Self { tx, rx }
     }
 
@@ -0,0 +1,144 @@
+use ggez::glam::Vec2;
+use spin_snowball_shared::*;
+use spin_snowball_simulation::{FixedTimestep, Player, World};
+use std::collections::VecDeque;
+
+/// Inputs older than this are dropped even if the server never confirmed them.
+const MAX_PENDING_INPUTS: usize = 240;
+
+/// Input sent to the server but not yet confirmed by a snapshot.
+struct PendingInput {
+    seq: u32,
+    left: bool,
+    right: bool,
+    shoot: bool,
+    dt: f32,
+}
+
+/// Predicts the own player from local inputs and reconciles with server snapshots
+/// by replaying the inputs the server has not processed yet.
+///
+/// Runs the same movement and collisions as the server on a world that only
+/// contains the own player and the map, so walls and holes are predicted too;
+/// other players, snowballs and the ball come from the snapshots. The mode rules
+/// are left to the server, their effects arrive with the snapshots.
+pub struct Prediction {
+    next_seq: u32,
+    pending: VecDeque<PendingInput>,
+    world: World,
+    timestep: FixedTimestep,
+}
+
+fn player_from_state(p: &PlayerState) -> Player {
+    Player {
+        id: p.id.clone(),
+        nick: p.nick.clone(),
+        pos: Vec2::new(p.pos[0], p.pos[1]),
+        vel: Vec2::new(p.vel[0], p.vel[1]),
+        rot_deg: p.rot_deg,
+        rotating_left: false,
+        rotating_right: false,
+        spin_timer: p.spin_timer,
+        last_shoot_pressed: false,
+        status: p.status,
+        shoot_cooldown_sec: p.shoot_cooldown_sec,
+    }
+}
+
+impl Prediction {
+    pub fn new(map: GameMap) -> Self {
+        let mut world = World::new(map);
+        world.ball = None;
+        Self {
+            next_seq: 0,
+            pending: VecDeque::new(),
+            world,
+            timestep: FixedTimestep::default(),
+        }
+    }
+
+    pub fn set_map(&mut self, map: GameMap) {
+        self.world.set_map(map);
+        // the ball is not predicted
+        self.world.ball = None;
+    }
+
+    /// Steps the prediction at the server's tick rate.
+    pub fn set_tick_rate(&mut self, tick_rate: f32) {
+        self.timestep = FixedTimestep::new(1.0 / tick_rate);
+    }
+
+    /// Seconds between two server ticks.
+    pub fn tick_interval(&self) -> f32 {
+        self.timestep.dt()
+    }
+
+    pub fn set_physics(&mut self, settings: PhysicsSettings) {
+        self.world.map.physics = settings;
+    }
+
+    pub fn player(&self) -> Option<&Player> {
+        self.world.players.values().next()
+    }
+
+    /// Applies an input locally and returns the sequence number to send it with.
+    pub fn push_input(&mut self, left: bool, right: bool, shoot: bool, dt: f32) -> u32 {
+        self.next_seq += 1;
+        let seq = self.next_seq;
+
+        let input = PendingInput {
+            seq,
+            left,
+            right,
+            shoot,
+            dt,
+        };
+        self.simulate(&input);
+
+        self.pending.push_back(input);
+        if self.pending.len() > MAX_PENDING_INPUTS {
+            self.pending.pop_front();
+        }
+        seq
+    }
+
+    /// Rewinds to the authoritative state and replays every input the server has not seen.
+    pub fn reconcile(&mut self, server: &PlayerState) {
+        self.pending.retain(|i| i.seq > server.last_input_seq);
+
+        self.load(server);
+        let pending = std::mem::take(&mut self.pending);
+        for input in &pending {
+            self.simulate(input);
+        }
+        self.pending = pending;
+    }
+
+    /// Takes the server state as is, e.g. while not playing.
+    pub fn reset(&mut self, server: &PlayerState) {
+        self.pending.clear();
+        self.load(server);
+    }
+
+    fn load(&mut self, server: &PlayerState) {
+        self.world.players.clear();
+        self.world
+            .players
+            .insert(server.id.clone(), player_from_state(server));
+        self.world.snowballs.clear();
+        // events come from the server, the predicted ones are thrown away
+        self.world.events.clear();
+        self.timestep.reset();
+    }
+
+    fn simulate(&mut self, input: &PendingInput) {
+        let Some(id) = self.world.players.keys().next().cloned() else {
+            return;
+        };
+        self.world
+            .apply_input(&id, input.left, input.right, input.shoot);
+        for _ in 0..self.timestep.advance(input.dt) {
+            self.world.step_physics(self.timestep.dt());
+        }
+    }
+}
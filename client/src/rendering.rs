@@ -9,13 +9,58 @@ use crate::state::GameState;
 
 pub struct Renderer;
 
+/// View into the map used by the replay viewer. The default camera shows the
+/// map 1:1 from the top-left corner, like a live match.
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    /// Map position shown in the top-left corner of the window.
+    pub offset: Vec2,
+    pub zoom: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            offset: Vec2::ZERO,
+            zoom: 1.0,
+        }
+    }
+}
+
+impl Camera {
+    const MIN_ZOOM: f32 = 0.25;
+    const MAX_ZOOM: f32 = 8.0;
+
+    /// Zooms by `factor` while keeping the map point in the middle of the window in place.
+    pub fn zoom_by(&mut self, factor: f32, screen_size: Vec2) {
+        let center = self.offset + screen_size / (2.0 * self.zoom);
+        self.zoom = (self.zoom * factor).clamp(Self::MIN_ZOOM, Self::MAX_ZOOM);
+        self.offset = center - screen_size / (2.0 * self.zoom);
+    }
+
+    fn screen_rect(&self, screen_size: Vec2) -> graphics::Rect {
+        let size = screen_size / self.zoom;
+        graphics::Rect::new(self.offset.x, self.offset.y, size.x, size.y)
+    }
+}
+
 impl Renderer {
     pub fn new() -> Self {
         Self
     }
 
-    pub fn draw(&self, ctx: &mut Context, state: &GameState, spin_timer: f32) -> GameResult {
+    pub fn draw(
+        &self,
+        ctx: &mut Context,
+        state: &GameState,
+        spin_timer: f32,
+        camera: Option<&Camera>,
+    ) -> GameResult {
         let mut canvas = graphics::Canvas::from_frame(ctx, Color::from_rgb(20, 20, 30));
+        if let Some(camera) = camera {
+            let (w, h) = ctx.gfx.drawable_size();
+            canvas.set_screen_coordinates(camera.screen_rect(Vec2::new(w, h)));
+        }
         let mut mb = MeshBuilder::new();
 
         // Draw map
@@ -81,6 +126,31 @@ impl Renderer {
 
                     mb.line(&[Vec2::new(*ax, *ay), Vec2::new(*bx, *by)], 3.0, c)?;
                 }
+
+                MapObject::Polygon {
+                    points,
+                    factor: _,
+                    color,
+                    is_hole,
+                    mask: _,
+                } => {
+                    let mut c = Color::from_rgba(
+                        color.r,
+                        color.g,
+                        color.b,
+                        color.a,
+                    );
+
+                    if *is_hole {
+                        c.a *= 0.6;
+                    }
+
+                    let points: Vec<Vec2> = points.iter().map(|p| Vec2::new(p.x, p.y)).collect();
+                    // the server refuses maps with fewer corners, but a mesh would fail on them
+                    if points.len() >= 3 {
+                        mb.polygon(DrawMode::fill(), &points, c)?;
+                    }
+                }
             }
         }
 
@@ -155,27 +225,27 @@ impl Renderer {
                 0.5,
                 color,
             )?;
-        }
 
-        // direction indicator triangle for local player
-        let dir = state.forward_vector();
-        let tip = Vec2::new(
-            state.player.pos.x + dir.x * (state.map.physics.player_radius + 8.0),
-            state.player.pos.y + dir.y * (state.map.physics.player_radius + 8.0),
-        );
-        let left = Vec2::new(
-            state.player.pos.x + (-dir.y) * 8.0,
-            state.player.pos.y + (dir.x) * 8.0,
-        );
-        let right = Vec2::new(
-            state.player.pos.x + (dir.y) * 8.0,
-            state.player.pos.y + (-dir.x) * 8.0,
-        );
-        mb.polygon(
-            DrawMode::fill(),
-            &[tip, left, right],
-            Color::from_rgb(255, 100, 100),
-        )?;
+            // direction indicator triangle for local player
+            let dir = state.forward_vector();
+            let tip = Vec2::new(
+                state.player.pos.x + dir.x * (state.map.physics.player_radius + 8.0),
+                state.player.pos.y + dir.y * (state.map.physics.player_radius + 8.0),
+            );
+            let left = Vec2::new(
+                state.player.pos.x + (-dir.y) * 8.0,
+                state.player.pos.y + (dir.x) * 8.0,
+            );
+            let right = Vec2::new(
+                state.player.pos.x + (dir.y) * 8.0,
+                state.player.pos.y + (-dir.x) * 8.0,
+            );
+            mb.polygon(
+                DrawMode::fill(),
+                &[tip, left, right],
+                Color::from_rgb(255, 100, 100),
+            )?;
+        }
 
         // snowballs
         for sb in &state.snowballs {
@@ -303,6 +373,21 @@ impl Renderer {
             }
         }
 
+        if let Some(text) = &state.hud_text {
+            let hud_text = Text::new(
+                TextFragment::new(text.as_str())
+                    .color(Color::WHITE)
+                    .scale(18.0),
+            );
+
+            canvas.draw(
+                &hud_text,
+                graphics::DrawParam::default()
+                    .dest(Vec2::new(20.0, state.map.height - 60.0))
+                    .z(200),
+            );
+        }
+
         canvas.finish(ctx)
     }
 }
@@ -0,0 +1,169 @@
+use ggez::glam::Vec2;
+use ggez::input::keyboard::KeyCode;
+use spin_snowball_shared::replay::{Replay, REPLAY_VERSION};
+use spin_snowball_shared::*;
+
+use crate::interpolation::blend;
+use crate::rendering::Camera;
+use crate::state::GameState;
+
+pub const REPLAY_SPEEDS: [f32; 6] = [0.1, 0.25, 0.5, 1.0, 2.0, 4.0];
+
+/// Camera pan speed in screen pixels per second.
+const PAN_SPEED: f32 = 600.0;
+const ZOOM_STEP: f32 = 1.25;
+
+/// Plays back a recorded match into its own `GameState`, which is then drawn
+/// with the regular renderer through a free camera.
+pub struct ReplayViewer {
+    game: GameState,
+    snapshots: Vec<WorldState>,
+    tick_hz: f32,
+    time: f32,
+    playing: bool,
+    speed: f32,
+    pub camera: Camera,
+    pan_left: bool,
+    pan_right: bool,
+    pan_up: bool,
+    pan_down: bool,
+}
+
+impl ReplayViewer {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let bytes =
+            std::fs::read(path).map_err(|e| format!("Cannot read replay {}: {}", path, e))?;
+        let invalid = |e| format!("{} is not a valid replay: {}", path, e);
+        let version = Replay::peek_version(&bytes).map_err(invalid)?;
+        if version != REPLAY_VERSION {
+            return Err(format!(
+                "{} was recorded with replay version {}, expected {}",
+                path, version, REPLAY_VERSION
+            ));
+        }
+        let replay = Replay::decode(&bytes).map_err(invalid)?;
+
+        let mut viewer = Self {
+            game: GameState::new(replay.map.clone()),
+            snapshots: replay.snapshots(),
+            tick_hz: replay.tick_hz,
+            time: 0.0,
+            playing: true,
+            speed: 1.0,
+            camera: Camera::default(),
+            pan_left: false,
+            pan_right: false,
+            pan_up: false,
+            pan_down: false,
+        };
+        viewer.show_frame();
+        Ok(viewer)
+    }
+
+    pub fn game(&self) -> &GameState {
+        &self.game
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.snapshots.len().saturating_sub(1) as f32 / self.tick_hz
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    pub fn toggle_playback(&mut self) {
+        if !self.playing && self.time >= self.duration() {
+            self.time = 0.0;
+        }
+        self.playing = !self.playing;
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.time = time.clamp(0.0, self.duration());
+        self.show_frame();
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let mut pan = Vec2::ZERO;
+        if self.pan_left {
+            pan.x -= 1.0;
+        }
+        if self.pan_right {
+            pan.x += 1.0;
+        }
+        if self.pan_up {
+            pan.y -= 1.0;
+        }
+        if self.pan_down {
+            pan.y += 1.0;
+        }
+        self.camera.offset += pan * PAN_SPEED * dt / self.camera.zoom;
+
+        if self.playing {
+            self.time += dt * self.speed;
+            if self.time >= self.duration() {
+                self.time = self.duration();
+                self.playing = false;
+            }
+            self.show_frame();
+        }
+    }
+
+    pub fn zoom(&mut self, steps: f32, screen_size: Vec2) {
+        self.camera.zoom_by(ZOOM_STEP.powf(steps), screen_size);
+    }
+
+    pub fn process_key_down(&mut self, key: KeyCode, screen_size: Vec2) {
+        match key {
+            KeyCode::ArrowLeft | KeyCode::KeyA => self.pan_left = true,
+            KeyCode::ArrowRight | KeyCode::KeyD => self.pan_right = true,
+            KeyCode::ArrowUp | KeyCode::KeyW => self.pan_up = true,
+            KeyCode::ArrowDown | KeyCode::KeyS => self.pan_down = true,
+            KeyCode::Equal | KeyCode::NumpadAdd => self.zoom(1.0, screen_size),
+            KeyCode::Minus | KeyCode::NumpadSubtract => self.zoom(-1.0, screen_size),
+            KeyCode::Space => self.toggle_playback(),
+            KeyCode::Home => self.camera = Camera::default(),
+            _ => {}
+        }
+    }
+
+    pub fn process_key_up(&mut self, key: KeyCode) {
+        match key {
+            KeyCode::ArrowLeft | KeyCode::KeyA => self.pan_left = false,
+            KeyCode::ArrowRight | KeyCode::KeyD => self.pan_right = false,
+            KeyCode::ArrowUp | KeyCode::KeyW => self.pan_up = false,
+            KeyCode::ArrowDown | KeyCode::KeyS => self.pan_down = false,
+            _ => {}
+        }
+    }
+
+    /// Shows the recorded world at `self.time`, blending neighbouring ticks so
+    /// slow motion stays smooth.
+    fn show_frame(&mut self) {
+        let Some(last) = self.snapshots.last() else {
+            return;
+        };
+
+        let position = self.time * self.tick_hz;
+        let index = position.floor() as usize;
+        let world = match (self.snapshots.get(index), self.snapshots.get(index + 1)) {
+            (Some(from), Some(to)) => blend(from, to, position.fract()),
+            (Some(from), None) => from.clone(),
+            _ => last.clone(),
+        };
+        self.game.apply_snapshot(&world);
+    }
+}
@@ -1,6 +1,13 @@
 use ggez::glam::Vec2;
+use spin_snowball_shared::delta::WorldDelta;
 use spin_snowball_shared::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+
+use crate::interpolation::Interpolator;
+use crate::prediction::Prediction;
+
+/// Received snapshots kept around as bases for incoming deltas.
+const SNAPSHOT_HISTORY: usize = 64;
 
 pub struct Player {
     pub id: Option<String>,
@@ -35,13 +42,26 @@ pub struct GameState {
     pub time_elapsed: f32,
     pub all_players: Vec<PlayerState>,
     pub player_status: PlayerStatus,
+    pub player_role: PlayerRole,
     pub paused: bool,
     pub team1_color: ColorDef,
     pub team2_color: ColorDef,
     pub action_player: Option<String>,
     pub action_time: f32,
+    /// Text the custom mode's script shows on the HUD.
+    pub hud_text: Option<String>,
     pub game_mode: GameMode,
-    pub action_target_time: Option<f32>
+    pub action_target_time: Option<f32>,
+    pub server_name: Option<String>,
+    pub room: Option<String>,
+    pub rooms: Vec<RoomInfo>,
+    /// The server's map library, as of the last `Command::ListMaps`.
+    pub maps: Vec<MapInfo>,
+    /// Best rated players, as of the last `Command::GetLeaderboard`.
+    pub leaderboard: Vec<LeaderboardEntry>,
+    snapshots: VecDeque<WorldState>,
+    pub prediction: Prediction,
+    interpolation: Interpolator,
 }
 
 impl GameState {
@@ -60,11 +80,13 @@ impl GameState {
             ball: None,
             scores: HashMap::new(),
             friction: map.physics.friction_per_frame,
+            prediction: Prediction::new(map.clone()),
             map,
             phase: MatchPhase::Lobby,
             time_elapsed: Default::default(),
             all_players: vec![],
             player_status: PlayerStatus::Spectator,
+            player_role: PlayerRole::Player,
             paused: Default::default(),
             team1_color: ColorDef {
                 r: 200,
@@ -80,11 +102,108 @@ impl GameState {
             },
             action_player: None,
             action_time: 0.0,
+            hud_text: None,
             game_mode: GameMode::Fight,
-            action_target_time: Some(10.0)
+            action_target_time: Some(10.0),
+            server_name: None,
+            room: None,
+            rooms: vec![],
+            maps: vec![],
+            leaderboard: vec![],
+            snapshots: VecDeque::new(),
+            interpolation: Interpolator::default(),
+        }
+    }
+
+    /// Whether local inputs currently drive the own player.
+    pub fn is_controlling(&self) -> bool {
+        matches!(self.player_status, PlayerStatus::Playing(_))
+            && matches!(self.phase, MatchPhase::Playing { .. })
+            && !self.paused
+    }
+
+    /// Predicts the effect of a local input and returns its sequence number.
+    pub fn predict_input(&mut self, left: bool, right: bool, shoot: bool, dt: f32) -> u32 {
+        let seq = self.prediction.push_input(left, right, shoot, dt);
+        self.sync_predicted_player();
+        seq
+    }
+
+    fn sync_predicted_player(&mut self) {
+        if let Some(p) = self.prediction.player() {
+            self.player.pos = p.pos;
+            self.player.vel = p.vel;
+            self.player.rotation = p.rot_deg;
+        }
+    }
+
+    pub fn set_map(&mut self, map: GameMap) {
+        self.prediction.set_map(map.clone());
+        self.map = map;
+    }
+
+    pub fn set_physics(&mut self, settings: PhysicsSettings) {
+        self.prediction.set_physics(settings.clone());
+        self.map.physics = settings;
+    }
+
+    /// Applies a full snapshot and returns the sequence number to acknowledge.
+    pub fn receive_world(&mut self, world: WorldState) -> u64 {
+        let seq = world.seq;
+        self.interpolation.push(&world);
+        self.apply_snapshot(&world);
+        self.remember_snapshot(world);
+        seq
+    }
+
+    /// Rebuilds the snapshot from its base and applies it. Returns `None` when the base
+    /// is no longer known; the server then falls back to a keyframe.
+    pub fn receive_delta(&mut self, delta: &WorldDelta) -> Option<u64> {
+        let mut world = self
+            .snapshots
+            .iter()
+            .find(|w| w.seq == delta.base_seq)?
+            .clone();
+        world.apply_delta(delta);
+        Some(self.receive_world(world))
+    }
+
+    /// Forgets all delta bases. The server does the same when it switches this client
+    /// to another room or map, so the next snapshot arrives as a keyframe.
+    pub fn reset_snapshots(&mut self) {
+        self.snapshots.clear();
+    }
+
+    fn remember_snapshot(&mut self, world: WorldState) {
+        self.snapshots.push_back(world);
+        if self.snapshots.len() > SNAPSHOT_HISTORY {
+            self.snapshots.pop_front();
         }
     }
 
+    pub fn apply_snapshot(&mut self, world: &WorldState) {
+        let world = world.clone();
+        let scores = [
+            (Team::Team1, world.scores_team1),
+            (Team::Team2, world.scores_team2),
+        ]
+        .into();
+        self.apply_world_state(
+            world.players,
+            world.snowballs,
+            world.ball,
+            scores,
+            world.phase,
+            world.time_elapsed,
+            world.paused,
+            world.team1_color,
+            world.team2_color,
+            world.hud,
+            world.game_mode,
+            world.action_target_time,
+        );
+    }
+
     pub fn apply_world_state(
         &mut self,
         players: Vec<PlayerState>,
@@ -96,22 +215,63 @@ impl GameState {
         paused: bool,
         team1_color: ColorDef,
         team2_color: ColorDef,
-        player_with_active_action: Option<(String, f32)>,
+        hud: ModeHud,
         game_mode: GameMode,
         action_target_time: Option<f32>
     ) {
-        if let Some(id) = &self.player.id {
-            for p in &players {
-                if &p.id == id {
-                    self.player.pos = Vec2::new(p.pos[0], p.pos[1]);
-                    self.player.vel = Vec2::new(p.vel[0], p.vel[1]);
-                    self.player.rotation = p.rot_deg;
-                }
+        if let Some(me) = players
+            .iter()
+            .find(|p| Some(&p.id) == self.player.id.as_ref())
+        {
+            let controlling = matches!(me.status, PlayerStatus::Playing(_))
+                && matches!(phase, MatchPhase::Playing { .. })
+                && !paused;
+            if controlling {
+                self.prediction.reconcile(me);
+            } else {
+                self.prediction.reset(me);
             }
+            self.sync_predicted_player();
+        }
+
+        self.set_remote_entities(players.clone(), snowballs, ball);
+        self.scores = scores;
+
+        self.time_elapsed = time_elapsed;
+        self.phase = phase;
+        self.all_players = players.clone();
+        if let Some(me) = players
+            .iter()
+            .find(|p| Some(&p.id) == self.player.id.as_ref())
+        {
+            self.player_status = me.status.clone();
+            self.player_role = me.role;
+        }
+        self.paused = paused;
+        self.team1_color = team1_color;
+        self.team2_color = team2_color;
+        self.action_player = hud.holder;
+        self.action_time = hud.timer;
+        self.hud_text = hud.text;
+        self.game_mode = game_mode;
+        self.action_target_time = action_target_time;
+    }
+
+    /// Moves remote players, snowballs and the ball to their interpolated
+    /// positions for this frame.
+    pub fn interpolate_remote(&mut self) {
+        if let Some(sample) = self.interpolation.sample() {
+            self.set_remote_entities(sample.players, sample.snowballs, sample.ball);
         }
+    }
 
+    fn set_remote_entities(
+        &mut self,
+        players: Vec<PlayerState>,
+        snowballs: Vec<SnowballState>,
+        ball: Option<BallState>,
+    ) {
         self.other_players = players
-            .clone()
             .into_iter()
             .filter(|p| {
                 // do not include yourself
@@ -131,33 +291,11 @@ impl GameState {
                 life: sb.life,
             })
             .collect();
-        self.scores = scores;
-
-        let (action_player, action_time) = match player_with_active_action {
-            Some(x) => (Some(x.0), x.1),
-            None => (None, 0.0),
-        };
         self.ball = ball.map(|b| Ball {
             pos: Vec2::new(b.pos[0], b.pos[1]),
             vel: Vec2::new(b.vel[0], b.vel[1]),
             radius: self.map.physics.ball_radius,
         });
-        self.time_elapsed = time_elapsed;
-        self.phase = phase;
-        self.all_players = players.clone();
-        if let Some(me) = players
-            .iter()
-            .find(|p| Some(&p.id) == self.player.id.as_ref())
-        {
-            self.player_status = me.status.clone();
-        }
-        self.paused = paused;
-        self.team1_color = team1_color;
-        self.team2_color = team2_color;
-        self.action_player = action_player;
-        self.action_time = action_time;
-        self.game_mode = game_mode;
-        self.action_target_time = action_target_time;
     }
 
     pub fn forward_vector(&self) -> Vec2 {
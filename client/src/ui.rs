@@ -4,8 +4,10 @@ use ggez::{
     graphics::{Canvas, DrawParam},
 };
 use spin_snowball_shared::*;
+use std::collections::VecDeque;
 use std::sync::mpsc::Sender;
 
+use crate::replay::{ReplayViewer, REPLAY_SPEEDS};
 use crate::state::GameState;
 
 pub enum UIMessage {
@@ -19,10 +21,18 @@ pub enum UIMessage {
     LoadMap {
         path: String,
     },
+    /// Plays the custom mode by the script at `path`, or by the map's script if `None`.
+    LoadScript {
+        path: Option<String>,
+    },
     JoinTeam {
         player_id: String,
         status: PlayerStatus,
     },
+    JoinSmallerTeam,
+    ShuffleTeams {
+        by_rating: bool,
+    },
     SetNick {
         nick: String,
     },
@@ -37,6 +47,63 @@ pub enum UIMessage {
         game_mode: GameMode,
         action_target_time: Option<f32>,
     },
+    ListRooms,
+    CreateRoom {
+        name: String,
+        host_password: Option<String>,
+    },
+    JoinRoom {
+        name: String,
+    },
+    LeaveRoom,
+    ClaimHost {
+        password: String,
+    },
+    SetAdmin {
+        player_id: String,
+        admin: bool,
+    },
+    AddBot {
+        team: Team,
+        difficulty: BotDifficulty,
+    },
+    RemoveBot {
+        player_id: String,
+    },
+    ListMaps,
+    GetLeaderboard,
+    SelectMap {
+        name: String,
+    },
+    OpenReplay {
+        path: String,
+    },
+    CloseReplay,
+    ToggleReplayPlayback,
+    SeekReplay {
+        time: f32,
+    },
+    SetReplaySpeed {
+        speed: f32,
+    },
+    ResetReplayCamera,
+    Chat {
+        channel: ChatChannel,
+        text: String,
+    },
+}
+
+/// How many chat lines are kept and shown.
+const CHAT_HISTORY: usize = 8;
+
+enum ChatLine {
+    Message {
+        nick: String,
+        channel: ChatChannel,
+        text: String,
+    },
+    /// Something that happened in the match, reported by the server.
+    Event(String),
 }
 
 pub struct UiState {
@@ -47,13 +114,29 @@ pub struct UiState {
     time_limit_enabled: bool,
     time_limit_secs: u32,
     map_path: String,
+    script_path: String,
     nick_edit: String,
     team1_color: egui::Color32,
     team2_color: egui::Color32,
     show_physics: bool,
     physics_edit: Option<PhysicsSettings>,
     action_target_time: f32,
-    show_top_hud: bool
+    show_top_hud: bool,
+    room_name_edit: String,
+    room_password_edit: String,
+    host_password_edit: String,
+    replay_path: String,
+    bot_team: Team,
+    bot_difficulty: BotDifficulty,
+    shuffle_by_rating: bool,
+    errors: Vec<String>,
+    chat: VecDeque<ChatLine>,
+    chat_open: bool,
+    chat_focus_pending: bool,
+    chat_edit: String,
+    chat_channel: ChatChannel,
+    /// Results of the last match, shown until dismissed.
+    summary: Option<MatchSummary>,
 }
 
 impl UiState {
@@ -66,14 +149,85 @@ impl UiState {
             time_limit_enabled: false,
             time_limit_secs: 300,
             map_path: "default_map.json".to_string(),
+            script_path: String::new(),
             nick_edit: String::new(),
             team1_color: egui::Color32::from_rgb(200, 0, 0),
             team2_color: egui::Color32::from_rgb(0, 0, 200),
             show_physics: false,
             physics_edit: None,
             action_target_time: 10.0,
-            show_top_hud: true
+            show_top_hud: true,
+            room_name_edit: String::new(),
+            room_password_edit: String::new(),
+            host_password_edit: String::new(),
+            replay_path: String::new(),
+            bot_team: Team::Team1,
+            bot_difficulty: BotDifficulty::Normal,
+            shuffle_by_rating: true,
+            errors: vec![],
+            chat: VecDeque::new(),
+            chat_open: false,
+            chat_focus_pending: false,
+            chat_edit: String::new(),
+            chat_channel: ChatChannel::All,
+            summary: None,
+        }
+    }
+
+    pub fn push_chat(&mut self, nick: String, channel: ChatChannel, text: String) {
+        self.push_chat_line(ChatLine::Message {
+            nick,
+            channel,
+            text,
+        });
+    }
+
+    pub fn push_event(&mut self, event: &GameEvent, state: &GameState) {
+        self.push_chat_line(ChatLine::Event(describe_event(event, state)));
+    }
+
+    fn push_chat_line(&mut self, line: ChatLine) {
+        self.chat.push_back(line);
+        if self.chat.len() > CHAT_HISTORY {
+            self.chat.pop_front();
+        }
+    }
+
+    /// While the chat is open, key presses are typed instead of steering.
+    pub fn is_chat_open(&self) -> bool {
+        self.chat_open
+    }
+
+    pub fn open_chat(&mut self) {
+        self.chat_open = true;
+        self.chat_focus_pending = true;
+    }
+
+    pub fn close_chat(&mut self) {
+        self.chat_open = false;
+        self.chat_edit.clear();
+    }
+
+    /// Sends the typed message, if any, and closes the chat.
+    pub fn submit_chat(&mut self) {
+        let text = self.chat_edit.trim();
+        if !text.is_empty() {
+            self.sender
+                .send(UIMessage::Chat {
+                    channel: self.chat_channel,
+                    text: text.to_string(),
+                })
+                .unwrap();
         }
+        self.close_chat();
+    }
+
+    pub fn show_summary(&mut self, summary: MatchSummary) {
+        self.summary = Some(summary);
+    }
+
+    pub fn push_error(&mut self, message: String) {
+        self.errors.push(message);
     }
 
     pub fn render(&mut self, ctx: &mut ggez::Context) {
@@ -82,27 +236,65 @@ impl UiState {
         canvas.finish(ctx).unwrap();
     }
 
-    pub fn update(&mut self, state: &GameState, ctx: &mut ggez::Context) {
+    pub fn update(
+        &mut self,
+        state: &GameState,
+        replay: Option<&ReplayViewer>,
+        ctx: &mut ggez::Context,
+    ) {
         let egui_ctx = self.ctx.ctx();
 
-        self.draw_top_hud(&egui_ctx, state);
+        // while watching a replay the HUD shows the recorded match
+        self.draw_top_hud(&egui_ctx, replay.map_or(state, |r| r.game()));
+        if let Some(replay) = replay {
+            self.draw_replay_window(&egui_ctx, replay);
+        }
+        self.draw_chat(&egui_ctx);
+        self.draw_summary_window(&egui_ctx);
 
         egui::Window::new("Menu")
             .default_width(460.0)
             .show(&egui_ctx, |ui| {
+                self.draw_errors(ui);
                 self.draw_players_section(ui, state);
                 ui.separator();
 
                 egui::CollapsingHeader::new("Player")
                     .default_open(false)
                     .show(ui, |ui| {
-                        self.draw_player_section(ui);
+                        self.draw_player_section(ui, state);
+                    });
+
+                egui::CollapsingHeader::new("Rooms")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        self.draw_rooms_section(ui, state);
+                    });
+
+                egui::CollapsingHeader::new("Maps")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        self.draw_maps_section(ui, state);
+                    });
+
+                egui::CollapsingHeader::new("Leaderboard")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        self.draw_leaderboard_section(ui, state);
+                    });
+
+                egui::CollapsingHeader::new("Replays")
+                    .default_open(false)
+                    .show(ui, |ui| {
+                        self.draw_replays_section(ui, replay.is_some());
                     });
 
                 egui::CollapsingHeader::new("Team Colors")
                     .default_open(false)
                     .show(ui, |ui| {
-                        self.draw_team_colors_section(ui);
+                        ui.add_enabled_ui(is_admin(state), |ui| {
+                            self.draw_team_colors_section(ui);
+                        });
                     });
 
                 ui.separator();
@@ -111,7 +303,10 @@ impl UiState {
                 self.draw_match_controls(ui, state);
                 ui.separator();
                 ui.horizontal(|ui| {
-                    if ui.button("⚙ Physics Settings").clicked() {
+                    if ui
+                        .add_enabled(is_admin(state), egui::Button::new("⚙ Physics Settings"))
+                        .clicked()
+                    {
                         self.show_physics = !self.show_physics;
                         if self.show_physics {
                             self.physics_edit = Some(state.map.physics.clone());
@@ -123,6 +318,10 @@ impl UiState {
                 });
             });
 
+        if self.show_physics && !is_admin(state) {
+            self.show_physics = false;
+        }
+
         if self.show_physics {
             self.draw_physics_window(&egui_ctx);
         } else if self.physics_edit.is_some() {
@@ -132,6 +331,75 @@ impl UiState {
         self.ctx.update(ctx);
     }
 
+    fn draw_errors(&mut self, ui: &mut egui::Ui) {
+        if self.errors.is_empty() {
+            return;
+        }
+
+        for error in &self.errors {
+            ui.colored_label(egui::Color32::from_rgb(230, 80, 80), error.as_str());
+        }
+        if ui.button("Dismiss").clicked() {
+            self.errors.clear();
+        }
+        ui.separator();
+    }
+
+    fn draw_chat(&mut self, egui_ctx: &egui::Context) {
+        if self.chat.is_empty() && !self.chat_open {
+            return;
+        }
+
+        egui::Area::new("chat")
+            .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(10.0, -10.0))
+            .show(egui_ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.set_max_width(420.0);
+                    for line in &self.chat {
+                        match line {
+                            ChatLine::Message {
+                                nick,
+                                channel: ChatChannel::All,
+                                text,
+                            } => ui.label(format!("{}: {}", nick, text)),
+                            ChatLine::Message {
+                                nick,
+                                channel: ChatChannel::Team,
+                                text,
+                            } => ui.colored_label(
+                                egui::Color32::from_rgb(120, 200, 255),
+                                format!("[team] {}: {}", nick, text),
+                            ),
+                            ChatLine::Event(text) => ui.colored_label(
+                                egui::Color32::from_rgb(240, 200, 90),
+                                format!("* {}", text),
+                            ),
+                        };
+                    }
+
+                    if !self.chat_open {
+                        ui.small("Press Enter to chat");
+                        return;
+                    }
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(&mut self.chat_channel, ChatChannel::All, "All");
+                        ui.selectable_value(&mut self.chat_channel, ChatChannel::Team, "Team");
+                        let edit = ui.add(
+                            egui::TextEdit::singleline(&mut self.chat_edit)
+                                .char_limit(MAX_CHAT_LEN)
+                                .desired_width(260.0),
+                        );
+                        if std::mem::take(&mut self.chat_focus_pending) {
+                            edit.request_focus();
+                        }
+                        if ui.button("Send").clicked() {
+                            self.submit_chat();
+                        }
+                    });
+                });
+            });
+    }
+
     fn draw_top_hud(&self, egui_ctx: &egui::Context, state: &GameState) {
         if !self.show_top_hud {
             return;
@@ -141,6 +409,10 @@ impl UiState {
             .resizable(false)
             .show(egui_ctx, |ui| {
                 ui.horizontal_wrapped(|ui| {
+                    if let Some(name) = &state.server_name {
+                        ui.strong(name);
+                        ui.separator();
+                    }
                     ui.label(format!("Phase: {:?}", state.phase));
                     ui.separator();
                     ui.label(format!("Time: {:.1}", state.time_elapsed));
@@ -163,6 +435,8 @@ impl UiState {
         ui.separator();
 
         let enabled = matches!(state.phase, MatchPhase::Lobby);
+        let can_manage_admins = state.player_role == PlayerRole::Host;
+        let can_manage_bots = state.player_role >= PlayerRole::Admin;
 
         ui.columns(3, |cols| {
             self.team_column(
@@ -176,6 +450,8 @@ impl UiState {
                     .collect(),
                 PlayerStatus::Playing(Team::Team1),
                 enabled,
+                can_manage_admins,
+                can_manage_bots,
             );
 
             self.team_column(
@@ -189,6 +465,8 @@ impl UiState {
                     .collect(),
                 PlayerStatus::Playing(Team::Team2),
                 enabled,
+                can_manage_admins,
+                can_manage_bots,
             );
 
             self.team_column(
@@ -202,11 +480,80 @@ impl UiState {
                     .collect(),
                 PlayerStatus::Spectator,
                 enabled,
+                can_manage_admins,
+                can_manage_bots,
             );
         });
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(enabled, egui::Button::new("Join smaller team"))
+                .clicked()
+            {
+                self.sender.send(UIMessage::JoinSmallerTeam).unwrap();
+            }
+            if can_manage_admins {
+                if ui
+                    .add_enabled(enabled, egui::Button::new("Shuffle teams"))
+                    .clicked()
+                {
+                    self.sender
+                        .send(UIMessage::ShuffleTeams {
+                            by_rating: self.shuffle_by_rating,
+                        })
+                        .unwrap();
+                }
+                ui.checkbox(&mut self.shuffle_by_rating, "by rating");
+            }
+        });
+
+        if can_manage_bots {
+            ui.horizontal(|ui| {
+                ui.label("Add bot:");
+                egui::ComboBox::from_id_source("bot_team_select")
+                    .selected_text(format!("{:?}", self.bot_team))
+                    .show_ui(ui, |ui| {
+                        for team in [Team::Team1, Team::Team2] {
+                            if ui
+                                .selectable_label(self.bot_team == team, format!("{:?}", team))
+                                .clicked()
+                            {
+                                self.bot_team = team;
+                            }
+                        }
+                    });
+                egui::ComboBox::from_id_source("bot_difficulty_select")
+                    .selected_text(format!("{:?}", self.bot_difficulty))
+                    .show_ui(ui, |ui| {
+                        for difficulty in [
+                            BotDifficulty::Easy,
+                            BotDifficulty::Normal,
+                            BotDifficulty::Hard,
+                        ] {
+                            if ui
+                                .selectable_label(
+                                    self.bot_difficulty == difficulty,
+                                    format!("{:?}", difficulty),
+                                )
+                                .clicked()
+                            {
+                                self.bot_difficulty = difficulty;
+                            }
+                        }
+                    });
+                if ui.button("Add").clicked() {
+                    self.sender
+                        .send(UIMessage::AddBot {
+                            team: self.bot_team,
+                            difficulty: self.bot_difficulty,
+                        })
+                        .unwrap();
+                }
+            });
+        }
     }
 
-    fn draw_player_section(&mut self, ui: &mut egui::Ui) {
+    fn draw_player_section(&mut self, ui: &mut egui::Ui, state: &GameState) {
         ui.horizontal(|ui| {
             ui.label("Nick:");
             let resp = ui.text_edit_singleline(&mut self.nick_edit);
@@ -223,6 +570,290 @@ impl UiState {
                 }
             }
         });
+
+        if state.player_role != PlayerRole::Host {
+            ui.horizontal(|ui| {
+                ui.label("Host password:");
+                ui.add(egui::TextEdit::singleline(&mut self.host_password_edit).password(true));
+                if ui.button("Claim host").clicked() {
+                    self.sender
+                        .send(UIMessage::ClaimHost {
+                            password: self.host_password_edit.clone(),
+                        })
+                        .unwrap();
+                }
+            });
+        }
+    }
+
+    fn draw_rooms_section(&mut self, ui: &mut egui::Ui, state: &GameState) {
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "Current room: {}",
+                state.room.as_deref().unwrap_or("-")
+            ));
+            if ui.button("Refresh").clicked() {
+                self.sender.send(UIMessage::ListRooms).unwrap();
+            }
+            if ui.button("Leave").clicked() {
+                self.sender.send(UIMessage::LeaveRoom).unwrap();
+            }
+        });
+
+        for room in &state.rooms {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{} - {} ({} players, {}{})",
+                    room.name,
+                    room.map_name,
+                    room.players,
+                    game_mode_label(room.game_mode),
+                    if matches!(room.phase, MatchPhase::Playing { .. }) {
+                        ", playing"
+                    } else {
+                        ""
+                    }
+                ));
+
+                let is_current = state.room.as_deref() == Some(room.name.as_str());
+                ui.add_enabled_ui(!is_current, |ui| {
+                    if ui.button("Join").clicked() {
+                        self.sender
+                            .send(UIMessage::JoinRoom {
+                                name: room.name.clone(),
+                            })
+                            .unwrap();
+                    }
+                });
+            });
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("New room:");
+            ui.text_edit_singleline(&mut self.room_name_edit);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Host password (optional):");
+            ui.add(egui::TextEdit::singleline(&mut self.room_password_edit).password(true));
+            if ui.button("Create").clicked() && !self.room_name_edit.trim().is_empty() {
+                self.sender
+                    .send(UIMessage::CreateRoom {
+                        name: self.room_name_edit.trim().to_string(),
+                        host_password: (!self.room_password_edit.is_empty())
+                            .then(|| self.room_password_edit.clone()),
+                    })
+                    .unwrap();
+                self.room_name_edit.clear();
+                self.room_password_edit.clear();
+            }
+        });
+    }
+
+    fn draw_maps_section(&mut self, ui: &mut egui::Ui, state: &GameState) {
+        ui.horizontal(|ui| {
+            ui.label(format!("Current map: {}", state.map.name));
+            if ui.button("Refresh").clicked() {
+                self.sender.send(UIMessage::ListMaps).unwrap();
+            }
+        });
+
+        if state.maps.is_empty() {
+            ui.label("The server has no map library.");
+        }
+        for map in &state.maps {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{} ({}x{})",
+                    map.title, map.width as i32, map.height as i32
+                ));
+                ui.add_enabled_ui(is_admin(state), |ui| {
+                    if ui.button("Load").clicked() {
+                        self.sender
+                            .send(UIMessage::SelectMap {
+                                name: map.name.clone(),
+                            })
+                            .unwrap();
+                    }
+                });
+            });
+            let modes: Vec<&str> = map
+                .recommended_modes
+                .iter()
+                .map(|&mode| game_mode_label(mode))
+                .collect();
+            ui.small(format!("Recommended: {}", modes.join(", ")));
+        }
+    }
+
+    fn draw_leaderboard_section(&mut self, ui: &mut egui::Ui, state: &GameState) {
+        if ui.button("Refresh").clicked() {
+            self.sender.send(UIMessage::GetLeaderboard).unwrap();
+        }
+
+        if state.leaderboard.is_empty() {
+            ui.label("No rated matches yet.");
+            return;
+        }
+        egui::Grid::new("leaderboard").striped(true).show(ui, |ui| {
+            for header in ["#", "Player", "Rating", "W", "L", "D"] {
+                ui.strong(header);
+            }
+            ui.end_row();
+
+            for (rank, entry) in state.leaderboard.iter().enumerate() {
+                ui.label((rank + 1).to_string());
+                ui.label(&entry.nick);
+                ui.label(format!("{:.0}", entry.rating));
+                ui.label(entry.wins.to_string());
+                ui.label(entry.losses.to_string());
+                ui.label(entry.draws.to_string());
+                ui.end_row();
+            }
+        });
+    }
+
+    fn draw_replays_section(&mut self, ui: &mut egui::Ui, watching: bool) {
+        ui.horizontal(|ui| {
+            ui.label("File:");
+            ui.text_edit_singleline(&mut self.replay_path);
+            if ui.button("Open").clicked() {
+                self.sender
+                    .send(UIMessage::OpenReplay {
+                        path: self.replay_path.clone(),
+                    })
+                    .unwrap();
+            }
+        });
+
+        if watching && ui.button("Back to live game").clicked() {
+            self.sender.send(UIMessage::CloseReplay).unwrap();
+        }
+    }
+
+    fn draw_summary_window(&mut self, egui_ctx: &egui::Context) {
+        let Some(summary) = &self.summary else {
+            return;
+        };
+        let mut open = true;
+
+        egui::Window::new("Match results")
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .open(&mut open)
+            .show(egui_ctx, |ui| {
+                let result = match summary.winner {
+                    Some(team) => format!("{} wins", team_name(team)),
+                    None => "Draw".to_string(),
+                };
+                ui.heading(format!(
+                    "{} {}:{}",
+                    result, summary.scores_team1, summary.scores_team2
+                ));
+                ui.label(format!(
+                    "{}, {:.0} s",
+                    game_mode_label(summary.game_mode),
+                    summary.duration_secs
+                ));
+                ui.separator();
+
+                let flag = matches!(summary.game_mode, GameMode::Ctf | GameMode::Htf);
+                let hill = summary.game_mode == GameMode::KingOfTheHill;
+                egui::Grid::new("match_summary")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for header in [
+                            "Player",
+                            "Goals",
+                            "Own goals",
+                            "Thrown",
+                            "Hits",
+                            "Hit",
+                            "KOs",
+                        ] {
+                            ui.strong(header);
+                        }
+                        if flag {
+                            ui.strong("Flag");
+                        }
+                        if hill {
+                            ui.strong("Hill");
+                        }
+                        ui.end_row();
+
+                        for team in [Team::Team1, Team::Team2] {
+                            for player in summary.players.iter().filter(|p| p.team == team) {
+                                let stats = &player.stats;
+                                let nick = if player.is_bot {
+                                    format!("{} (bot)", player.nick)
+                                } else {
+                                    player.nick.clone()
+                                };
+                                ui.label(format!("[{}] {}", team_name(team), nick));
+                                ui.label(stats.goals.to_string());
+                                ui.label(stats.own_goals.to_string());
+                                ui.label(stats.snowballs_thrown.to_string());
+                                ui.label(stats.hits.to_string());
+                                ui.label(stats.times_hit.to_string());
+                                ui.label(stats.knockouts.to_string());
+                                if flag {
+                                    ui.label(format!("{:.1} s", stats.flag_carry_secs));
+                                }
+                                if hill {
+                                    ui.label(format!("{:.1} s", stats.hill_secs));
+                                }
+                                ui.end_row();
+                            }
+                        }
+                    });
+            });
+
+        if !open {
+            self.summary = None;
+        }
+    }
+
+    fn draw_replay_window(&self, egui_ctx: &egui::Context, replay: &ReplayViewer) {
+        egui::Window::new("Replay")
+            .default_width(420.0)
+            .anchor(egui::Align2::CENTER_BOTTOM, egui::vec2(0.0, -10.0))
+            .show(egui_ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let label = if replay.is_playing() { "Pause" } else { "Play" };
+                    if ui.button(label).clicked() {
+                        self.sender.send(UIMessage::ToggleReplayPlayback).unwrap();
+                    }
+
+                    let mut time = replay.time();
+                    let slider = egui::Slider::new(&mut time, 0.0..=replay.duration())
+                        .suffix(" s")
+                        .fixed_decimals(1);
+                    if ui.add(slider).changed() {
+                        self.sender.send(UIMessage::SeekReplay { time }).unwrap();
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Speed:");
+                    for speed in REPLAY_SPEEDS {
+                        if ui
+                            .selectable_label(replay.speed() == speed, format!("{}x", speed))
+                            .clicked()
+                        {
+                            self.sender.send(UIMessage::SetReplaySpeed { speed }).unwrap();
+                        }
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    if ui.button("Reset camera").clicked() {
+                        self.sender.send(UIMessage::ResetReplayCamera).unwrap();
+                    }
+                    if ui.button("Close").clicked() {
+                        self.sender.send(UIMessage::CloseReplay).unwrap();
+                    }
+                });
+                ui.label("WASD/arrows: pan, +/- or mouse wheel: zoom, space: play/pause");
+            });
     }
 
     fn draw_team_colors_section(&mut self, ui: &mut egui::Ui) {
@@ -252,7 +883,7 @@ impl UiState {
     }
 
     fn draw_match_settings(&mut self, ui: &mut egui::Ui, state: &GameState) {
-        let match_stopped = matches!(state.phase, MatchPhase::Lobby);
+        let match_stopped = matches!(state.phase, MatchPhase::Lobby) && is_admin(state);
 
         ui.heading("Match Settings");
         ui.separator();
@@ -272,6 +903,7 @@ impl UiState {
                             GameMode::Race,
                             GameMode::HotPotato,
                             GameMode::Shooter,
+                            GameMode::Custom,
                         ] {
                             if ui
                                 .selectable_label(state.game_mode == mode, game_mode_label(mode))
@@ -294,22 +926,24 @@ impl UiState {
         });
 
         if mode_uses_action_timer(state.game_mode) {
-            ui.horizontal(|ui| {
-                ui.label("Target time (sec):");
-                ui.add(
-                    egui::DragValue::new(&mut self.action_target_time)
-                        .clamp_range(1.0..=60.0)
-                        .speed(0.5),
-                );
+            ui.add_enabled_ui(is_admin(state), |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Target time (sec):");
+                    ui.add(
+                        egui::DragValue::new(&mut self.action_target_time)
+                            .clamp_range(1.0..=60.0)
+                            .speed(0.5),
+                    );
 
-                if ui.button("Set").clicked() {
-                    self.sender
-                        .send(UIMessage::SetGameMode {
-                            game_mode: state.game_mode,
-                            action_target_time: Some(self.action_target_time),
-                        })
-                        .unwrap();
-                }
+                    if ui.button("Set").clicked() {
+                        self.sender
+                            .send(UIMessage::SetGameMode {
+                                game_mode: state.game_mode,
+                                action_target_time: Some(self.action_target_time),
+                            })
+                            .unwrap();
+                    }
+                });
             });
         }
 
@@ -343,14 +977,32 @@ impl UiState {
                         .unwrap();
                 }
             });
+
+            ui.horizontal(|ui| {
+                ui.label("Script:");
+                ui.text_edit_singleline(&mut self.script_path);
+                if ui.button("Load").clicked() {
+                    self.sender
+                        .send(UIMessage::LoadScript {
+                            path: Some(self.script_path.clone()),
+                        })
+                        .unwrap();
+                }
+                if ui.button("Map's").clicked() {
+                    self.sender
+                        .send(UIMessage::LoadScript { path: None })
+                        .unwrap();
+                }
+            });
         });
     }
 
     fn draw_match_controls(&mut self, ui: &mut egui::Ui, state: &GameState) {
-        let can_start = matches!(state.phase, MatchPhase::Lobby);
-        let can_pause = matches!(state.phase, MatchPhase::Playing { .. }) && !state.paused;
-        let can_resume = matches!(state.phase, MatchPhase::Playing { .. }) && state.paused;
-        let can_stop = matches!(state.phase, MatchPhase::Playing { .. });
+        let admin = is_admin(state);
+        let can_start = admin && matches!(state.phase, MatchPhase::Lobby);
+        let can_pause = admin && matches!(state.phase, MatchPhase::Playing { .. }) && !state.paused;
+        let can_resume = admin && matches!(state.phase, MatchPhase::Playing { .. }) && state.paused;
+        let can_stop = admin && matches!(state.phase, MatchPhase::Playing { .. });
 
         ui.horizontal(|ui| {
             ui.add_enabled_ui(can_start, |ui| {
@@ -393,6 +1045,8 @@ impl UiState {
         players: Vec<PlayerState>,
         drop_status: PlayerStatus,
         enabled: bool,
+        can_manage_admins: bool,
+        can_manage_bots: bool,
     ) {
         ui.heading(title);
         ui.add_space(4.0);
@@ -428,15 +1082,44 @@ impl UiState {
 
                 // Draw players
                 for p in players {
-                    let response = ui.add(egui::Label::new(&p.nick).sense(if enabled {
-                        egui::Sense::drag()
-                    } else {
-                        egui::Sense::hover()
-                    }));
+                    ui.horizontal(|ui| {
+                        let label = match p.role {
+                            _ if p.is_bot => format!("{} (bot)", p.nick),
+                            PlayerRole::Host => format!("{} ★", p.nick),
+                            PlayerRole::Admin => format!("{} (admin)", p.nick),
+                            PlayerRole::Player => p.nick.clone(),
+                        };
+                        let response = ui.add(egui::Label::new(label).sense(if enabled {
+                            egui::Sense::drag()
+                        } else {
+                            egui::Sense::hover()
+                        }));
 
-                    if enabled && response.drag_started() {
-                        egui::DragAndDrop::set_payload(ui.ctx(), p.id.clone());
-                    }
+                        if enabled && response.drag_started() {
+                            egui::DragAndDrop::set_payload(ui.ctx(), p.id.clone());
+                        }
+
+                        if p.is_bot {
+                            if can_manage_bots && ui.small_button("remove").clicked() {
+                                self.sender
+                                    .send(UIMessage::RemoveBot {
+                                        player_id: p.id.clone(),
+                                    })
+                                    .unwrap();
+                            }
+                        } else if can_manage_admins && p.role != PlayerRole::Host {
+                            let promote = p.role == PlayerRole::Player;
+                            let text = if promote { "make admin" } else { "revoke admin" };
+                            if ui.small_button(text).clicked() {
+                                self.sender
+                                    .send(UIMessage::SetAdmin {
+                                        player_id: p.id.clone(),
+                                        admin: promote,
+                                    })
+                                    .unwrap();
+                            }
+                        }
+                    });
                 }
             });
     }
@@ -581,12 +1264,73 @@ fn game_mode_label(mode: GameMode) -> &'static str {
         GameMode::Race => "Race",
         GameMode::HotPotato => "Hot Potato",
         GameMode::Shooter => "Shooter",
+        GameMode::Custom => "Custom",
     }
 }
 
+fn is_admin(state: &GameState) -> bool {
+    state.player_role >= PlayerRole::Admin
+}
+
 fn mode_uses_action_timer(mode: GameMode) -> bool {
     matches!(
         mode,
         GameMode::Htf | GameMode::KingOfTheHill | GameMode::HotPotato
     )
 }
+
+fn team_name(team: Team) -> &'static str {
+    match team {
+        Team::Team1 => "Team 1",
+        Team::Team2 => "Team 2",
+    }
+}
+
+/// One line of text for the chat overlay.
+fn describe_event(event: &GameEvent, state: &GameState) -> String {
+    let nick = |id: &str| {
+        state
+            .all_players
+            .iter()
+            .find(|p| p.id == id)
+            .map_or_else(|| "someone".to_string(), |p| p.nick.clone())
+    };
+
+    match event {
+        GameEvent::MatchStarted { .. } => "The match has started".to_string(),
+        GameEvent::MatchPaused => "The match is paused".to_string(),
+        GameEvent::MatchResumed => "The match goes on".to_string(),
+        GameEvent::MatchEnded {
+            winner,
+            scores_team1,
+            scores_team2,
+        } => match winner {
+            Some(team) => format!(
+                "{} wins {}:{}",
+                team_name(*team),
+                scores_team1,
+                scores_team2
+            ),
+            None => format!("The match ends in a draw {}:{}", scores_team1, scores_team2),
+        },
+        GameEvent::GoalScored { team, by: Some(by) } => {
+            format!("{} scores for {}", nick(by), team_name(*team))
+        }
+        GameEvent::GoalScored { team, by: None } => format!("{} scores", team_name(*team)),
+        GameEvent::PlayerHit { player, by } => format!("{} hit {}", nick(by), nick(player)),
+        GameEvent::PlayerFellInHole { player } => format!("{} fell into a hole", nick(player)),
+        GameEvent::FlagPickedUp { player } => format!("{} has the flag", nick(player)),
+        GameEvent::FlagDropped { player } => format!("{} dropped the flag", nick(player)),
+        GameEvent::FlagCaptured { player, team } => {
+            format!(
+                "{} captured the flag for {}",
+                nick(player),
+                team_name(*team)
+            )
+        }
+        GameEvent::KingChanged { king: Some(king) } => {
+            format!("{} is king of the hill", nick(king))
+        }
+        GameEvent::KingChanged { king: None } => "The hill is free".to_string(),
+    }
+}
@@ -6,7 +6,10 @@ use std::path::{Path, PathBuf};
 use image::Pixel;
 use ndarray::Array2;
 use serde::Serialize;
-use spin_snowball_shared::{CollisionMaskTag, ColorDef, GoalDef, MapObject, Team};
+use spin_snowball_shared::validation::validate_map;
+use spin_snowball_shared::{
+    CollisionMaskTag, ColorDef, GameMap, GoalDef, MapObject, MapPoint, Team,
+};
 
 #[derive(Clone, Copy)]
 struct RGBA {
@@ -385,6 +388,192 @@ fn extract_lines(data: &[RGBA], width: u32, height: u32) -> Vec<MapObject> {
     objects
 }
 
+/// How far, in pixels, a traced polygon outline may stray from the pixels.
+const POLYGON_TOLERANCE: f32 = 1.5;
+
+/// Neighbours of a pixel in clockwise order on screen, starting east.
+const NEIGHBOURS: [(i32, i32); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
+/// Outline of the component `label`, walking its border pixels clockwise from
+/// `start`, its first pixel in reading order (Moore neighbour tracing).
+fn trace_outline(labels: &Array2<i32>, label: i32, start: (usize, usize)) -> Vec<(i32, i32)> {
+    let (h, w) = labels.dim();
+    let inside = |(x, y): (i32, i32)| {
+        x >= 0
+            && y >= 0
+            && (x as usize) < w
+            && (y as usize) < h
+            && labels[(y as usize, x as usize)] == label
+    };
+
+    let start = (start.0 as i32, start.1 as i32);
+    let mut outline = vec![start];
+    let mut current = start;
+    // the pixel west of the first one in reading order is never part of the component
+    let mut from = 4;
+    let mut first_step = None;
+
+    loop {
+        let Some(dir) = (1..=8)
+            .map(|i| (from + i) % 8)
+            .find(|d| inside((current.0 + NEIGHBOURS[*d].0, current.1 + NEIGHBOURS[*d].1)))
+        else {
+            // a single pixel
+            return outline;
+        };
+        // stop once the walk is about to repeat its first step
+        if current == start {
+            match first_step {
+                Some(first) if first == dir => break,
+                Some(_) => {}
+                None => first_step = Some(dir),
+            }
+        }
+
+        let next = (current.0 + NEIGHBOURS[dir].0, current.1 + NEIGHBOURS[dir].1);
+        // continue the search from the outside pixel checked just before `next`
+        let back = NEIGHBOURS[(dir + 7) % 8];
+        let back = (current.0 + back.0 - next.0, current.1 + back.1 - next.1);
+        from = NEIGHBOURS.iter().position(|n| *n == back).unwrap_or(0);
+        current = next;
+        if current != start {
+            outline.push(current);
+        }
+        if outline.len() > 4 * w * h {
+            break;
+        }
+    }
+
+    outline
+}
+
+fn distance_to_segment(p: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len2 = dx * dx + dy * dy;
+    let t = if len2 > 0.0 {
+        (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / len2).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    ((p.0 - a.0 - dx * t).powi(2) + (p.1 - a.1 - dy * t).powi(2)).sqrt()
+}
+
+/// Drops the points of an open path that lie within `tolerance` of the
+/// straight line between their neighbours (Douglas-Peucker). The ends are kept.
+fn simplify_path(path: &[(f32, f32)], tolerance: f32) -> Vec<(f32, f32)> {
+    if path.len() < 3 {
+        return path.to_vec();
+    }
+    let (first, last) = (path[0], path[path.len() - 1]);
+    let (index, dist) = path[1..path.len() - 1]
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (i + 1, distance_to_segment(*p, first, last)))
+        .fold((0, 0.0), |best, d| if d.1 > best.1 { d } else { best });
+
+    if dist <= tolerance {
+        return vec![first, last];
+    }
+    let mut simplified = simplify_path(&path[..=index], tolerance);
+    simplified.pop();
+    simplified.extend(simplify_path(&path[index..], tolerance));
+    simplified
+}
+
+/// Simplifies a closed outline by splitting it at the point farthest from its
+/// start and simplifying both halves.
+fn simplify_outline(outline: &[(i32, i32)], tolerance: f32) -> Vec<(f32, f32)> {
+    let points: Vec<(f32, f32)> = outline
+        .iter()
+        .map(|(x, y)| (*x as f32, *y as f32))
+        .collect();
+    let start = points[0];
+    let far = (0..points.len())
+        .max_by(|a, b| {
+            let da = (points[*a].0 - start.0).powi(2) + (points[*a].1 - start.1).powi(2);
+            let db = (points[*b].0 - start.0).powi(2) + (points[*b].1 - start.1).powi(2);
+            da.total_cmp(&db)
+        })
+        .unwrap_or(0);
+    if far == 0 {
+        return points;
+    }
+
+    let mut simplified = simplify_path(&points[..=far], tolerance);
+    simplified.pop();
+    let mut back_half = points[far..].to_vec();
+    back_half.push(start);
+    simplified.extend(simplify_path(&back_half, tolerance));
+    simplified.pop();
+    simplified
+}
+
+/// One polygon per same-coloured area, following its outer outline; gaps
+/// inside an area are filled.
+fn extract_polygons(data: &[RGBA], width: u32, height: u32) -> Vec<MapObject> {
+    let mask = color_mask(data, width, height);
+    let (labels, count) = label_components(&mask);
+
+    let mut starts = vec![None; count as usize];
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            let label = labels[(y, x)];
+            if label > 0 && starts[label as usize - 1].is_none() {
+                starts[label as usize - 1] = Some((x, y));
+            }
+        }
+    }
+
+    let mut objects = Vec::new();
+
+    for (label, start) in (1..=count).zip(starts) {
+        let Some((x, y)) = start else {
+            continue;
+        };
+        let outline = trace_outline(&labels, label, (x, y));
+        let points = simplify_outline(&outline, POLYGON_TOLERANCE);
+        if points.len() < 3 {
+            eprintln!(
+                "  skipping the polygon at {},{}: too thin to have an area",
+                x, y
+            );
+            continue;
+        }
+
+        let px = data[y * width as usize + x];
+        let (is_hole, mask) = decode_rgb(px.r, px.g, px.b, px.a);
+
+        objects.push(MapObject::Polygon {
+                points: points.into_iter().map(|(x, y)| MapPoint { x, y }).collect(),
+                is_hole,
+                factor: 1.0,
+                color: ColorDef {
+                    r: strip_mask_bit(px.r),
+                    g: strip_mask_bit(px.g),
+                    b: strip_mask_bit(px.b),
+                    a: 255,
+                },
+                mask: if is_hole {
+                    vec![]
+                } else {
+                    mask
+                },
+            },
+        );
+    }
+
+    objects
+}
+
 #[derive(Serialize)]
 struct MapData {
     name: String,
@@ -424,6 +613,14 @@ fn main() {
     for l in extract_lines(&lines, w, h) {
         objects.push(serde_json::to_value(l).unwrap());
     }
+    // older map directories have no polygon layer
+    let polygons_path = map_dir.join("polygons.png");
+    if polygons_path.exists() {
+        let (polygons, _, _) = load_rgba(&polygons_path);
+        for p in extract_polygons(&polygons, w, h) {
+            objects.push(serde_json::to_value(p).unwrap());
+        }
+    }
 
     let data = MapData {
         name: map_dir.file_name().unwrap().to_string_lossy().to_string(),
@@ -440,7 +637,9 @@ fn main() {
             "player_bounciness": 0.6,
             "snowball_bounciness": 0.9,
             "snowball_lifetime_sec": 3.0,
-            "friction_per_frame": 0.99
+            "friction_per_frame": 0.99,
+            "recoil_power": 1.2,
+            "shoot_cooldown_sec": 0.5
         }),
         team1: serde_json::json!({ "spawn_x": w as f32 * 0.25, "spawn_y": h as f32 * 0.5 }),
         team2: serde_json::json!({ "spawn_x": w as f32 * 0.75, "spawn_y": h as f32 * 0.5 }),
@@ -455,4 +654,21 @@ fn main() {
         .unwrap();
 
     println!("✔ Map generated: {:?}", out_path);
+
+    // catch spawns inside walls and the like before the map reaches a server
+    let map: GameMap = match serde_json::to_value(&data).and_then(serde_json::from_value) {
+        Ok(map) => map,
+        Err(e) => {
+            eprintln!("✘ The generated map is not a valid GameMap: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let report = validate_map(&map);
+    for issue in &report.issues {
+        eprintln!("  {}", issue);
+    }
+    if report.has_errors() {
+        eprintln!("✘ The map has errors and will be refused by the server");
+        std::process::exit(1);
+    }
 }
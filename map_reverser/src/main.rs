@@ -1,9 +1,11 @@
 use std::env;
-use std::fs::File;
 use std::path::PathBuf;
 use image::{Rgba, RgbaImage};
 use serde::Deserialize;
-use spin_snowball_shared::{CollisionMaskTag, ColorDef, MapObject, GoalDef};
+use spin_snowball_shared::validation::validate_map;
+use spin_snowball_shared::{
+    polygon_contains, CollisionMaskTag, ColorDef, GameMap, GoalDef, MapObject,
+};
 
 #[derive(Deserialize)]
 struct MapData {
@@ -50,12 +52,23 @@ fn main() {
     let out_dir = PathBuf::from(&args[2]);
     std::fs::create_dir_all(&out_dir).expect("Failed to create output folder");
 
-    let file = File::open(&json_path).expect("Failed to open JSON file");
-    let map: MapData = serde_json::from_reader(file).expect("Failed to parse JSON");
+    let json = std::fs::read_to_string(&json_path).expect("Failed to open JSON file");
+    let map: MapData = serde_json::from_str(&json).expect("Failed to parse JSON");
+
+    // the images only need the geometry, but point out problems with the map while at it
+    match serde_json::from_str::<GameMap>(&json) {
+        Ok(full) => {
+            for issue in &validate_map(&full).issues {
+                eprintln!("  {}", issue);
+            }
+        }
+        Err(e) => eprintln!("  warning: not a complete GameMap, skipping validation: {}", e),
+    }
 
     let mut rects_img = RgbaImage::new(map.width, map.height);
     let mut circles_img = RgbaImage::new(map.width, map.height);
     let mut lines_img = RgbaImage::new(map.width, map.height);
+    let mut polygons_img = RgbaImage::new(map.width, map.height);
     let mut goals_img = RgbaImage::new(map.width, map.height);
 
     for obj in &map.objects {
@@ -96,6 +109,23 @@ fn main() {
                     }
                 }
             }
+            MapObject::Polygon { points, color, mask, is_hole, .. } => {
+                let rgba = color_to_rgba(color, mask, *is_hole);
+                // only the box around the polygon needs testing
+                let xs = || points.iter().map(|p| p.x);
+                let ys = || points.iter().map(|p| p.y);
+                let x0 = xs().fold(f32::INFINITY, f32::min).max(0.0) as u32;
+                let y0 = ys().fold(f32::INFINITY, f32::min).max(0.0) as u32;
+                let x1 = (xs().fold(0.0, f32::max) as u32).min(map.width.saturating_sub(1));
+                let y1 = (ys().fold(0.0, f32::max) as u32).min(map.height.saturating_sub(1));
+                for iy in y0..=y1 {
+                    for ix in x0..=x1 {
+                        if polygon_contains(points, ix as f32, iy as f32) {
+                            polygons_img.put_pixel(ix, iy, rgba);
+                        }
+                    }
+                }
+            }
         }
     }
 
@@ -116,6 +146,7 @@ fn main() {
     rects_img.save(out_dir.join("rects.png")).unwrap();
     circles_img.save(out_dir.join("circles.png")).unwrap();
     lines_img.save(out_dir.join("lines.png")).unwrap();
+    polygons_img.save(out_dir.join("polygons.png")).unwrap();
     goals_img.save(out_dir.join("goals.png")).unwrap();
 
     println!("✔ PNG images generated in {:?}", out_dir);
@@ -0,0 +1,298 @@
+use glam::Vec2;
+use spin_snowball_shared::*;
+use spin_snowball_simulation::{Player, World};
+
+use crate::rng::Rng;
+
+/// One tick of input, as a client would send it.
+#[derive(Default)]
+pub struct BotInput {
+    pub left: bool,
+    pub right: bool,
+    pub shoot: bool,
+}
+
+/// What a bot wants to do this tick. Players only move through the recoil of
+/// their own shots, so getting somewhere means shooting away from it.
+enum Intent {
+    MoveTo(Vec2),
+    Attack(Vec2),
+    Idle,
+}
+
+/// Once moving towards a target faster than this, a bot waits instead of shooting again.
+const CRUISE_SPEED: f32 = 150.0;
+
+/// A computer-controlled player. Bots occupy a regular `Player` in the world and
+/// are driven through `GameState::apply_input`, like connected clients.
+pub struct Bot {
+    difficulty: BotDifficulty,
+    rng: Rng,
+    /// Offset added to the ideal aim, re-rolled after every shot.
+    aim_error_deg: f32,
+    /// Time left before the bot reacts with another shot.
+    reaction_sec: f32,
+    shoot_pressed: bool,
+}
+
+impl Bot {
+    pub fn new(difficulty: BotDifficulty, seed: u64) -> Self {
+        let mut bot = Self {
+            difficulty,
+            rng: Rng::new(seed),
+            aim_error_deg: 0.0,
+            reaction_sec: 0.0,
+            shoot_pressed: false,
+        };
+        bot.aim_error_deg = bot.roll_aim_error();
+        bot
+    }
+
+    fn aim_tolerance_deg(&self) -> f32 {
+        match self.difficulty {
+            BotDifficulty::Easy => 20.0,
+            BotDifficulty::Normal => 10.0,
+            BotDifficulty::Hard => 4.0,
+        }
+    }
+
+    fn max_aim_error_deg(&self) -> f32 {
+        match self.difficulty {
+            BotDifficulty::Easy => 25.0,
+            BotDifficulty::Normal => 10.0,
+            BotDifficulty::Hard => 3.0,
+        }
+    }
+
+    fn reaction_time_sec(&self) -> f32 {
+        match self.difficulty {
+            BotDifficulty::Easy => 1.0,
+            BotDifficulty::Normal => 0.5,
+            BotDifficulty::Hard => 0.15,
+        }
+    }
+
+    fn roll_aim_error(&mut self) -> f32 {
+        (self.rng.next_f32() * 2.0 - 1.0) * self.max_aim_error_deg()
+    }
+
+    pub fn think(&mut self, id: &str, world: &World, dt: f32) -> BotInput {
+        let Some(me) = world.players.get(id) else {
+            return BotInput::default();
+        };
+        let PlayerStatus::Playing(team) = me.status else {
+            return BotInput::default();
+        };
+
+        // release the button after every shot, the server only fires on a fresh press
+        if self.shoot_pressed {
+            self.shoot_pressed = false;
+            return BotInput::default();
+        }
+        self.reaction_sec -= dt;
+
+        let aim = match choose_intent(me, team, world) {
+            Intent::MoveTo(target) => {
+                let to_target = target - me.pos;
+                if to_target.length() < world.map.physics.player_radius
+                    || me.vel.dot(to_target.normalize_or_zero()) > CRUISE_SPEED
+                {
+                    return BotInput::default();
+                }
+                -to_target
+            }
+            Intent::Attack(target) => target - me.pos,
+            Intent::Idle => return BotInput::default(),
+        };
+        if aim.length_squared() < 1.0 {
+            return BotInput::default();
+        }
+
+        let desired = aim.y.atan2(aim.x).to_degrees() + self.aim_error_deg;
+        let diff = (desired - me.rot_deg + 180.0).rem_euclid(360.0) - 180.0;
+
+        // never ask for more precision than one tick of rotation can give
+        let t = me.spin_timer.min(1.0);
+        let rot_step = (180.0 + (440.0 - 180.0) * t) * dt;
+        if diff.abs() > self.aim_tolerance_deg().max(rot_step) {
+            return BotInput {
+                left: diff < 0.0,
+                right: diff > 0.0,
+                shoot: false,
+            };
+        }
+
+        if me.shoot_cooldown_sec <= 0.0 && self.reaction_sec <= 0.0 {
+            self.shoot_pressed = true;
+            self.reaction_sec = self.reaction_time_sec();
+            self.aim_error_deg = self.roll_aim_error();
+            return BotInput {
+                left: false,
+                right: false,
+                shoot: true,
+            };
+        }
+
+        BotInput::default()
+    }
+}
+
+fn choose_intent(me: &Player, team: Team, world: &World) -> Intent {
+    let holes = holes(&world.map);
+    let physics = &world.map.physics;
+
+    // holes are deadly everywhere except where they are the objective
+    if !matches!(world.game_mode, GameMode::KingOfTheHill | GameMode::Race) {
+        for (center, radius) in &holes {
+            let away = me.pos - *center;
+            if away.length() - radius < physics.player_radius * 3.0 {
+                return Intent::MoveTo(me.pos + away.normalize_or_zero() * 200.0);
+            }
+        }
+    }
+    let hole_centers = || holes.iter().map(|(center, _)| *center);
+
+    let enemies = || {
+        world
+            .players
+            .values()
+            .filter(|p| matches!(p.status, PlayerStatus::Playing(t) if t != team))
+            .map(|p| p.pos)
+    };
+    let nearest_enemy = nearest(me.pos, enemies());
+    let attack_enemy = || nearest_enemy.map_or(Intent::Idle, Intent::Attack);
+
+    // the flag carrier or the king of the hill
+    let holder = world.hud().holder;
+    let carrier = holder.as_ref().and_then(|id| world.players.get(id));
+    let ball = world.ball.as_ref().map(|b| b.pos);
+    // a goal scores for the team it belongs to
+    let goal = world
+        .map
+        .goals
+        .iter()
+        .find(|g| g.team == team)
+        .map(|g| Vec2::new(g.x + g.w / 2.0, g.y + g.h / 2.0));
+
+    match world.game_mode {
+        GameMode::Football | GameMode::HotPotato => match (ball, goal) {
+            (Some(ball), Some(goal)) => {
+                // get behind the ball before pushing it towards the goal
+                let to_goal = (goal - ball).normalize_or_zero();
+                if (me.pos - ball).dot(to_goal) > 0.0 {
+                    let behind =
+                        ball - to_goal * (physics.player_radius + physics.ball_radius) * 2.0;
+                    Intent::MoveTo(behind)
+                } else {
+                    Intent::MoveTo(ball + to_goal * physics.ball_radius)
+                }
+            }
+            (Some(ball), None) => Intent::MoveTo(ball),
+            _ => attack_enemy(),
+        },
+        GameMode::Ctf => match carrier {
+            None => ball.map_or_else(attack_enemy, Intent::MoveTo),
+            Some(c) if c.id == me.id => goal.map_or_else(attack_enemy, Intent::MoveTo),
+            Some(c) if c.status != me.status => Intent::Attack(c.pos),
+            Some(_) => attack_enemy(),
+        },
+        GameMode::Htf => match carrier {
+            None => ball.map_or_else(attack_enemy, Intent::MoveTo),
+            Some(c) if c.status != me.status => Intent::Attack(c.pos),
+            Some(_) => attack_enemy(),
+        },
+        GameMode::KingOfTheHill => match carrier {
+            Some(king) if king.id == me.id => attack_enemy(),
+            Some(king) if king.status != me.status => Intent::Attack(king.pos),
+            _ => nearest(me.pos, hole_centers()).map_or_else(attack_enemy, Intent::MoveTo),
+        },
+        GameMode::Race => nearest(me.pos, hole_centers()).map_or(Intent::Idle, Intent::MoveTo),
+        GameMode::Fight => push_into_hole(me.pos, enemies(), &holes, physics)
+            .unwrap_or_else(attack_enemy),
+        GameMode::Shooter | GameMode::Custom => attack_enemy(),
+    }
+}
+
+/// Picks the enemy that is quickest to push into a hole, counting the way to
+/// the enemy and from there to the hole. The bot first gets to the side of the
+/// enemy away from the hole, then shoots along the line from the enemy to the
+/// hole, so the hits drive the enemy in. `None` on a map without holes.
+fn push_into_hole(
+    me: Vec2,
+    enemies: impl Iterator<Item = Vec2>,
+    holes: &[(Vec2, f32)],
+    physics: &PhysicsSettings,
+) -> Option<Intent> {
+    let (enemy, hole) = enemies
+        .flat_map(|enemy| holes.iter().map(move |(hole, _)| (enemy, *hole)))
+        .min_by(|(e1, h1), (e2, h2)| {
+            let cost = |e: &Vec2, h: &Vec2| me.distance(*e) + e.distance(*h);
+            cost(e1, h1).total_cmp(&cost(e2, h2))
+        })?;
+
+    let to_hole = (hole - enemy).normalize_or_zero();
+    // within about 25 degrees of the line, a hit still pushes the enemy towards the hole
+    if (enemy - me).normalize_or_zero().dot(to_hole) > 0.9 {
+        Some(Intent::Attack(enemy))
+    } else {
+        let behind = enemy - to_hole * physics.player_radius * 4.0;
+        Some(Intent::MoveTo(behind))
+    }
+}
+
+/// Center and rough radius of every hole on the map.
+fn holes(map: &GameMap) -> Vec<(Vec2, f32)> {
+    map.objects
+        .iter()
+        .filter_map(|obj| match obj {
+            MapObject::Circle {
+                x,
+                y,
+                radius,
+                is_hole: true,
+                ..
+            } => Some((Vec2::new(*x, *y), *radius)),
+            MapObject::Rect {
+                x,
+                y,
+                w,
+                h,
+                is_hole: true,
+                ..
+            } => Some((Vec2::new(x + w / 2.0, y + h / 2.0), w.max(*h) / 2.0)),
+            MapObject::Line {
+                ax,
+                ay,
+                bx,
+                by,
+                is_hole: true,
+                ..
+            } => {
+                let a = Vec2::new(*ax, *ay);
+                let b = Vec2::new(*bx, *by);
+                Some(((a + b) / 2.0, a.distance(b) / 2.0))
+            }
+            MapObject::Polygon {
+                points,
+                is_hole: true,
+                ..
+            } if !points.is_empty() => {
+                let corners: Vec<Vec2> = points.iter().map(|p| Vec2::new(p.x, p.y)).collect();
+                let center = corners.iter().sum::<Vec2>() / corners.len() as f32;
+                let radius = corners
+                    .iter()
+                    .map(|c| c.distance(center))
+                    .fold(0.0, f32::max);
+                Some((center, radius))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn nearest(from: Vec2, points: impl Iterator<Item = Vec2>) -> Option<Vec2> {
+    points.min_by(|a, b| {
+        a.distance_squared(from)
+            .total_cmp(&b.distance_squared(from))
+    })
+}
@@ -0,0 +1,52 @@
+use std::time::Instant;
+
+use spin_snowball_shared::MAX_CHAT_LEN;
+
+/// Messages a player can send in a quick burst.
+const BURST: f32 = 5.0;
+/// Messages regained per second once the burst is used up.
+const REFILL_PER_SEC: f32 = 0.5;
+
+/// Token bucket limiting how fast one player can chat.
+pub struct ChatLimiter {
+    tokens: f32,
+    last: Instant,
+}
+
+impl ChatLimiter {
+    pub fn new() -> Self {
+        Self {
+            tokens: BURST,
+            last: Instant::now(),
+        }
+    }
+
+    /// Takes a token for one message, or returns false if the player has to wait.
+    pub fn try_take(&mut self) -> bool {
+        let now = Instant::now();
+        let refill = now.duration_since(self.last).as_secs_f32() * REFILL_PER_SEC;
+        self.tokens = (self.tokens + refill).min(BURST);
+        self.last = now;
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// Trims the message and strips control characters, rejecting empty and overlong ones.
+pub fn clean_message(text: &str) -> Result<String, String> {
+    let text: String = text.trim().chars().filter(|c| !c.is_control()).collect();
+    if text.is_empty() {
+        return Err("Cannot send an empty message".to_string());
+    }
+    if text.chars().count() > MAX_CHAT_LEN {
+        return Err(format!(
+            "Chat messages are limited to {} characters",
+            MAX_CHAT_LEN
+        ));
+    }
+    Ok(text)
+}
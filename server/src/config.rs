@@ -0,0 +1,323 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use spin_snowball_shared::validation::{validate_game_mode, validate_map};
+use spin_snowball_shared::*;
+
+use crate::script::ScriptRules;
+
+/// Starting map when none is configured, looked up in the working directory.
+const DEFAULT_MAP: &str = "default_map.json";
+
+const USAGE: &str = "\
+Usage: spin-snowball-server [OPTIONS] [HOST:PORT]
+
+Options (override the config file):
+  --config=FILE         read settings from a JSON config file
+  --name=NAME           server name shown to clients
+  --host=HOST           address to bind to (default 0.0.0.0)
+  --port=PORT           port to listen on (default 9001)
+  --map=FILE            map every new room starts with (default default_map.json)
+  --map-dir=DIR         map library, also where a relative --map is looked up
+  --map-rotation=BOOL   switch to the next library map after every finished match
+  --tick-rate=HZ        physics ticks per second (default 30)
+  --snapshot-rate=HZ    snapshots sent to each client per second (default 60)
+  --host-password=PASS  password that makes a player host of the default room
+  --max-players=N       players and bots allowed per room (default 16)
+  --max-team-size=N     players and bots allowed per team (default no limit)
+  --auto-assign=BOOL    put joining players on the smaller team, whatever they ask for
+  --mode=MODE           game mode of new rooms, e.g. fight, football, kingOfTheHill
+  --action-time=SECS    target time of the flag and hill modes (default 10)
+  --score-limit=N       score limit used when a match is started without limits
+  --time-limit=SECS     time limit used when a match is started without limits
+  --replays=DIR         record finished matches into DIR
+  --ratings=FILE        keep player ratings in FILE instead of only in memory
+  --reconnect-grace=SECS
+                        how long dropped players are kept for resuming (default 30)
+  --help                print this help
+
+The config file is a JSON object with any of the keys name, host, port, map,
+map_dir, map_rotation, tick_rate, snapshot_rate, host_password, max_players,
+max_team_size, auto_assign_teams, game_mode, action_target_time, score_limit,
+time_limit_secs, team1_color, team2_color, replays, ratings and
+reconnect_grace_secs.";
+
+/// Server settings, read from an optional JSON file and then overridden by
+/// command-line flags. Every field has a default, so a config file only needs
+/// the settings it changes.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    /// Map every new room starts with. Relative paths are looked up in `map_dir`
+    /// if set; without a map, `default_map.json` in the working directory is used.
+    pub map: Option<PathBuf>,
+    /// Maps players can pick from with `Command::SelectMap`.
+    pub map_dir: Option<PathBuf>,
+    /// Move on to the next map of `map_dir` whenever a match ends by its limits.
+    pub map_rotation: bool,
+    pub tick_rate: f32,
+    pub snapshot_rate: f32,
+    /// Lets a player claim host of the default room with `Command::ClaimHost`;
+    /// `None` leaves that room without a host.
+    pub host_password: Option<String>,
+    /// Players, spectators and bots allowed in one room.
+    pub max_players: usize,
+    /// Players and bots allowed on one team; `None` for no limit besides `max_players`.
+    pub max_team_size: Option<usize>,
+    /// Join players to the team with fewer players instead of the one they pick.
+    pub auto_assign_teams: bool,
+    pub game_mode: GameMode,
+    pub action_target_time: Option<f32>,
+    /// Applied when a match is started with neither a score nor a time limit.
+    pub score_limit: Option<u8>,
+    pub time_limit_secs: Option<u32>,
+    pub team1_color: ColorDef,
+    pub team2_color: ColorDef,
+    /// Where finished matches are saved; `None` disables recording.
+    pub replays: Option<PathBuf>,
+    /// Where player ratings are kept; `None` forgets them when the server stops.
+    pub ratings: Option<PathBuf>,
+    /// How long the player of a dropped connection is kept so the client can
+    /// resume it; 0 removes players right away.
+    pub reconnect_grace_secs: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            name: "Spin Snowball".to_string(),
+            host: "0.0.0.0".to_string(),
+            port: 9001,
+            map: None,
+            map_dir: None,
+            map_rotation: false,
+            tick_rate: 30.0,
+            snapshot_rate: 60.0,
+            host_password: None,
+            max_players: 16,
+            max_team_size: None,
+            auto_assign_teams: false,
+            game_mode: GameMode::Fight,
+            action_target_time: Some(10.0),
+            score_limit: None,
+            time_limit_secs: None,
+            team1_color: ColorDef {
+                r: 200,
+                g: 0,
+                b: 0,
+                a: 255,
+            },
+            team2_color: ColorDef {
+                r: 0,
+                g: 0,
+                b: 200,
+                a: 255,
+            },
+            replays: None,
+            ratings: None,
+            reconnect_grace_secs: 30,
+        }
+    }
+}
+
+/// A setting that cannot be used, with the reason.
+#[derive(Debug)]
+pub struct ConfigError(String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn err<T>(message: impl Into<String>) -> Result<T, ConfigError> {
+    Err(ConfigError(message.into()))
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, ConfigError>
+where
+    T::Err: fmt::Display,
+{
+    value
+        .parse()
+        .or_else(|e| err(format!("--{}={}: {}", flag, value, e)))
+}
+
+/// Parses a game mode by its config-file name, e.g. `kingOfTheHill`.
+fn parse_game_mode(value: &str) -> Result<GameMode, ConfigError> {
+    serde_json::from_value(serde_json::Value::String(value.to_string())).or_else(|_| {
+        err(format!(
+            "--mode={}: unknown game mode, expected one of fight, football, ctf, htf, \
+             kingOfTheHill, race, hotPotato, shooter, custom",
+            value
+        ))
+    })
+}
+
+impl Config {
+    /// Builds the configuration from the command line, reading the config file
+    /// it names first. Returns `Ok(None)` when only the help was asked for.
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Option<Self>, ConfigError> {
+        let args: Vec<String> = args.collect();
+
+        let mut config = match args.iter().find_map(|a| a.strip_prefix("--config=")) {
+            Some(path) => Self::from_file(Path::new(path))?,
+            None => Self::default(),
+        };
+
+        for arg in &args {
+            if arg == "--help" || arg == "-h" {
+                println!("{}", USAGE);
+                return Ok(None);
+            }
+
+            let Some(flag) = arg.strip_prefix("--") else {
+                // a bare address, as older versions of the server took it
+                let Some((host, port)) = arg.rsplit_once(':') else {
+                    return err(format!("{}: expected HOST:PORT\n\n{}", arg, USAGE));
+                };
+                config.host = host.to_string();
+                config.port = parse_number("port", port)?;
+                continue;
+            };
+            let Some((flag, value)) = flag.split_once('=') else {
+                return err(format!(
+                    "--{}: expected --{}=VALUE\n\n{}",
+                    flag, flag, USAGE
+                ));
+            };
+
+            match flag {
+                "config" => {}
+                "name" => config.name = value.to_string(),
+                "host" => config.host = value.to_string(),
+                "port" => config.port = parse_number(flag, value)?,
+                "map" => config.map = Some(PathBuf::from(value)),
+                "map-dir" => config.map_dir = Some(PathBuf::from(value)),
+                "map-rotation" => config.map_rotation = parse_number(flag, value)?,
+                "tick-rate" => config.tick_rate = parse_number(flag, value)?,
+                "snapshot-rate" => config.snapshot_rate = parse_number(flag, value)?,
+                "host-password" => config.host_password = Some(value.to_string()),
+                "max-players" => config.max_players = parse_number(flag, value)?,
+                "max-team-size" => config.max_team_size = Some(parse_number(flag, value)?),
+                "auto-assign" => config.auto_assign_teams = parse_number(flag, value)?,
+                "mode" => config.game_mode = parse_game_mode(value)?,
+                "action-time" => config.action_target_time = Some(parse_number(flag, value)?),
+                "score-limit" => config.score_limit = Some(parse_number(flag, value)?),
+                "time-limit" => config.time_limit_secs = Some(parse_number(flag, value)?),
+                "replays" => config.replays = Some(PathBuf::from(value)),
+                "ratings" => config.ratings = Some(PathBuf::from(value)),
+                "reconnect-grace" => config.reconnect_grace_secs = parse_number(flag, value)?,
+                _ => return err(format!("unknown option --{}\n\n{}", flag, USAGE)),
+            }
+        }
+
+        config.check()?;
+        Ok(Some(config))
+    }
+
+    fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let data = fs::read_to_string(path)
+            .or_else(|e| err(format!("cannot read config {}: {}", path.display(), e)))?;
+        serde_json::from_str(&data)
+            .or_else(|e| err(format!("invalid config {}: {}", path.display(), e)))
+    }
+
+    fn check(&self) -> Result<(), ConfigError> {
+        if self.name.trim().is_empty() {
+            return err("the server name cannot be empty");
+        }
+        if !(1.0..=240.0).contains(&self.tick_rate) {
+            return err(format!(
+                "tick rate {} is out of range, expected 1 to 240",
+                self.tick_rate
+            ));
+        }
+        if !(1.0..=240.0).contains(&self.snapshot_rate) {
+            return err(format!(
+                "snapshot rate {} is out of range, expected 1 to 240",
+                self.snapshot_rate
+            ));
+        }
+        if self.host_password.as_deref() == Some("") {
+            return err("the host password cannot be empty");
+        }
+        if self.max_players == 0 {
+            return err("max players must be at least 1");
+        }
+        if self.max_team_size == Some(0) {
+            return err("the team size limit must be at least 1");
+        }
+        if self
+            .action_target_time
+            .is_some_and(|t| !t.is_finite() || t <= 0.0)
+        {
+            return err("the action time must be a positive number of seconds");
+        }
+        if self.score_limit == Some(0) || self.time_limit_secs == Some(0) {
+            return err("score and time limits must be at least 1");
+        }
+        if let Some(dir) = &self.map_dir {
+            if !dir.is_dir() {
+                return err(format!("map directory {} does not exist", dir.display()));
+            }
+        } else if self.map_rotation {
+            return err("map rotation needs a map directory");
+        }
+        Ok(())
+    }
+
+    pub fn bind_address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    /// Seconds per physics tick.
+    pub fn dt(&self) -> f32 {
+        1.0 / self.tick_rate
+    }
+
+    pub fn map_path(&self) -> PathBuf {
+        match (&self.map, &self.map_dir) {
+            (Some(map), Some(dir)) if map.is_relative() => dir.join(map),
+            (Some(map), _) => map.clone(),
+            (None, _) => PathBuf::from(DEFAULT_MAP),
+        }
+    }
+
+    /// Loads and validates the starting map.
+    pub fn load_map(&self) -> Result<GameMap, ConfigError> {
+        let path = self.map_path();
+        let data = fs::read_to_string(&path)
+            .or_else(|e| err(format!("cannot read map {}: {}", path.display(), e)))?;
+        let map: GameMap = serde_json::from_str(&data)
+            .or_else(|e| err(format!("invalid map {}: {}", path.display(), e)))?;
+
+        let mut report = validate_map(&map);
+        report.merge(validate_game_mode(&map, self.game_mode));
+        for warning in report.warnings() {
+            println!("Map {}: {}", path.display(), warning);
+        }
+        if report.has_errors() {
+            return err(format!(
+                "map {} cannot be played: {}",
+                path.display(),
+                report.error_summary()
+            ));
+        }
+        if let Some(source) = &map.script {
+            ScriptRules::new(source).or_else(|e| {
+                err(format!(
+                    "map {} has an invalid script: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+        }
+        Ok(map)
+    }
+}
@@ -1,41 +1,51 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
 use std::time::{Duration, Instant};
 
-use glam::Vec2;
+use spin_snowball_shared::validation::{validate_game_mode, validate_map};
 use spin_snowball_shared::*;
 use tokio::net::TcpListener;
 use tokio::sync::mpsc::UnboundedSender;
+use uuid::Uuid;
 
 use crate::network::handle_connection;
-use crate::physics::{simulate_collisions, simulate_movement, SimulateCollisionResponse};
-
+use spin_snowball_simulation::{Player, World};
+
+use crate::bot::Bot;
+use crate::chat::ChatLimiter;
+use crate::config::Config;
+use crate::maps::MapLibrary;
+use crate::ratings::Ratings;
+use crate::replay::ReplayRecorder;
+use crate::rng::Rng;
+use crate::room::{Room, Rooms};
+use crate::script::ScriptRules;
+use crate::stats::MatchStats;
+
+mod bot;
+mod chat;
+mod config;
+mod maps;
 mod network;
-mod physics;
-
-const TICK_HZ: f32 = 30.0;
-const DT: f32 = 1.0 / TICK_HZ;
-
-struct Player {
-    id: String,
-    nick: String,
-    pos: Vec2,
-    vel: Vec2,
-    rot_deg: f32,
-    rotating_left: bool,
-    rotating_right: bool,
-    spin_timer: f32,
-    last_shoot_pressed: bool,
-    status: PlayerStatus,
-    shoot_cooldown_sec: f32,
-}
-
-struct Snowball {
-    id: u64,
-    pos: Vec2,
-    vel: Vec2,
-    life: f32,
+mod ratings;
+mod replay;
+mod rng;
+mod room;
+mod script;
+mod stats;
+
+/// Per-connection bookkeeping that the simulation does not need.
+struct Member {
+    join_order: u64,
+    last_input_seq: u32,
+    /// Lets a new connection resume this member after the old one dropped.
+    token: String,
+    /// The connection currently attached; a member without a peer is waiting
+    /// for its client to resume.
+    connection: u64,
+    chat: ChatLimiter,
 }
 
 #[derive(Debug, Clone)]
@@ -95,232 +105,377 @@ impl MatchTimer {
 
 type Tx = UnboundedSender<ServerMessage>;
 struct ClientOut {
+    connection: u64,
     tx: UnboundedSender<ServerMessage>,
-    latest_world: Arc<Mutex<Option<ServerMessage>>>,
+    latest_world: Arc<Mutex<Option<WorldState>>>,
 }
 type PeerMap = Arc<Mutex<HashMap<String, ClientOut>>>;
 
-fn load_map_form_data(data: &str) -> GameMap {
-    serde_json::from_str(data).unwrap()
+static NEXT_SNAPSHOT_SEQ: AtomicU64 = AtomicU64::new(1);
+
+fn publish_world(peers: &PeerMap, world: &WorldState) {
+    let peers_guard = peers.lock().unwrap();
+    for client in peers_guard.values() {
+        *client.latest_world.lock().unwrap() = Some(world.clone());
+    }
+}
+
+fn broadcast(peers: &PeerMap, msg: ServerMessage) {
+    let peers_guard = peers.lock().unwrap();
+    for client in peers_guard.values() {
+        let _ = client.tx.send(msg.clone());
+    }
 }
 
 #[tokio::main]
 async fn main() {
-    let addr = env::args()
-        .nth(1)
-        .unwrap_or_else(|| "0.0.0.0:9001".to_string());
-    println!("Starting server on {}", addr);
-
-    let listener = TcpListener::bind(&addr).await.unwrap();
-    let peers: PeerMap = Arc::new(Mutex::new(HashMap::new()));
-    let map = load_map_form_data(&std::fs::read_to_string("default_map.json").unwrap());
-    let game_state = Arc::new(Mutex::new(GameState::new(map)));
-
-    {
-        let peers = peers.clone();
-        let game_state = game_state.clone();
-        tokio::spawn(async move {
-            physics_loop(game_state, peers).await;
-        });
-    }
+    let config = match Config::from_args(env::args().skip(1)) {
+        Ok(Some(config)) => config,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let map = match config.load_map() {
+        Ok(map) => map,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(2);
+        }
+    };
+    let ratings = match Ratings::load(config.ratings.clone()) {
+        Ok(ratings) => ratings,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(2);
+        }
+    };
+
+    let addr = config.bind_address();
+    println!("Starting server {:?} on {}", config.name, addr);
+    let listener = match TcpListener::bind(&addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Error: cannot listen on {}: {}", addr, e);
+            std::process::exit(1);
+        }
+    };
+    let maps = MapLibrary::scan(config.map_dir.clone());
+    let map_name = maps.name_of(&config.map_path());
+    let rooms = Rooms::new(
+        Arc::new(config),
+        Arc::new(Mutex::new(maps)),
+        Arc::new(Mutex::new(ratings)),
+        map,
+        map_name,
+    );
 
     while let Ok((stream, _)) = listener.accept().await {
-        let peers = peers.clone();
-        let game_state = game_state.clone();
+        let rooms = rooms.clone();
         tokio::spawn(async move {
-            handle_connection(stream, peers, game_state).await;
+            handle_connection(stream, rooms).await;
         });
     }
 }
 
-#[derive(Clone)]
-struct Ball {
-    pos: Vec2,
-    vel: Vec2,
-}
-
 struct GameState {
-    players: HashMap<String, Player>,
-    snowballs: HashMap<u64, Snowball>,
-    next_snowball_id: u64,
-    map: GameMap,
-    scores: HashMap<Team, u8>,
-    ball: Option<Ball>,
+    world: World,
+    members: HashMap<String, Member>,
     phase: MatchPhase,
     timer: MatchTimer,
     paused: bool,
     team1_color: ColorDef,
     team2_color: ColorDef,
-    player_with_active_action: Option<(String, f32)>,
-    game_mode: GameMode,
-    action_target_time: Option<f32>,
+    host: Option<String>,
+    admins: HashSet<String>,
+    host_password: Option<String>,
+    /// Players who chose a nick. Only they are rated, as the default nicks are
+    /// worn by whoever joins and would share one rating.
+    named: HashSet<String>,
+    next_join_order: u64,
+    /// Physics ticks since the room was created, including paused ones.
+    tick: u64,
+    room_name: String,
+    config: Arc<Config>,
+    maps: Arc<Mutex<MapLibrary>>,
+    ratings: Arc<Mutex<Ratings>>,
+    /// Library name of the current map; `None` for maps uploaded with `Command::LoadMap`.
+    map_name: Option<String>,
+    /// Script the custom mode plays by instead of the map's, set with `Command::SetScript`.
+    script: Option<String>,
+    recorder: Option<ReplayRecorder>,
+    stats: MatchStats,
+    /// Bots have a `Player` in the world but no `Member`, so they never become host.
+    bots: HashMap<String, Bot>,
+    next_bot_number: u32,
 }
 
 impl GameState {
-    fn new(map: GameMap) -> Self {
-        let ball = match map.ball.as_ref() {
-            Some(b) => Some(Ball {
-                pos: Vec2::new(b.spawn_x, b.spawn_y),
-                vel: Vec2::ZERO,
-            }),
-            _ => None,
-        };
-        println!("HEJA {:?}", ball.is_some());
-
-        Self {
-            players: HashMap::new(),
-            snowballs: HashMap::new(),
-            next_snowball_id: 1,
-            scores: [(Team::Team1, 0), (Team::Team2, 0)].into(),
-            ball,
-            map,
+    fn new(
+        map: GameMap,
+        config: Arc<Config>,
+        maps: Arc<Mutex<MapLibrary>>,
+        ratings: Arc<Mutex<Ratings>>,
+    ) -> Self {
+        let mut world = World::new(map);
+        world.set_game_mode(config.game_mode);
+        world.action_target_time = config.action_target_time;
+
+        let mut gs = Self {
+            world,
+            members: HashMap::new(),
             phase: MatchPhase::Lobby,
             timer: MatchTimer::new(),
             paused: false,
-            team1_color: ColorDef {
-                r: 200,
-                g: 0,
-                b: 0,
-                a: 255,
-            },
-            team2_color: ColorDef {
-                r: 0,
-                g: 0,
-                b: 200,
-                a: 255,
-            },
-            player_with_active_action: None,
-            game_mode: GameMode::Fight,
-            action_target_time: Some(10.0),
-        }
+            team1_color: config.team1_color.clone(),
+            team2_color: config.team2_color.clone(),
+            host: None,
+            admins: HashSet::new(),
+            host_password: None,
+            named: HashSet::new(),
+            next_join_order: 0,
+            tick: 0,
+            room_name: String::new(),
+            config,
+            maps,
+            ratings,
+            map_name: None,
+            script: None,
+            recorder: None,
+            stats: MatchStats::default(),
+            bots: HashMap::new(),
+            next_bot_number: 1,
+        };
+        gs.load_script();
+        gs
     }
 
-    fn get_team_of_player(&self, player_id: &str) -> Option<Team> {
-        self.players.get(player_id).and_then(|x| {
-            if let PlayerStatus::Playing(x) = x.status {
-                Some(x)
-            } else {
-                None
-            }
-        })
-    }
+    fn add_new_player(&mut self, id: String, token: String, connection: u64) {
+        if self.host.is_none() {
+            self.host = Some(id.clone());
+        }
 
-    fn add_new_player(&mut self, id: String) {
-        self.players.insert(
+        let nick = format!("Player {}", self.world.players.len() + 1);
+        self.members.insert(
             id.clone(),
-            Player {
-                id,
-                nick: format!("Player {}", self.players.len() + 1),
-                pos: Vec2::ZERO,
-                vel: Vec2::ZERO,
-                rot_deg: -90.0,
-                rotating_left: false,
-                rotating_right: false,
-                spin_timer: 0.0,
-                last_shoot_pressed: false,
-                status: PlayerStatus::Spectator,
-                shoot_cooldown_sec: 0.0,
+            Member {
+                join_order: self.next_join_order,
+                last_input_seq: 0,
+                token,
+                connection,
+                chat: ChatLimiter::new(),
             },
         );
+        self.world.players.insert(id.clone(), Player::new(id, nick));
+        self.next_join_order += 1;
     }
 
     fn remove_player(&mut self, id: &str) {
-        self.players.remove(id);
+        self.world.players.remove(id);
+        self.members.remove(id);
+        self.admins.remove(id);
+
+        if self.host.as_deref() == Some(id) {
+            // hand the room over to whoever has been here the longest
+            self.host = self
+                .members
+                .iter()
+                .min_by_key(|(_, m)| m.join_order)
+                .map(|(id, _)| id.clone());
+            println!("host left, new host: {:?}", self.host);
+        }
     }
 
-    fn apply_input(&mut self, id: &str, left: bool, right: bool, shoot: bool) {
-        if self.paused {
-            return;
+    /// The member whose session `token` belongs to.
+    fn session_of(&self, token: &str) -> Option<String> {
+        self.members
+            .iter()
+            .find(|(_, m)| m.token == token)
+            .map(|(id, _)| id.clone())
+    }
+
+    /// Hands the member over to a new connection, returning the one it replaces.
+    fn attach(&mut self, id: &str, connection: u64) -> Option<u64> {
+        let member = self.members.get_mut(id)?;
+        Some(std::mem::replace(&mut member.connection, connection))
+    }
+
+    /// Whether `connection` still belongs to the member, i.e. it has not been resumed since.
+    fn is_attached(&self, id: &str, connection: u64) -> bool {
+        self.members
+            .get(id)
+            .is_some_and(|m| m.connection == connection)
+    }
+
+    fn is_full(&self) -> bool {
+        self.world.players.len() >= self.config.max_players
+    }
+
+    fn add_bot(&mut self, team: Team, difficulty: BotDifficulty) {
+        let id = format!("bot-{}", Uuid::new_v4());
+        let nick = format!("Bot {} ({:?})", self.next_bot_number, difficulty);
+        self.next_bot_number += 1;
+
+        let mut player = Player::new(id.clone(), nick);
+        player.status = PlayerStatus::Playing(team);
+        self.world.players.insert(id.clone(), player);
+        if let MatchPhase::Playing { .. } = self.phase {
+            self.world.respawn(&id);
         }
 
-        if let Some(p) = self.players.get_mut(id) {
-            if let PlayerStatus::Playing(_) = p.status {
-                p.rotating_left = left;
-                p.rotating_right = right;
+        let seed = self.tick ^ ((self.next_bot_number as u64) << 32);
+        self.bots.insert(id, Bot::new(difficulty, seed));
+    }
 
-                if p.shoot_cooldown_sec > 0.0 {
-                    p.last_shoot_pressed = true;
-                    return;
-                }
+    fn remove_bot(&mut self, id: &str) -> bool {
+        if self.bots.remove(id).is_none() {
+            return false;
+        }
+        self.world.players.remove(id);
+        true
+    }
 
-                // Edge-detect the shoot button on server side:
-                // only spawn a snowball when shoot transitions from false -> true
-                if shoot && !p.last_shoot_pressed {
-                    // --- Charge ---
-                    let max_charge = 1.0;
-                    let charge = p.spin_timer.min(max_charge);
-                    let charge_t = (charge / max_charge).clamp(0.0, 1.0);
-
-                    // --- Snowball speed ---
-                    let base_speed = 300.0;
-                    let max_extra_speed = 700.0;
-                    let snowball_speed = base_speed + max_extra_speed * charge_t;
-
-                    // --- Direction ---
-                    let r = p.rot_deg.to_radians();
-                    let dir = Vec2::new(r.cos(), r.sin());
-
-                    // --- Spawn ---
-                    let spawn_pos = p.pos + dir * 26.0;
-
-                    let id = self.next_snowball_id;
-                    self.next_snowball_id += 1;
-                    self.snowballs.insert(
-                        id,
-                        Snowball {
-                            id,
-                            pos: spawn_pos,
-                            vel: dir * snowball_speed,
-                            life: self.map.physics.snowball_lifetime_sec,
-                        },
-                    );
-
-                    // --- Recoil (momentum-based) ---
-                    let snowball_mass = self.map.physics.snowball_mass;
-                    let player_mass = self.map.physics.player_mass;
-
-                    let recoil_velocity = (snowball_mass * snowball_speed / player_mass)
-                        * self.map.physics.recoil_power;
-
-                    p.vel -= dir * recoil_velocity;
-
-                    // --- Reset ---
-                    p.spin_timer = 0.0;
-                    p.shoot_cooldown_sec = self.map.physics.shoot_cooldown_sec;
-                    p.last_shoot_pressed = true;
-                } else if !shoot {
-                    p.last_shoot_pressed = false;
-                }
-            }
+    /// Players and bots on `team`, not counting `except`.
+    fn team_size(&self, team: Team, except: &str) -> usize {
+        self.world
+            .players
+            .values()
+            .filter(|p| p.id != except && p.status == PlayerStatus::Playing(team))
+            .count()
+    }
+
+    /// The team `id` would even out the most; Team 1 on a tie.
+    fn smaller_team(&self, id: &str) -> Team {
+        if self.team_size(Team::Team2, id) < self.team_size(Team::Team1, id) {
+            Team::Team2
+        } else {
+            Team::Team1
         }
     }
 
-    fn logic_step(&mut self, dt: f32) {
-        for p in self.players.values_mut() {
-            if p.shoot_cooldown_sec > 0.0 {
-                p.shoot_cooldown_sec -= dt;
+    /// Refuses a player or bot that would take `team` past the team size limit.
+    fn check_team_size(&self, team: Team, id: &str) -> Result<(), String> {
+        match self.config.max_team_size {
+            Some(limit) if self.team_size(team, id) >= limit => Err(format!(
+                "{:?} is full, teams are limited to {} players",
+                team, limit
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Puts a player on a team, the smaller one if they did not pick one or
+    /// the server assigns teams itself. Returns the team joined.
+    fn join_team(&mut self, id: &str, team: Option<Team>) -> Result<Team, String> {
+        let team = match team {
+            Some(team) if !self.config.auto_assign_teams => team,
+            _ => self.smaller_team(id),
+        };
+        self.check_team_size(team, id)?;
+        if let Some(p) = self.world.players.get_mut(id) {
+            p.status = PlayerStatus::Playing(team);
+        }
+        Ok(team)
+    }
+
+    /// Deals everyone on a team out again. By rating, the best remaining player
+    /// goes to the team with the lower total so far; otherwise at random.
+    fn shuffle_teams(&mut self, by_rating: bool) {
+        let mut players: Vec<(String, f32)> = {
+            let ratings = self.ratings.lock().unwrap();
+            self.world
+                .players
+                .values()
+                .filter(|p| matches!(p.status, PlayerStatus::Playing(_)))
+                .map(|p| (p.id.clone(), ratings.rating(&p.nick)))
+                .collect()
+        };
+        if by_rating {
+            players.sort_by(|a, b| b.1.total_cmp(&a.1));
+        } else {
+            // Fisher-Yates, seeded from a random uuid
+            let mut rng = Rng::new(Uuid::new_v4().as_u128() as u64);
+            for i in (1..players.len()).rev() {
+                players.swap(i, rng.below(i + 1));
             }
+        }
 
-            if p.rotating_left || p.rotating_right {
-                p.spin_timer += dt;
+        let max_size = players.len().div_ceil(2);
+        let mut teams = [(Team::Team1, 0, 0.0), (Team::Team2, 0, 0.0)];
+        for (id, rating) in players {
+            let open = |t: &(Team, usize, f32)| t.1 < max_size;
+            let index = match (open(&teams[0]), open(&teams[1])) {
+                (true, false) => 0,
+                (false, true) => 1,
+                _ if by_rating => usize::from(teams[1].2 < teams[0].2),
+                _ => usize::from(teams[1].1 < teams[0].1),
+            };
+            let (team, size, total) = &mut teams[index];
+            *size += 1;
+            *total += rating;
+            if let Some(p) = self.world.players.get_mut(&id) {
+                p.status = PlayerStatus::Playing(*team);
             }
         }
-        let mut dead = Vec::new();
-        for (&id, sb) in self.snowballs.iter_mut() {
-            sb.pos += sb.vel * dt;
-            sb.vel *= 0.995;
-            sb.life -= dt;
-            if sb.life <= 0.0 {
-                dead.push(id);
+    }
+
+    /// Lets every bot decide on its input for the coming tick.
+    fn run_bots(&mut self, dt: f32) {
+        let inputs: Vec<_> = self
+            .bots
+            .iter_mut()
+            .map(|(id, bot)| (id.clone(), bot.think(id, &self.world, dt)))
+            .collect();
+        for (id, input) in inputs {
+            self.apply_input(&id, input.left, input.right, input.shoot);
+        }
+    }
+
+    fn role_of(&self, id: &str) -> PlayerRole {
+        if self.host.as_deref() == Some(id) {
+            PlayerRole::Host
+        } else if self.admins.contains(id) {
+            PlayerRole::Admin
+        } else {
+            PlayerRole::Player
+        }
+    }
+
+    fn claim_host(&mut self, id: &str, password: &str) -> bool {
+        if self.host_password.as_deref() != Some(password) {
+            return false;
+        }
+
+        if let Some(old_host) = self.host.replace(id.to_string()) {
+            if old_host != id {
+                self.admins.insert(old_host);
             }
         }
-        for id in dead {
-            self.snowballs.remove(&id);
+        self.admins.remove(id);
+        true
+    }
+
+    fn apply_input(&mut self, id: &str, left: bool, right: bool, shoot: bool) {
+        if self.paused {
+            return;
+        }
+
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record_input(id, left, right, shoot);
+        }
+        let next_snowball_id = self.world.next_snowball_id;
+        self.world.apply_input(id, left, right, shoot);
+        if self.world.next_snowball_id != next_snowball_id
+            && matches!(self.phase, MatchPhase::Playing { .. })
+        {
+            self.stats.record_throw(id);
         }
     }
 
-    fn snapshot(&self) -> (Vec<PlayerState>, Vec<SnowballState>) {
-        let players = self
+    fn snapshot(&self) -> WorldState {
+        let players: Vec<PlayerState> = self
+            .world
             .players
             .values()
             .map(|p| PlayerState {
@@ -330,10 +485,16 @@ impl GameState {
                 vel: [p.vel.x, p.vel.y],
                 rot_deg: p.rot_deg,
                 status: p.status,
+                role: self.role_of(&p.id),
+                spin_timer: p.spin_timer,
+                shoot_cooldown_sec: p.shoot_cooldown_sec,
+                last_input_seq: self.members.get(&p.id).map_or(0, |m| m.last_input_seq),
+                is_bot: self.bots.contains_key(&p.id),
             })
             .collect();
 
-        let snowballs = self
+        let snowballs: Vec<SnowballState> = self
+            .world
             .snowballs
             .values()
             .map(|s| SnowballState {
@@ -344,41 +505,264 @@ impl GameState {
             })
             .collect();
 
-        (players, snowballs)
+        WorldState {
+            seq: NEXT_SNAPSHOT_SEQ.fetch_add(1, Ordering::Relaxed),
+            tick: self.tick,
+            server_time: self.tick as f64 * self.config.dt() as f64,
+            players,
+            snowballs,
+            ball: self.world.ball.clone().map(|x| BallState {
+                pos: x.pos.into(),
+                vel: x.vel.into(),
+            }),
+            scores_team1: self.world.scores[&Team::Team1],
+            scores_team2: self.world.scores[&Team::Team2],
+            phase: self.phase,
+            time_elapsed: self.timer.elapsed_secs(),
+            paused: self.paused,
+            team1_color: self.team1_color.clone(),
+            team2_color: self.team2_color.clone(),
+            hud: self.world.hud(),
+            game_mode: self.world.game_mode,
+            action_target_time: self.world.action_target_time,
+        }
+    }
+
+    fn load_map(&mut self, data: &str) -> Result<(), String> {
+        let map: GameMap =
+            serde_json::from_str(data).map_err(|e| format!("Cannot parse map: {}", e))?;
+        self.set_map(map, None)
+    }
+
+    fn set_map(&mut self, map: GameMap, library_name: Option<String>) -> Result<(), String> {
+        let mut report = validate_map(&map);
+        report.merge(validate_game_mode(&map, self.world.game_mode));
+        for warning in report.warnings() {
+            println!("Map {}: {}", map.name, warning);
+        }
+        if report.has_errors() {
+            return Err(report.error_summary());
+        }
+        if let Some(source) = &map.script {
+            ScriptRules::new(source).map_err(|e| format!("Invalid map script: {}", e))?;
+        }
+
+        self.world.set_map(map);
+        self.map_name = library_name;
+        self.load_script();
+        Ok(())
+    }
+
+    /// Switches to the next library map playable in the current mode. Returns
+    /// false when there is no other map to switch to or it cannot be loaded.
+    fn rotate_map(&mut self) -> bool {
+        let next = self
+            .maps
+            .lock()
+            .unwrap()
+            .next(self.map_name.as_deref(), self.world.game_mode);
+        let Some((name, map)) = next else {
+            return false;
+        };
+        if let Err(e) = self.set_map(map, Some(name.clone())) {
+            println!("Room {} cannot rotate to map {}: {}", self.room_name, name, e);
+            return false;
+        }
+        println!("Room {} rotates to map {}", self.room_name, name);
+        true
+    }
+
+    fn set_physics(&mut self, settings: PhysicsSettings) -> Result<(), String> {
+        let map = GameMap {
+            physics: settings,
+            ..self.world.map.clone()
+        };
+        let report = validate_map(&map);
+        if report.has_errors() {
+            return Err(report.error_summary());
+        }
+        self.world.map.physics = map.physics;
+        Ok(())
+    }
+
+    fn set_game_mode(
+        &mut self,
+        game_mode: GameMode,
+        action_target_time: Option<f32>,
+    ) -> Result<(), String> {
+        let report = validate_game_mode(&self.world.map, game_mode);
+        if report.has_errors() {
+            return Err(report.error_summary());
+        }
+        self.world.set_game_mode(game_mode);
+        self.world.action_target_time = action_target_time;
+        self.load_script();
+        Ok(())
     }
 
-    fn load_map(&mut self, data: &str) {
-        self.map = serde_json::from_str(&data).unwrap();
-        self.reset_positions();
+    /// Plays the custom mode by `source` instead of the map's script, or by
+    /// the map's script again if `None`, switching to the custom mode.
+    fn set_script(&mut self, source: Option<String>) -> Result<(), String> {
+        let report = validate_game_mode(&self.world.map, GameMode::Custom);
+        if report.has_errors() {
+            return Err(report.error_summary());
+        }
+        match (&source, &self.world.map.script) {
+            (Some(source), _) => {
+                ScriptRules::new(source)?;
+            }
+            (None, Some(map_script)) => {
+                ScriptRules::new(map_script).map_err(|e| format!("Invalid map script: {}", e))?;
+            }
+            (None, None) => {}
+        }
+        self.script = source;
+        self.world.set_game_mode(GameMode::Custom);
+        self.load_script();
+        Ok(())
     }
 
-    pub fn start_match(&mut self, score_limit: Option<u8>, time_limit_secs: Option<u32>) {
+    /// Puts the script rules in place when playing the custom mode. Without a
+    /// script the mode keeps its placeholder rules, where nothing scores.
+    fn load_script(&mut self) {
+        if self.world.game_mode != GameMode::Custom {
+            return;
+        }
+        self.world.set_game_mode(GameMode::Custom);
+        let Some(source) = self.script.as_ref().or(self.world.map.script.as_ref()) else {
+            return;
+        };
+        match ScriptRules::new(source) {
+            Ok(rules) => self.world.set_rules(Box::new(rules)),
+            Err(e) => println!("Room {} cannot load its script: {}", self.room_name, e),
+        }
+    }
+
+    pub fn start_match(
+        &mut self,
+        mut score_limit: Option<u8>,
+        mut time_limit_secs: Option<u32>,
+    ) {
+        if score_limit.is_none() && time_limit_secs.is_none() {
+            score_limit = self.config.score_limit;
+            time_limit_secs = self.config.time_limit_secs;
+        }
         println!("match started: {:?} {:?}", score_limit, time_limit_secs);
 
-        self.scores.clear();
-        self.scores.insert(Team::Team1, 0);
-        self.scores.insert(Team::Team2, 0);
-        self.reset_positions();
+        self.world.start_match();
         self.phase = MatchPhase::Playing {
             score_limit,
             time_limit_secs,
         };
         self.timer.reset();
         self.timer.start();
+        self.stats = MatchStats::default();
+        self.update_stats(0.0);
+        self.world.events.push(GameEvent::MatchStarted {
+            score_limit,
+            time_limit_secs,
+        });
+
+        if let Some(dir) = self.config.replays.clone() {
+            let initial = self.snapshot();
+            self.recorder = Some(ReplayRecorder::new(
+                dir,
+                &self.room_name,
+                initial,
+                &self.world.map,
+                self.config.tick_rate,
+            ));
+        }
+    }
+
+    /// Counts the tick into the match statistics; must run before its events are taken.
+    fn update_stats(&mut self, dt: f32) {
+        let bots = &self.bots;
+        self.stats
+            .update_roster(&self.world, |id| bots.contains_key(id));
+        self.stats
+            .record_events(&self.world.events, self.timer.elapsed_secs());
+        self.stats.record_tick(&self.world, dt);
+    }
+
+    fn record_tick(&mut self) {
+        if self.recorder.is_some() {
+            let world = self.snapshot();
+            if let Some(recorder) = &mut self.recorder {
+                recorder.record_tick(world);
+            }
+        }
+        if self.recorder.as_ref().is_some_and(|r| r.is_full()) {
+            println!(
+                "Replay of room {} is full, the rest of the match is not recorded",
+                self.room_name
+            );
+            if let Some(recorder) = self.recorder.take() {
+                recorder.save();
+            }
+        }
+    }
+
+    /// Sends everyone back to the lobby; bots stay on their team for the next match.
+    fn move_players_to_spectators(&mut self) {
+        for p in self.world.players.values_mut() {
+            if !self.bots.contains_key(&p.id) {
+                p.status = PlayerStatus::Spectator;
+            }
+        }
     }
 
-    pub fn stop_match(&mut self) {
+    /// Ends the match and returns its summary, or `None` if none was running.
+    /// Only `rated` matches change ratings; those are the ones that reached
+    /// their limit, so a host cannot bank a lead by stopping early.
+    pub fn stop_match(&mut self, rated: bool) -> Option<MatchSummary> {
+        let mut summary = None;
+        if let MatchPhase::Playing { .. } = &self.phase {
+            let score = |team| self.world.scores.get(&team).copied().unwrap_or(0);
+            let (scores_team1, scores_team2) = (score(Team::Team1), score(Team::Team2));
+            let winner = match scores_team1.cmp(&scores_team2) {
+                std::cmp::Ordering::Greater => Some(Team::Team1),
+                std::cmp::Ordering::Less => Some(Team::Team2),
+                std::cmp::Ordering::Equal => None,
+            };
+            self.world.events.push(GameEvent::MatchEnded {
+                winner,
+                scores_team1,
+                scores_team2,
+            });
+            let match_summary = self.stats.summary(
+                self.world.game_mode,
+                winner,
+                (scores_team1, scores_team2),
+                self.timer.elapsed_secs(),
+            );
+            if rated {
+                self.ratings
+                    .lock()
+                    .unwrap()
+                    .record(&match_summary, &self.named);
+            }
+            // players who left are only remembered for the match they played in
+            let players = &self.world.players;
+            self.named.retain(|id| players.contains_key(id));
+            summary = Some(match_summary);
+        }
+
         self.phase = MatchPhase::Lobby;
         self.timer.pause();
-        for p in self.players.values_mut() {
-            p.status = PlayerStatus::Spectator;
+        self.move_players_to_spectators();
+
+        if let Some(recorder) = self.recorder.take() {
+            recorder.save();
         }
+        summary
     }
 
     pub fn pause_match(&mut self) {
         if let MatchPhase::Playing { .. } = &self.phase {
             self.paused = true;
             self.timer.pause();
+            self.world.events.push(GameEvent::MatchPaused);
         }
     }
 
@@ -386,348 +770,84 @@ impl GameState {
         if let MatchPhase::Playing { .. } = &self.phase {
             self.paused = false;
             self.timer.start();
+            self.world.events.push(GameEvent::MatchResumed);
         }
     }
 
-    fn reset_player_position(p: &mut Player, team1: &TeamDef, team2: &TeamDef) {
-        match p.status {
-            PlayerStatus::Playing(Team::Team1) => {
-                let x = team1.spawn_x;
-                let y = team1.spawn_y;
-                p.pos = Vec2::new(x, y);
-                p.vel = Vec2::ZERO;
-                p.rot_deg = -90.0;
-            }
-            PlayerStatus::Playing(Team::Team2) => {
-                let x = team2.spawn_x;
-                let y = team2.spawn_y;
-                p.pos = Vec2::new(x, y);
-                p.vel = Vec2::ZERO;
-                p.rot_deg = -90.0;
-            }
-            PlayerStatus::Spectator => (),
-        }
-    }
-
-    pub fn reset_positions(&mut self) {
-        for p in self.players.values_mut() {
-            Self::reset_player_position(p, &self.map.team1, &self.map.team2);
-        }
-
-        self.snowballs = HashMap::new();
-        self.player_with_active_action = None;
-        if let Some(x) = self.map.ball.clone() {
-            if let Some(ball) = &mut self.ball {
-                ball.pos = Vec2::new(x.spawn_x, x.spawn_y);
-                ball.vel = Vec2::ZERO;
-            }
-        }
-    }
-
-    pub fn check_end_conditions(&mut self) -> bool {
+    /// Whether the running match has reached its score or time limit; the
+    /// caller ends it with `finish_match`.
+    pub fn check_end_conditions(&self) -> bool {
         if let MatchPhase::Playing {
             score_limit,
             time_limit_secs,
         } = &self.phase
         {
-            // Score limit checks (unchanged)
-            if let Some(limit) = score_limit {
-                if let Some(&s1) = self.scores.get(&Team::Team1) {
-                    if s1 >= *limit {
-                        self.phase = MatchPhase::Lobby;
-                        self.timer.pause();
-                        for p in self.players.values_mut() {
-                            p.status = PlayerStatus::Spectator;
-                        }
-                        return true;
-                    }
-                }
-                if let Some(&s2) = self.scores.get(&Team::Team2) {
-                    if s2 >= *limit {
-                        self.phase = MatchPhase::Lobby;
-                        self.timer.pause();
-                        for p in self.players.values_mut() {
-                            p.status = PlayerStatus::Spectator;
-                        }
-                        return true;
-                    }
-                }
+            if self.world.is_over(*score_limit) {
+                return true;
             }
 
             if let Some(secs) = time_limit_secs {
-                let elapsed_secs = self.timer.elapsed_secs();
-                if elapsed_secs >= *secs as f32 {
-                    self.phase = MatchPhase::Lobby;
-                    self.timer.pause();
-                    for p in self.players.values_mut() {
-                        p.status = PlayerStatus::Spectator;
-                    }
+                if self.timer.elapsed_secs() >= *secs as f32 {
                     return true;
                 }
             }
         }
         false
     }
-}
 
-enum GameModeRules {
-    CaptureTheFlag,
-    HoldTheFlag,
-    Football,
-    Fight,
-    KingOfTheHill,
-    Race,
-    HotPotato,
-    Shooter,
+    /// Events that happened since the last call, in order.
+    pub fn take_events(&mut self) -> Vec<GameEvent> {
+        std::mem::take(&mut self.world.events)
+    }
 }
 
-impl GameModeRules {
-    fn from_map_game_mode(mode: GameMode) -> Self {
-        match mode {
-            GameMode::Fight => Self::Fight,
-            GameMode::Football => Self::Football,
-            GameMode::Ctf => Self::CaptureTheFlag,
-            GameMode::Htf => Self::HoldTheFlag,
-            GameMode::KingOfTheHill => Self::KingOfTheHill,
-            GameMode::Race => Self::Race,
-            GameMode::HotPotato => Self::HotPotato,
-            GameMode::Shooter => Self::Shooter,
-        }
-    }
-
-    fn logic_step(&self, state: &mut GameState, delta: f32) {
-        match self {
-            GameModeRules::HoldTheFlag => {
-                if let Some((player, time)) = state.player_with_active_action.clone() {
-                    let mut new_time = time + delta;
-                    if new_time >= state.action_target_time.unwrap_or(10.0) {
-                        let team = state.get_team_of_player(&player).unwrap();
-                        *state.scores.entry(team).or_insert(0) += 1;
-                        new_time = 0.0;
-                    }
-                    state.player_with_active_action = Some((player.clone(), new_time));
-                }
-            }
-            GameModeRules::KingOfTheHill => {
-                if let Some((player, time)) = state.player_with_active_action.clone() {
-                    let new_time = time + delta;
-                    if new_time >= state.action_target_time.unwrap_or(10.0) {
-                        let team = state.get_team_of_player(&player).unwrap();
-                        *state.scores.entry(team).or_insert(0) += 1;
-                        state.player_with_active_action = None;
-                        state.reset_positions();
-                    } else {
-                        state.player_with_active_action = Some((player, new_time));
-                    }
-                }
-            }
-            GameModeRules::HotPotato => {
-                if let Some((placeholder, time)) = state.player_with_active_action.clone() {
-                    let new_time = time + delta;
-                    state.player_with_active_action = Some((placeholder, new_time));
-                } else {
-                    state.player_with_active_action = Some((Default::default(), 0.0));
-                }
-            }
-            _ => (),
-        }
+fn publish_events(peers: &PeerMap, events: Vec<GameEvent>) {
+    for event in events {
+        broadcast(peers, ServerMessage::GameEvent { event });
     }
-    fn handle_collisions_response(
-        &self,
-        response: &SimulateCollisionResponse,
-        state: &mut GameState,
-    ) {
-        match self {
-            GameModeRules::CaptureTheFlag => {
-                if let Some((player_id, team)) = &response.ball_touched_by_player {
-                    if state.player_with_active_action.is_none() {
-                        state.player_with_active_action = Some((player_id.to_string(), 0.0));
-                    }
-                }
-
-                let ball_spawn = Vec2::new(
-                    state.map.ball.clone().unwrap().spawn_x,
-                    state.map.ball.clone().unwrap().spawn_y,
-                );
-                for player_id in &response.players_hit_by_snowball {
-                    if let Some(ball) = &mut state.ball {
-                        if state.player_with_active_action.is_some() {
-                            state.player_with_active_action = None;
-                            ball.vel = Vec2::ZERO;
-                            ball.pos = ball_spawn;
-                        }
-                    }
-                }
-
-                if let (Some(goal_team), Some(ball)) =
-                    (response.ball_in_goal_of_team, state.ball.as_mut())
-                {
-                    if let Some((player_id, _)) = &state.player_with_active_action {
-                        if let Some(carrier_team) = state.get_team_of_player(&player_id) {
-                            if carrier_team == goal_team {
-                                *state.scores.entry(carrier_team).or_insert(0) += 1;
-                                state.reset_positions();
-                            }
-                        }
-                    }
-                }
-
-                if let Some(ball) = &mut state.ball {
-                    if let Some((player, _)) = &state.player_with_active_action {
-                        if let Some(player) = state.players.get(player) {
-                            ball.pos = player.pos;
-                            ball.vel = Vec2::ZERO;
-                        }
-                    }
-                }
-            }
-            GameModeRules::HoldTheFlag => {
-                if let Some((player_id, _)) = &response.ball_touched_by_player {
-                    if state.player_with_active_action.is_none() {
-                        state.player_with_active_action = Some((player_id.to_string(), 0.0));
-                    }
-                }
-
-                let ball_spawn = Vec2::new(
-                    state.map.ball.clone().unwrap().spawn_x,
-                    state.map.ball.clone().unwrap().spawn_y,
-                );
-                for hit_player_id in response.players_hit_by_snowball.clone() {
-                    if let Some(ball) = &mut state.ball {
-                        if let Some((carrying_player_id, _)) = &state.player_with_active_action {
-                            if *carrying_player_id == hit_player_id {
-                                state.player_with_active_action = None;
-                                ball.vel = Vec2::ZERO;
-                                ball.pos = ball_spawn;
-                            }
-                        }
-                    }
-                }
-
-                let carrier_pos = {
-                    if let Some((carrier_id, _)) = &state.player_with_active_action {
-                        state.players.get(carrier_id).map(|p| p.pos)
-                    } else {
-                        None
-                    }
-                };
-
-                if let (Some(pos), Some(ball)) = (carrier_pos, state.ball.as_mut()) {
-                    ball.pos = pos;
-                    ball.vel = Vec2::ZERO;
-                }
-            }
-            GameModeRules::Football => {
-                if let Some(scoring_team) = &response.ball_in_goal_of_team {
-                    *state.scores.entry(*scoring_team).or_insert(0) += 1;
-
-                    state.reset_positions();
-                }
-            }
-            GameModeRules::Fight => {
-                for id in response.players_in_holes.iter() {
-                    if state.players.values_mut().find(|x| x.id == *id).is_some() {
-                        state.reset_positions();
-                    }
-                    if let Some(team) = state.get_team_of_player(id) {
-                        for (other_id, score) in state.scores.iter_mut() {
-                            if *other_id != team {
-                                *score += 1;
-                            }
-                        }
-                    }
-                }
-            }
-            GameModeRules::KingOfTheHill => {
-                if let Some((king_id, _)) = &state.player_with_active_action {
-                    let still_in_hole = response.players_in_holes.iter().any(|id| id == king_id);
-
-                    if !still_in_hole {
-                        state.player_with_active_action = None;
-                    }
-                }
+}
 
-                if state.player_with_active_action.is_none() {
-                    if let Some(player_id) = response.players_in_holes.first() {
-                        state.player_with_active_action = Some((player_id.clone(), 0.0));
-                    }
-                }
-            }
-            GameModeRules::Race => {
-                if let Some(player_id) = response.players_in_holes.first() {
-                    let team = state.get_team_of_player(player_id).unwrap();
-                    *state.scores.entry(team).or_insert(0) += 1;
-                    state.reset_positions();
-                }
-            }
-            GameModeRules::HotPotato => {
-                if let Some((_placeholder, time)) = &state.player_with_active_action {
-                    if *time >= state.action_target_time.unwrap_or(10.0) {
-                        if let Some(goal_team) = response.ball_in_goal_of_team {
-                            *state.scores.entry(goal_team).or_insert(0) += 1;
-
-                            state.player_with_active_action = None;
-                            state.reset_positions();
-                        }
-                    }
-                }
-            }
-            GameModeRules::Shooter => {
-                for hit_player_id in &response.players_hit_by_snowball {
-                    if let Some(hit_team) = state.get_team_of_player(hit_player_id) {
-                        for (team, score) in state.scores.iter_mut() {
-                            if *team != hit_team {
-                                *score += 1;
-                            }
-                        }
-                    }
-                }
-            }
-        }
+/// Stops the match and tells everyone how it went, the summary after the
+/// events leading up to it. See `GameState::stop_match` for `rated`.
+fn finish_match(gs: &mut GameState, peers: &PeerMap, rated: bool) {
+    let summary = gs.stop_match(rated);
+    publish_events(peers, gs.take_events());
+    if let Some(summary) = summary {
+        broadcast(peers, ServerMessage::MatchSummary { summary });
     }
 }
 
-async fn physics_loop(game_state: Arc<Mutex<GameState>>, peers: PeerMap) {
-    let tick = Duration::from_secs_f32(DT);
+async fn physics_loop(room: Weak<Room>) {
+    let Some(dt) = room
+        .upgrade()
+        .map(|room| room.game_state.lock().unwrap().config.dt())
+    else {
+        return;
+    };
+    let tick = Duration::from_secs_f32(dt);
     let mut last = Instant::now();
 
     loop {
+        let Some(room) = room.upgrade() else {
+            return;
+        };
+        let game_state = &room.game_state;
+        let peers = &room.peers;
+
         let now = Instant::now();
         let elapsed = now.duration_since(last);
         if elapsed >= tick {
             {
                 let mut gs = game_state.lock().unwrap();
+                gs.tick += 1;
                 // if matches!(gs.phase, MatchPhase::Lobby) {
                 //     last = now;
                 //     continue;
                 // } else
                 if gs.paused {
-                    let (players, snowballs) = gs.snapshot();
-                    let world = WorldState {
-                        players,
-                        snowballs,
-                        ball: gs.ball.clone().map(|x| BallState {
-                            pos: x.pos.into(),
-                            vel: x.vel.into(),
-                        }),
-                        scores_team1: gs.scores[&Team::Team1].clone(),
-                        scores_team2: gs.scores[&Team::Team2].clone(),
-                        phase: gs.phase.clone(),
-                        time_elapsed: gs.timer.elapsed_secs(),
-                        paused: gs.paused,
-                        team1_color: gs.team1_color.clone(),
-                        team2_color: gs.team2_color.clone(),
-                        player_with_active_action: gs.player_with_active_action.clone(),
-                        game_mode: gs.game_mode,
-                        action_target_time: gs.action_target_time,
-                    };
-
-                    let peers_guard = peers.lock().unwrap();
-                    for client in peers_guard.values() {
-                        *client.latest_world.lock().unwrap() = Some(ServerMessage::WorldState {
-                            world: world.clone(),
-                        });
-                    }
+                    publish_events(peers, gs.take_events());
+                    let world = gs.snapshot();
+                    publish_world(peers, &world);
 
                     last = now;
                     continue;
@@ -736,57 +856,29 @@ async fn physics_loop(game_state: Arc<Mutex<GameState>>, peers: PeerMap) {
                 let phase = gs.phase.clone();
 
                 if let MatchPhase::Playing { .. } = phase {
-                    gs.logic_step(DT);
-                    GameModeRules::from_map_game_mode(gs.game_mode.clone()).logic_step(&mut gs, DT);
-                    simulate_movement(&mut gs, DT);
-                    let response = simulate_collisions(&mut gs);
-                    GameModeRules::from_map_game_mode(gs.game_mode.clone())
-                        .handle_collisions_response(&response, &mut gs);
-
-                    for sid in response.snowballs_in_holes.into_iter() {
-                        gs.snowballs.remove(&sid);
-                    }
+                    gs.run_bots(dt);
+                    gs.world.step(dt);
+                    gs.update_stats(dt);
+                    gs.record_tick();
 
                     if gs.check_end_conditions() {
-                        gs.stop_match();
+                        finish_match(&mut gs, peers, true);
+                        if gs.config.map_rotation && gs.rotate_map() {
+                            broadcast(
+                                peers,
+                                ServerMessage::Map {
+                                    map: gs.world.map.clone(),
+                                },
+                            );
+                        }
                     }
                 }
 
-                let map = gs.map.clone();
-                for p in gs.players.values_mut() {
-                    sanity_check_player(p, &map);
-                }
-
-                if let Some(ball) = &mut gs.ball {
-                    sanity_check_ball(ball, &map);
-                }
+                gs.world.sanity_check();
 
-                let (players, snowballs) = gs.snapshot();
-                let world = WorldState {
-                    players,
-                    snowballs,
-                    ball: gs.ball.clone().map(|x| BallState {
-                        pos: x.pos.into(),
-                        vel: x.vel.into(),
-                    }),
-                    scores_team1: gs.scores[&Team::Team1].clone(),
-                    scores_team2: gs.scores[&Team::Team2].clone(),
-                    phase: gs.phase.clone(),
-                    time_elapsed: gs.timer.elapsed_secs(),
-                    paused: gs.paused,
-                    team1_color: gs.team1_color.clone(),
-                    team2_color: gs.team2_color.clone(),
-                    player_with_active_action: gs.player_with_active_action.clone(),
-                    game_mode: gs.game_mode,
-                    action_target_time: gs.action_target_time,
-                };
-
-                let peers_guard = peers.lock().unwrap();
-                for client in peers_guard.values() {
-                    *client.latest_world.lock().unwrap() = Some(ServerMessage::WorldState {
-                        world: world.clone(),
-                    });
-                }
+                publish_events(peers, gs.take_events());
+                let world = gs.snapshot();
+                publish_world(peers, &world);
             }
             last = now;
         } else {
@@ -794,59 +886,3 @@ async fn physics_loop(game_state: Arc<Mutex<GameState>>, peers: PeerMap) {
         }
     }
 }
-
-#[inline]
-fn vec2_invalid(v: Vec2) -> bool {
-    !v.x.is_finite() || !v.y.is_finite()
-}
-
-#[inline]
-fn out_of_bounds(pos: Vec2, map: &GameMap) -> bool {
-    pos.x < 0.0 || pos.y < 0.0 || pos.x > map.width || pos.y > map.height
-}
-
-fn sanity_check_player(player: &mut Player, map: &GameMap) -> bool {
-    if vec2_invalid(player.pos)
-        || vec2_invalid(player.vel)
-        || player.vel.length() > 5000.0
-        || out_of_bounds(player.pos, map)
-    {
-        player.vel = Vec2::ZERO;
-        player.spin_timer = 0.0;
-        player.rotating_left = false;
-        player.rotating_right = false;
-
-        match player.status {
-            PlayerStatus::Playing(Team::Team1) => {
-                player.pos = Vec2::new(map.team1.spawn_x, map.team1.spawn_y);
-            }
-            PlayerStatus::Playing(Team::Team2) => {
-                player.pos = Vec2::new(map.team2.spawn_x, map.team2.spawn_y);
-            }
-            PlayerStatus::Spectator => {}
-        }
-
-        return true;
-    }
-
-    false
-}
-
-fn sanity_check_ball(ball: &mut Ball, map: &GameMap) -> bool {
-    if vec2_invalid(ball.pos)
-        || vec2_invalid(ball.vel)
-        || ball.vel.length() > 5000.0
-        || out_of_bounds(ball.pos, map)
-    {
-        if let Some(ball_def) = &map.ball {
-            ball.pos = Vec2::new(ball_def.spawn_x, ball_def.spawn_y);
-        } else {
-            ball.pos = Vec2::ZERO;
-        }
-
-        ball.vel = Vec2::ZERO;
-        return true;
-    }
-
-    false
-}
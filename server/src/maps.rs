@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use spin_snowball_shared::validation::{recommended_modes, validate_game_mode, validate_map};
+use spin_snowball_shared::*;
+
+/// Least time between two rescans asked for by clients.
+const RESCAN_INTERVAL: Duration = Duration::from_secs(10);
+
+struct LibraryMap {
+    path: PathBuf,
+    map: GameMap,
+}
+
+/// The playable maps of the configured map directory, by file name without
+/// extension. Files that do not parse or fail validation are left out.
+pub struct MapLibrary {
+    dir: Option<PathBuf>,
+    maps: BTreeMap<String, LibraryMap>,
+    scanned_at: Instant,
+}
+
+impl MapLibrary {
+    pub fn scan(dir: Option<PathBuf>) -> Self {
+        let mut library = Self {
+            dir,
+            maps: BTreeMap::new(),
+            scanned_at: Instant::now(),
+        };
+        library.refresh();
+        library
+    }
+
+    /// Re-reads the directory, picking up maps added or changed since the last scan.
+    pub fn refresh(&mut self) {
+        self.scanned_at = Instant::now();
+        self.maps.clear();
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                println!("Cannot read map directory {}: {}", dir.display(), e);
+                return;
+            }
+        };
+
+        for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            match read_map(&path) {
+                Ok(map) => {
+                    self.maps.insert(name.to_string(), LibraryMap { path, map });
+                }
+                Err(e) => println!("Skipping map {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    /// Rescans unless the last scan is recent. Scanning reads and validates every
+    /// file, so clients asking for the list over and over get the cached one.
+    pub fn refresh_if_stale(&mut self) {
+        if self.scanned_at.elapsed() >= RESCAN_INTERVAL {
+            self.refresh();
+        }
+    }
+
+    pub fn list(&self) -> Vec<MapInfo> {
+        self.maps
+            .iter()
+            .map(|(name, entry)| MapInfo {
+                name: name.clone(),
+                title: entry.map.name.clone(),
+                width: entry.map.width,
+                height: entry.map.height,
+                recommended_modes: recommended_modes(&entry.map),
+            })
+            .collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&GameMap> {
+        self.maps.get(name).map(|entry| &entry.map)
+    }
+
+    /// Name of the library map read from `path`, if it is one.
+    pub fn name_of(&self, path: &Path) -> Option<String> {
+        let path = fs::canonicalize(path).ok()?;
+        self.maps
+            .iter()
+            .find(|(_, entry)| fs::canonicalize(&entry.path).is_ok_and(|p| p == path))
+            .map(|(name, _)| name.clone())
+    }
+
+    /// The map after `current` in name order that can be played in `mode`,
+    /// wrapping around at the end. Never returns `current` itself.
+    pub fn next(&self, current: Option<&str>, mode: GameMode) -> Option<(String, GameMap)> {
+        let (after, before): (Vec<_>, Vec<_>) = self
+            .maps
+            .iter()
+            .filter(|(name, _)| Some(name.as_str()) != current)
+            .partition(|(name, _)| current.is_none_or(|current| name.as_str() > current));
+
+        after
+            .into_iter()
+            .chain(before)
+            .find(|(_, entry)| !validate_game_mode(&entry.map, mode).has_errors())
+            .map(|(name, entry)| (name.clone(), entry.map.clone()))
+    }
+}
+
+fn read_map(path: &Path) -> Result<GameMap, String> {
+    let data = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let map: GameMap = serde_json::from_str(&data).map_err(|e| e.to_string())?;
+    let report = validate_map(&map);
+    if report.has_errors() {
+        return Err(report.error_summary());
+    }
+    Ok(map)
+}
@@ -1,192 +1,846 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::{sync::{Arc, Mutex}, time::Duration};
 
 use futures::{SinkExt, StreamExt};
+use spin_snowball_shared::wire::{query_param, BinaryMessage, WireFormat, RESUME_QUERY_KEY};
 use spin_snowball_shared::*;
-use tokio::{net::TcpStream, sync::mpsc, time::sleep};
-use tokio_tungstenite::accept_async;
+use tokio::{
+    net::TcpStream,
+    sync::mpsc,
+    time::sleep,
+};
+use tokio_tungstenite::accept_hdr_async;
+use tungstenite::handshake::server::{Request, Response};
 use tungstenite::Message;
 use uuid::Uuid;
 
-use crate::{ClientOut, GameState, MatchPhase, PeerMap, PlayerStatus, Team};
+use crate::chat;
+use crate::room::{Room, Rooms};
+use crate::{broadcast, finish_match, ClientOut, MatchPhase, PlayerStatus, Team};
 
-pub async fn handle_connection(
-    stream: TcpStream,
-    peers: PeerMap,
-    game_state: Arc<Mutex<GameState>>,
-) {
-    let ws = accept_async(stream).await.unwrap();
-    let (mut ws_sender, mut ws_receiver) = ws.split();
+/// A full snapshot is sent at least this often, even if the client keeps acking.
+const KEYFRAME_INTERVAL: u32 = 60;
+/// How many sent snapshots are kept as potential delta bases.
+const SNAPSHOT_HISTORY: usize = 64;
 
-    let client_id = Uuid::new_v4().to_string();
-    println!("New client {}", client_id);
-    let (tx, mut rx) = mpsc::unbounded_channel::<ServerMessage>();
-    let latest_world = Arc::new(Mutex::new(None));
+static NEXT_CONNECTION: AtomicU64 = AtomicU64::new(1);
 
-    peers.lock().unwrap().insert(
-        client_id.clone(),
+/// One connection of a client and the session it is attached to.
+struct Session {
+    client_id: String,
+    token: String,
+    connection: u64,
+    tx: mpsc::UnboundedSender<ServerMessage>,
+    latest_world: Arc<Mutex<Option<WorldState>>>,
+}
+
+impl Session {
+    fn out(&self) -> ClientOut {
         ClientOut {
-            tx: tx.clone(),
-            latest_world: latest_world.clone(),
-        },
+            connection: self.connection,
+            tx: self.tx.clone(),
+            latest_world: self.latest_world.clone(),
+        }
+    }
+}
+
+/// Snapshots sent to one client, kept until they are too old to serve as a delta base.
+struct SnapshotHistory {
+    sent: VecDeque<WorldState>,
+    since_keyframe: u32,
+}
+
+impl SnapshotHistory {
+    fn new() -> Self {
+        Self {
+            sent: VecDeque::new(),
+            since_keyframe: 0,
+        }
+    }
+
+    /// Drops every delta base, so the next snapshot is a keyframe. Called whenever the
+    /// client is told about a new room or map; it clears its own history at that point.
+    fn reset(&mut self) {
+        self.sent.clear();
+        self.since_keyframe = 0;
+    }
+
+    /// Encodes `world` as a delta against the last acknowledged snapshot when possible,
+    /// falling back to a full keyframe.
+    fn encode(&mut self, world: WorldState, acked: Option<u64>) -> ServerMessage {
+        if let Some(acked) = acked {
+            // acks arrive in order, so older snapshots will never be used again
+            self.sent.retain(|w| w.seq >= acked);
+        }
+
+        let base = acked.and_then(|seq| self.sent.iter().find(|w| w.seq == seq));
+        let msg = match base {
+            Some(base) if self.since_keyframe < KEYFRAME_INTERVAL => {
+                self.since_keyframe += 1;
+                ServerMessage::WorldDelta {
+                    delta: world.delta_from(base),
+                }
+            }
+            _ => {
+                self.since_keyframe = 0;
+                ServerMessage::WorldState {
+                    world: world.clone(),
+                }
+            }
+        };
+
+        self.sent.push_back(world);
+        if self.sent.len() > SNAPSHOT_HISTORY {
+            self.sent.pop_front();
+        }
+        msg
+    }
+}
+
+fn encode_message(msg: &ServerMessage, format: WireFormat) -> Message {
+    match format {
+        WireFormat::Json => Message::Text(serde_json::to_string(msg).unwrap().into()),
+        WireFormat::Binary => Message::Binary(msg.to_binary().unwrap().into()),
+    }
+}
+
+/// Decodes by frame type rather than by the negotiated format, so a client can always
+/// fall back to JSON. Returns `None` for frames that carry no message (ping, close...).
+fn decode_message(msg: &Message) -> Option<Result<ClientMessage, String>> {
+    match msg {
+        Message::Text(txt) => Some(serde_json::from_str(txt).map_err(|e| e.to_string())),
+        Message::Binary(bytes) => Some(ClientMessage::from_binary(bytes).map_err(|e| e.to_string())),
+        _ => None,
+    }
+}
+
+/// Adds a new player for the session to the room and gives its map, or `None`
+/// when the room is full.
+fn enter_room(room: &Room, session: &Session) -> Option<GameMap> {
+    // checked under the same lock the player is added with, so racing joins
+    // cannot both take the last place
+    let mut gs = room.game_state.lock().unwrap();
+    if gs.is_full() {
+        return None;
+    }
+    gs.add_new_player(
+        session.client_id.clone(),
+        session.token.clone(),
+        session.connection,
+    );
+    room.peers
+        .lock()
+        .unwrap()
+        .insert(session.client_id.clone(), session.out());
+    Some(gs.world.map.clone())
+}
+
+/// Attaches the connection to a player that is already in the room. A previous
+/// connection that has not noticed yet that it dropped is superseded.
+fn resume_room(room: &Room, session: &Session) -> Option<GameMap> {
+    let mut gs = room.game_state.lock().unwrap();
+    gs.attach(&session.client_id, session.connection)?;
+    room.peers
+        .lock()
+        .unwrap()
+        .insert(session.client_id.clone(), session.out());
+    Some(gs.world.map.clone())
+}
+
+/// Reattaches the connection of `session` to the player `token` belongs to,
+/// giving up the player the connection started with.
+fn resume_session(
+    rooms: &Rooms,
+    current: &mut Arc<Room>,
+    session: &mut Session,
+    token: String,
+) -> Result<(), CommandError> {
+    if token == session.token {
+        return Ok(());
+    }
+    let expired = || {
+        CommandError::new(
+            ErrorCode::SessionExpired,
+            "Your previous session has expired",
+        )
+    };
+    let (target, client_id) = rooms.find_session(&token).ok_or_else(expired)?;
+    let resumed = Session {
+        client_id,
+        token,
+        connection: session.connection,
+        tx: session.tx.clone(),
+        latest_world: session.latest_world.clone(),
+    };
+    let map = resume_room(&target, &resumed).ok_or_else(expired)?;
+    leave_room(rooms, current, &session.client_id);
+    session.latest_world.lock().unwrap().take();
+    println!(
+        "Client {} resumed as {}",
+        session.client_id, resumed.client_id
+    );
+
+    let tx = &session.tx;
+    let _ = tx.send(ServerMessage::AssignId {
+        id: resumed.client_id.clone(),
+        token: resumed.token.clone(),
+    });
+    let _ = tx.send(ServerMessage::RoomJoined {
+        name: target.name.clone(),
+    });
+    let _ = tx.send(ServerMessage::Map { map });
+    *session = resumed;
+    *current = target;
+    Ok(())
+}
+
+fn leave_room(rooms: &Rooms, room: &Room, client_id: &str) {
+    room.peers.lock().unwrap().remove(client_id);
+    {
+        let mut gs = room.game_state.lock().unwrap();
+        gs.remove_player(client_id);
+    }
+    rooms.remove_if_empty(&room.name);
+}
+
+/// Detaches a closed connection. Its player stays in the room, idle, for the
+/// reconnect grace period, unless the session was already resumed elsewhere.
+fn disconnect(rooms: Arc<Rooms>, room: Arc<Room>, session: &Session) {
+    {
+        let mut peers = room.peers.lock().unwrap();
+        if peers
+            .get(&session.client_id)
+            .is_some_and(|p| p.connection == session.connection)
+        {
+            peers.remove(&session.client_id);
+        }
+    }
+
+    let grace = rooms.config().reconnect_grace_secs;
+    {
+        let mut gs = room.game_state.lock().unwrap();
+        if !gs.is_attached(&session.client_id, session.connection) {
+            return;
+        }
+        if grace > 0 {
+            gs.apply_input(&session.client_id, false, false, false);
+        }
+    }
+    if grace == 0 {
+        leave_room(&rooms, &room, &session.client_id);
+        return;
+    }
+
+    println!(
+        "Client {} dropped, keeping its player for {}s",
+        session.client_id, grace
     );
+    let client_id = session.client_id.clone();
+    let connection = session.connection;
+    tokio::spawn(async move {
+        sleep(Duration::from_secs(grace as u64)).await;
+        let expired = room
+            .game_state
+            .lock()
+            .unwrap()
+            .is_attached(&client_id, connection);
+        if expired {
+            println!("Session of {} expired", client_id);
+            leave_room(&rooms, &room, &client_id);
+        }
+    });
+}
 
-    let map = {
-        let mut gs = game_state.lock().unwrap();
-        gs.add_new_player(client_id.clone());
-        gs.map.clone()
+/// Moves the client into `target`, sending it the new room's map. The stale world
+/// of the previous room is dropped so it never reaches the client after the switch.
+fn switch_room(
+    rooms: &Rooms,
+    current: &mut Arc<Room>,
+    target: Arc<Room>,
+    session: &Session,
+) -> Result<(), CommandError> {
+    if Arc::ptr_eq(current, &target) {
+        return Ok(());
+    }
+    let map = match rooms.enter(&target, |target| enter_room(target, session)) {
+        Some(Some(map)) => map,
+        Some(None) => {
+            return Err(CommandError::new(
+                ErrorCode::RoomFull,
+                format!("Room {} is full", target.name),
+            ))
+        }
+        None => {
+            return Err(CommandError::new(
+                ErrorCode::NotFound,
+                format!("Room {} does not exist", target.name),
+            ))
+        }
     };
+    leave_room(rooms, current, &session.client_id);
+    session.latest_world.lock().unwrap().take();
+    println!("Client {} joined room {}", session.client_id, target.name);
+
+    let tx = &session.tx;
+    let _ = tx.send(ServerMessage::RoomJoined {
+        name: target.name.clone(),
+    });
+    let _ = tx.send(ServerMessage::Map { map });
+    let _ = tx.send(ServerMessage::RoomList {
+        rooms: rooms.list(),
+    });
+    *current = target;
+    Ok(())
+}
+
+/// Why a command was rejected, sent back to its sender as `ServerMessage::Error`.
+struct CommandError {
+    code: ErrorCode,
+    message: String,
+}
+
+impl CommandError {
+    fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Sends a chat message of `session` to everyone on `channel` in its room,
+/// including the sender.
+fn handle_chat(
+    room: &Room,
+    session: &Session,
+    channel: ChatChannel,
+    text: &str,
+) -> Result<(), CommandError> {
+    let text =
+        chat::clean_message(text).map_err(|e| CommandError::new(ErrorCode::InvalidArgument, e))?;
 
-    let assign = ServerMessage::AssignId {
-        id: client_id.clone(),
+    let mut gs = room.game_state.lock().unwrap();
+    let allowed = gs
+        .members
+        .get_mut(&session.client_id)
+        .is_some_and(|m| m.chat.try_take());
+    if !allowed {
+        return Err(CommandError::new(
+            ErrorCode::RateLimited,
+            "You are sending messages too fast",
+        ));
+    }
+    let Some(sender) = gs.world.players.get(&session.client_id) else {
+        return Ok(());
+    };
+    let msg = ServerMessage::Chat {
+        from: session.client_id.clone(),
+        nick: sender.nick.clone(),
+        channel,
+        text,
     };
-    ws_sender
-        .send(Message::Text(
-            serde_json::to_string(&assign).unwrap().into(),
-        ))
-        .await
-        .unwrap();
-
-    let map = ServerMessage::Map { map };
-    ws_sender
-        .send(Message::Text(serde_json::to_string(&map).unwrap().into()))
-        .await
-        .unwrap();
-    println!("just sent map");
+    let peers = room.peers.lock().unwrap();
+    for (id, client) in peers.iter() {
+        let receives = match channel {
+            ChatChannel::All => true,
+            ChatChannel::Team => gs
+                .world
+                .players
+                .get(id)
+                .is_some_and(|p| p.status == sender.status),
+        };
+        if receives {
+            let _ = client.tx.send(msg.clone());
+        }
+    }
+    Ok(())
+}
+
+/// Longest nick a player can choose, in characters.
+const MAX_NICK_LEN: usize = 24;
+
+/// Carries out one command of `client_id`, which is currently in `room`.
+fn handle_command(
+    cmd: Command,
+    rooms: &Rooms,
+    room: &mut Arc<Room>,
+    session: &Session,
+) -> Result<(), CommandError> {
+    let client_id = session.client_id.as_str();
+    let tx = &session.tx;
+    let peers = &room.peers;
+    let mut gs = room.game_state.lock().unwrap();
+
+    let required = cmd.required_role();
+    if gs.role_of(client_id) < required {
+        return Err(CommandError::new(
+            ErrorCode::PermissionDenied,
+            format!(
+                "Only the {} can do that",
+                if required == PlayerRole::Host {
+                    "host"
+                } else {
+                    "host or an admin"
+                }
+            ),
+        ));
+    }
 
+    let playing = matches!(gs.phase, MatchPhase::Playing { .. });
+    match cmd {
+        Command::Start {
+            score_limit,
+            time_limit_secs,
+        } => {
+            if playing {
+                return Err(CommandError::new(
+                    ErrorCode::WrongPhase,
+                    "A match is already running",
+                ));
+            }
+            if !gs
+                .world
+                .players
+                .values()
+                .any(|player| player.status != PlayerStatus::Spectator)
+            {
+                return Err(CommandError::new(
+                    ErrorCode::NoPlayers,
+                    "Nobody belongs to a team - cannot start a match",
+                ));
+            }
+            gs.start_match(score_limit, time_limit_secs);
+        }
+        Command::Pause | Command::Resume | Command::Stop if !playing => {
+            return Err(CommandError::new(
+                ErrorCode::WrongPhase,
+                "No match is running",
+            ));
+        }
+        Command::Pause => {
+            gs.pause_match();
+        }
+        Command::Resume => {
+            gs.resume_match();
+        }
+        Command::Stop => {
+            finish_match(&mut gs, peers, false);
+        }
+        // replays keep the map and physics of the match start
+        Command::LoadMap { .. } | Command::SelectMap { .. } | Command::SetPhysicsSettings { .. }
+            if playing =>
+        {
+            return Err(CommandError::new(
+                ErrorCode::WrongPhase,
+                "The map and physics can only be changed between matches",
+            ));
+        }
+        Command::LoadMap { data } => {
+            gs.load_map(&data)
+                .map_err(|e| CommandError::new(ErrorCode::InvalidMap, e))?;
+            broadcast(
+                peers,
+                ServerMessage::Map {
+                    map: gs.world.map.clone(),
+                },
+            );
+        }
+        Command::JoinAsPlayer { team } => {
+            gs.join_team(client_id, team)
+                .map_err(|e| CommandError::new(ErrorCode::TeamFull, e))?;
+        }
+        Command::JoinAsSpectator => {
+            if let Some(p) = gs.world.players.get_mut(client_id) {
+                p.status = PlayerStatus::Spectator;
+            }
+        }
+        Command::SetNick { nick } => {
+            let nick = nick.trim();
+            if nick.is_empty() || nick.chars().count() > MAX_NICK_LEN {
+                return Err(CommandError::new(
+                    ErrorCode::InvalidArgument,
+                    format!("A nick must have 1 to {} characters", MAX_NICK_LEN),
+                ));
+            }
+            if let Some(p) = gs.world.players.get_mut(client_id) {
+                p.nick = nick.to_string();
+            }
+            gs.named.insert(client_id.to_string());
+        }
+        Command::SetColorDef { color, team } => match team {
+            Team::Team1 => gs.team1_color = color,
+            Team::Team2 => gs.team2_color = color,
+        },
+        Command::SetPhysicsSettings { settings } => {
+            gs.set_physics(settings.clone())
+                .map_err(|e| CommandError::new(ErrorCode::InvalidArgument, e))?;
+            let peers_guard = peers.lock().unwrap();
+            for (_id, tx) in peers_guard.iter() {
+                let _ = tx.tx.send(ServerMessage::PhysicsSettings {
+                    settings: settings.clone(),
+                });
+            }
+        }
+        Command::SetGameMode {
+            game_mode,
+            action_target_time,
+        } => {
+            if action_target_time.is_some_and(|t| !t.is_finite() || t <= 0.0) {
+                return Err(CommandError::new(
+                    ErrorCode::InvalidArgument,
+                    "The target time must be a positive number of seconds",
+                ));
+            }
+            gs.set_game_mode(game_mode, action_target_time)
+                .map_err(|e| CommandError::new(ErrorCode::InvalidMap, e))?;
+        }
+        Command::CreateRoom {
+            name,
+            host_password,
+        } => {
+            drop(gs);
+            let name = name.trim().to_string();
+            let host_password = host_password.filter(|p| !p.is_empty());
+            if name.is_empty() {
+                return Err(CommandError::new(
+                    ErrorCode::InvalidArgument,
+                    "Cannot create a room without a name",
+                ));
+            }
+            let Some(target) = rooms.create(&name, host_password) else {
+                return Err(CommandError::new(
+                    ErrorCode::AlreadyExists,
+                    format!("Room {} already exists", name),
+                ));
+            };
+            switch_room(rooms, room, target, session)?;
+        }
+        Command::ListRooms => {
+            drop(gs);
+            let _ = tx.send(ServerMessage::RoomList {
+                rooms: rooms.list(),
+            });
+        }
+        Command::JoinRoom { name } => {
+            drop(gs);
+            let Some(target) = rooms.get(&name) else {
+                return Err(CommandError::new(
+                    ErrorCode::NotFound,
+                    format!("Room {} does not exist", name),
+                ));
+            };
+            switch_room(rooms, room, target, session)?;
+        }
+        Command::LeaveRoom => {
+            drop(gs);
+            let target = rooms.default_room();
+            switch_room(rooms, room, target, session)?;
+        }
+        Command::ClaimHost { password } => {
+            if !gs.claim_host(client_id, &password) {
+                return Err(CommandError::new(
+                    ErrorCode::WrongPassword,
+                    "Wrong host password",
+                ));
+            }
+        }
+        Command::SetAdmin { player_id, admin } => {
+            if !gs.world.players.contains_key(&player_id) {
+                return Err(CommandError::new(ErrorCode::NotFound, "No such player"));
+            } else if gs.bots.contains_key(&player_id) {
+                return Err(CommandError::new(
+                    ErrorCode::InvalidArgument,
+                    "Bots cannot be admins",
+                ));
+            } else if gs.host.as_deref() == Some(player_id.as_str()) {
+                return Err(CommandError::new(
+                    ErrorCode::InvalidArgument,
+                    "The host is always an admin",
+                ));
+            } else if admin {
+                gs.admins.insert(player_id);
+            } else {
+                gs.admins.remove(&player_id);
+            }
+        }
+        Command::AddBot { team, difficulty } => {
+            if gs.is_full() {
+                return Err(CommandError::new(ErrorCode::RoomFull, "The room is full"));
+            }
+            gs.check_team_size(team, "")
+                .map_err(|e| CommandError::new(ErrorCode::TeamFull, e))?;
+            gs.add_bot(team, difficulty);
+        }
+        Command::RemoveBot { player_id } => {
+            if !gs.remove_bot(&player_id) {
+                return Err(CommandError::new(ErrorCode::NotFound, "No such bot"));
+            }
+        }
+        Command::ListMaps => {
+            drop(gs);
+            let mut maps = rooms.maps().lock().unwrap();
+            maps.refresh_if_stale();
+            let _ = tx.send(ServerMessage::MapList { maps: maps.list() });
+        }
+        Command::ShuffleTeams { by_rating } => {
+            if !matches!(gs.phase, MatchPhase::Lobby) {
+                return Err(CommandError::new(
+                    ErrorCode::WrongPhase,
+                    "Teams can only be shuffled between matches",
+                ));
+            }
+            gs.shuffle_teams(by_rating);
+        }
+        Command::GetLeaderboard => {
+            drop(gs);
+            let entries = rooms.ratings().lock().unwrap().leaderboard();
+            let _ = tx.send(ServerMessage::Leaderboard { entries });
+        }
+        Command::SetScript { source } => {
+            gs.set_script(source)
+                .map_err(|e| CommandError::new(ErrorCode::InvalidScript, e))?;
+        }
+        Command::SelectMap { name } => {
+            let Some(map) = gs.maps.lock().unwrap().get(&name).cloned() else {
+                return Err(CommandError::new(
+                    ErrorCode::NotFound,
+                    format!("No map named {}", name),
+                ));
+            };
+            gs.set_map(map, Some(name))
+                .map_err(|e| CommandError::new(ErrorCode::InvalidMap, e))?;
+            broadcast(
+                peers,
+                ServerMessage::Map {
+                    map: gs.world.map.clone(),
+                },
+            );
+        }
+    }
+    Ok(())
+}
+
+pub async fn handle_connection(stream: TcpStream, rooms: Arc<Rooms>) {
+    let mut format = WireFormat::Json;
+    // a returning client names its session in the handshake
+    let mut resume_token = None;
+    #[allow(
+        clippy::result_large_err,
+        reason = "tungstenite fixes the handshake callback's error type"
+    )]
+    let negotiate = |req: &Request, resp: Response| {
+        format = WireFormat::from_query(req.uri().query());
+        resume_token = query_param(req.uri().query(), RESUME_QUERY_KEY).map(str::to_string);
+        Ok(resp)
+    };
+    let ws = match accept_hdr_async(stream, negotiate).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            println!("WebSocket handshake failed: {}", e);
+            return;
+        }
+    };
+    let (mut ws_sender, mut ws_receiver) = ws.split();
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<ServerMessage>();
+    let latest_world = Arc::new(Mutex::new(None));
+    let connection = NEXT_CONNECTION.fetch_add(1, Ordering::Relaxed);
+
+    let mut greeting = Vec::new();
+    let mut resumed = None;
+    if let Some(token) = resume_token {
+        if let Some((room, client_id)) = rooms.find_session(&token) {
+            let session = Session {
+                client_id,
+                token,
+                connection,
+                tx: tx.clone(),
+                latest_world: latest_world.clone(),
+            };
+            if let Some(map) = resume_room(&room, &session) {
+                println!("Client {} resumed ({})", session.client_id, format.as_str());
+                resumed = Some((session, room, map));
+            }
+        }
+        if resumed.is_none() {
+            greeting.push(ServerMessage::Error {
+                code: ErrorCode::SessionExpired,
+                message: "Your previous session has expired".to_string(),
+            });
+        }
+    }
+
+    let (mut session, mut room, map) = match resumed {
+        Some(resumed) => resumed,
+        None => {
+            let session = Session {
+                client_id: Uuid::new_v4().to_string(),
+                token: Uuid::new_v4().to_string(),
+                connection,
+                tx: tx.clone(),
+                latest_world: latest_world.clone(),
+            };
+            println!("New client {} ({})", session.client_id, format.as_str());
+
+            let room = rooms.default_room();
+            let Some(map) = enter_room(&room, &session) else {
+                let full = ServerMessage::Error {
+                    code: ErrorCode::RoomFull,
+                    message: format!(
+                        "Room {} is full ({} players per room)",
+                        room.name,
+                        rooms.config().max_players
+                    ),
+                };
+                let _ = ws_sender.send(encode_message(&full, format)).await;
+                println!(
+                    "Client {} turned away, {} is full",
+                    session.client_id, room.name
+                );
+                return;
+            };
+            (session, room, map)
+        }
+    };
+
+    greeting.extend([
+        ServerMessage::AssignId {
+            id: session.client_id.clone(),
+            token: session.token.clone(),
+        },
+        ServerMessage::ServerInfo {
+            name: rooms.config().name.clone(),
+            tick_rate: rooms.config().tick_rate,
+        },
+        ServerMessage::Map { map },
+        ServerMessage::RoomJoined {
+            name: room.name.clone(),
+        },
+    ]);
+    for msg in &greeting {
+        if ws_sender.send(encode_message(msg, format)).await.is_err() {
+            println!("Client {} disconnected", session.client_id);
+            disconnect(rooms.clone(), room.clone(), &session);
+            return;
+        }
+    }
+
+    // 0 means nothing acknowledged yet; snapshot sequence numbers start at 1
+    let acked_seq = AtomicU64::new(0);
+    let send_interval = Duration::from_secs_f32(1.0 / rooms.config().snapshot_rate);
     let forward_out = async {
+        let mut history = SnapshotHistory::new();
         loop {
+            // ---- reliable messages ----
+            // drained before the world, so a room or map change queued in the same
+            // wakeup resets the delta bases before the new room's first snapshot
+            while let Ok(msg) = rx.try_recv() {
+                if matches!(msg, ServerMessage::RoomJoined { .. } | ServerMessage::Map { .. }) {
+                    history.reset();
+                }
+                if ws_sender.send(encode_message(&msg, format)).await.is_err() {
+                    continue;
+                }
+            }
+
             // ---- take world state WITHOUT holding lock across await ----
             let world_msg = {
                 let mut guard = latest_world.lock().unwrap();
                 guard.take()
             };
 
-            if let Some(msg) = world_msg {
-                let txt = serde_json::to_string(&msg).unwrap();
-                if ws_sender.send(Message::Text(txt.into())).await.is_err() {
+            if let Some(world) = world_msg {
+                let acked = Some(acked_seq.load(Ordering::Relaxed)).filter(|&seq| seq != 0);
+                let msg = history.encode(world, acked);
+                if ws_sender.send(encode_message(&msg, format)).await.is_err() {
                     continue;
                 }
             }
-
-            // ---- reliable messages ----
-            while let Ok(msg) = rx.try_recv() {
-                let txt = serde_json::to_string(&msg).unwrap();
-                if ws_sender.send(Message::Text(txt.into())).await.is_err() {
-                    continue;
-                }
-            }
-            sleep(Duration::from_millis(16)).await;
+            sleep(send_interval).await;
         }
     };
 
-    let peers_clone = peers.clone();
-    let game_state_clone = game_state.clone();
-    let client_id_clone = client_id.clone();
+    let room_ref = &mut room;
+    let session_ref = &mut session;
     let inbound = async {
         while let Some(Ok(msg)) = ws_receiver.next().await {
-            if let Message::Text(txt) = msg {
-                match serde_json::from_str::<ClientMessage>(&txt) {
-                    Ok(ClientMessage::Input { left, right, shoot }) => {
+            if let Some(parsed) = decode_message(&msg) {
+                let room = &mut *room_ref;
+                match parsed {
+                    Ok(ClientMessage::Input {
+                        seq,
+                        left,
+                        right,
+                        shoot,
+                    }) => {
                         // update player's input snapshot in game state
-                        let mut gs = game_state_clone.lock().unwrap();
+                        let mut gs = room.game_state.lock().unwrap();
+                        // acknowledge even ignored inputs so the client can stop replaying them
+                        if let Some(m) = gs.members.get_mut(&session_ref.client_id) {
+                            m.last_input_seq = seq;
+                        }
                         if let MatchPhase::Playing {
                             score_limit: _,
                             time_limit_secs: _,
                         } = gs.phase
                         {
-                            gs.apply_input(&client_id_clone, left, right, shoot);
+                            gs.apply_input(&session_ref.client_id, left, right, shoot);
                         }
                     }
+                    Ok(ClientMessage::Ack { seq }) => {
+                        acked_seq.fetch_max(seq, Ordering::Relaxed);
+                    }
                     Ok(ClientMessage::Ping { ts }) => {
                         // reply Pong
-                        if let Some(tx) = peers_clone.lock().unwrap().get(&client_id_clone) {
-                            let _ = tx.tx.send(ServerMessage::Pong { ts });
-                        }
+                        let _ = tx.send(ServerMessage::Pong { ts });
                     }
                     Ok(ClientMessage::Command { cmd }) => {
-                        let mut gs = game_state_clone.lock().unwrap();
-                        match cmd {
-                            Command::Start {
-                                score_limit,
-                                time_limit_secs,
-                            } => {
-                                match gs.phase {
-                                    MatchPhase::Lobby => {
-                                        if gs.players.iter().any(|(_, player)| {
-                                            player.status != PlayerStatus::Spectator
-                                        }) {
-                                            gs.start_match(score_limit, time_limit_secs);
-                                        } else {
-                                            println!(
-                                                "Noone belongs to any team - cannot start a match"
-                                            );
-                                        }
-                                    }
-                                    MatchPhase::Playing { .. } => {
-                                        // already playing; optionally send a message back
-                                    }
-                                }
-                            }
-                            Command::Pause => {
-                                gs.pause_match();
-                            }
-                            Command::Resume => {
-                                gs.resume_match();
-                            }
-                            Command::Stop => {
-                                gs.stop_match();
-                            }
-                            Command::LoadMap { data } => {
-                                println!("got map");
-                                gs.load_map(&data);
-                                let peers_guard = peers.lock().unwrap();
-                                for (_id, tx) in peers_guard.iter() {
-                                    println!("SENDIN");
-                                    let _ = tx.tx.send(ServerMessage::Map {
-                                        map: gs.map.clone(),
-                                    });
-                                }
-                            }
-                            Command::JoinAsPlayer { team } => {
-                                if let Some(p) = gs.players.get_mut(&client_id_clone) {
-                                    println!("got join team");
-                                    p.status = PlayerStatus::Playing(team);
-                                }
-                            }
-                            Command::JoinAsSpectator => {
-                                if let Some(p) = gs.players.get_mut(&client_id_clone) {
-                                    p.status = PlayerStatus::Spectator;
-                                }
-                            }
-                            Command::SetNick { nick } => {
-                                if let Some(p) = gs.players.get_mut(&client_id_clone) {
-                                    p.nick = nick;
-                                }
-                            }
-                            Command::SetColorDef { color, team } => match team {
-                                Team::Team1 => gs.team1_color = color,
-                                Team::Team2 => gs.team2_color = color,
+                        let command = cmd.name();
+                        let reply = match handle_command(cmd, &rooms, room, session_ref) {
+                            Ok(()) => ServerMessage::CommandAck {
+                                command: command.to_string(),
                             },
-                            Command::SetPhysicsSettings { settings } => {
-                                gs.map.physics = settings.clone();
-                                let peers_guard = peers.lock().unwrap();
-                                for (_id, tx) in peers_guard.iter() {
-                                    let _ = tx.tx.send(ServerMessage::PhysicsSettings {
-                                        settings: settings.clone(),
-                                    });
+                            Err(e) => {
+                                println!(
+                                    "Rejected {} from {}: {}",
+                                    command, session_ref.client_id, e.message
+                                );
+                                ServerMessage::Error {
+                                    code: e.code,
+                                    message: e.message,
                                 }
                             }
-                            Command::SetGameMode {
-                                game_mode,
-                                action_target_time,
-                            } => {
-                                gs.game_mode = game_mode;
-                                gs.action_target_time = action_target_time;
-                            }
+                        };
+                        let _ = tx.send(reply);
+                    }
+                    Ok(ClientMessage::Resume { token }) => {
+                        if let Err(e) = resume_session(&rooms, room, session_ref, token) {
+                            let _ = tx.send(ServerMessage::Error {
+                                code: e.code,
+                                message: e.message,
+                            });
+                        }
+                    }
+                    Ok(ClientMessage::Chat { channel, text }) => {
+                        if let Err(e) = handle_chat(room, session_ref, channel, &text) {
+                            // the text stays private, only the rejection is logged
+                            println!(
+                                "Rejected chat from {}: {}",
+                                session_ref.client_id, e.message
+                            );
+                            let _ = tx.send(ServerMessage::Error {
+                                code: e.code,
+                                message: e.message,
+                            });
                         }
                     }
                     Err(e) => {
                         println!("Malformed client msg: {e}");
+                        let _ = tx.send(ServerMessage::Error {
+                            code: ErrorCode::MalformedMessage,
+                            message: format!("Malformed message: {}", e),
+                        });
                     }
                 }
             }
@@ -198,10 +852,6 @@ pub async fn handle_connection(
         res = inbound => { let _ = res; },
     }
 
-    println!("Client {} disconnected", client_id);
-    peers.lock().unwrap().remove(&client_id);
-    {
-        let mut gs = game_state.lock().unwrap();
-        gs.remove_player(&client_id);
-    }
+    println!("Client {} disconnected", session.client_id);
+    disconnect(rooms, room, &session);
 }
@@ -0,0 +1,176 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+use spin_snowball_shared::*;
+
+/// Rating a nick starts with.
+const INITIAL_RATING: f32 = 1000.0;
+/// Most a single match can move a rating.
+const K_FACTOR: f32 = 32.0;
+/// Players listed in answer to `Command::GetLeaderboard`.
+const LEADERBOARD_SIZE: usize = 50;
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Record {
+    rating: f32,
+    matches: u32,
+    wins: u32,
+    losses: u32,
+    draws: u32,
+}
+
+impl Default for Record {
+    fn default() -> Self {
+        Self {
+            rating: INITIAL_RATING,
+            matches: 0,
+            wins: 0,
+            losses: 0,
+            draws: 0,
+        }
+    }
+}
+
+/// Elo ratings of everyone who played a rated match, by nick. With a file they
+/// survive restarts, otherwise they last as long as the server runs.
+pub struct Ratings {
+    players: BTreeMap<String, Record>,
+    /// Hands the serialized ratings to the thread that writes the file, so
+    /// matches ending in a room never wait for the disk.
+    writer: Option<Sender<String>>,
+}
+
+impl Ratings {
+    /// Reads the ratings file; a file that does not exist yet is an empty ladder.
+    pub fn load(path: Option<PathBuf>) -> Result<Self, String> {
+        let players = match &path {
+            Some(path) if path.exists() => {
+                let data = fs::read_to_string(path)
+                    .map_err(|e| format!("cannot read ratings {}: {}", path.display(), e))?;
+                serde_json::from_str(&data)
+                    .map_err(|e| format!("invalid ratings {}: {}", path.display(), e))?
+            }
+            _ => BTreeMap::new(),
+        };
+        let writer = path.map(|path| {
+            let (tx, rx) = mpsc::channel::<String>();
+            thread::spawn(move || {
+                while let Ok(mut data) = rx.recv() {
+                    // only the latest ratings are worth writing
+                    while let Ok(newer) = rx.try_recv() {
+                        data = newer;
+                    }
+                    write(&path, &data);
+                }
+            });
+            tx
+        });
+        Ok(Self { players, writer })
+    }
+
+    pub fn rating(&self, nick: &str) -> f32 {
+        self.players
+            .get(nick)
+            .map_or(INITIAL_RATING, |record| record.rating)
+    }
+
+    /// Rates a finished match as one game between the average ratings of its
+    /// teams. Only the players in `named`, who chose their nick, are rated;
+    /// bots never are, and a match without rated people on both sides does
+    /// not count.
+    pub fn record(&mut self, summary: &MatchSummary, named: &HashSet<String>) {
+        let mut teams: [Vec<&str>; 2] = [vec![], vec![]];
+        for player in summary
+            .players
+            .iter()
+            .filter(|p| !p.is_bot && named.contains(&p.id))
+        {
+            let nick = player.nick.as_str();
+            // two players may share a nick, it is still one rating
+            if teams.iter().any(|team| team.contains(&nick)) {
+                continue;
+            }
+            match player.team {
+                Team::Team1 => teams[0].push(nick),
+                Team::Team2 => teams[1].push(nick),
+            }
+        }
+        if teams.iter().any(|team| team.is_empty()) {
+            return;
+        }
+
+        let average = |team: &[&str]| {
+            team.iter().map(|nick| self.rating(nick)).sum::<f32>() / team.len() as f32
+        };
+        let expected1 = 1.0 / (1.0 + 10f32.powf((average(&teams[1]) - average(&teams[0])) / 400.0));
+        let score1 = match summary.winner {
+            Some(Team::Team1) => 1.0,
+            Some(Team::Team2) => 0.0,
+            None => 0.5,
+        };
+        let change1 = K_FACTOR * (score1 - expected1);
+
+        for (team, change, score) in [
+            (&teams[0], change1, score1),
+            (&teams[1], -change1, 1.0 - score1),
+        ] {
+            for nick in team {
+                let record = self.players.entry(nick.to_string()).or_default();
+                record.rating += change;
+                record.matches += 1;
+                if score == 1.0 {
+                    record.wins += 1;
+                } else if score == 0.0 {
+                    record.losses += 1;
+                } else {
+                    record.draws += 1;
+                }
+            }
+        }
+        self.save();
+    }
+
+    /// The best rated players, highest first.
+    pub fn leaderboard(&self) -> Vec<LeaderboardEntry> {
+        let mut entries: Vec<LeaderboardEntry> = self
+            .players
+            .iter()
+            .map(|(nick, record)| LeaderboardEntry {
+                nick: nick.clone(),
+                rating: record.rating,
+                matches: record.matches,
+                wins: record.wins,
+                losses: record.losses,
+                draws: record.draws,
+            })
+            .collect();
+        entries.sort_by(|a, b| b.rating.total_cmp(&a.rating));
+        entries.truncate(LEADERBOARD_SIZE);
+        entries
+    }
+
+    fn save(&self) {
+        let Some(writer) = &self.writer else {
+            return;
+        };
+        match serde_json::to_string_pretty(&self.players) {
+            Ok(data) => {
+                let _ = writer.send(data);
+            }
+            Err(e) => println!("Failed to serialize ratings: {}", e),
+        }
+    }
+}
+
+fn write(path: &Path, data: &str) {
+    // write next to the file first, so a crash never leaves half a ladder behind
+    let tmp = path.with_extension("tmp");
+    let result = fs::write(&tmp, data).and_then(|()| fs::rename(&tmp, path));
+    if let Err(e) = result {
+        println!("Failed to save ratings {}: {}", path.display(), e);
+    }
+}
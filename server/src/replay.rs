@@ -0,0 +1,101 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use spin_snowball_shared::replay::*;
+use spin_snowball_shared::*;
+
+/// Longest match a replay covers; a longer match keeps only its beginning.
+const MAX_REPLAY_SECS: f32 = 60.0 * 60.0;
+
+/// Collects the ticks of one match and writes them to a replay file when it ends.
+pub struct ReplayRecorder {
+    dir: PathBuf,
+    replay: Replay,
+    last: WorldState,
+    inputs: Vec<ReplayInput>,
+}
+
+impl ReplayRecorder {
+    pub fn new(dir: PathBuf, room: &str, initial: WorldState, map: &GameMap, tick_hz: f32) -> Self {
+        Self {
+            dir,
+            replay: Replay {
+                version: REPLAY_VERSION,
+                room: room.to_string(),
+                map: map.clone(),
+                game_mode: initial.game_mode,
+                action_target_time: initial.action_target_time,
+                tick_hz,
+                initial: initial.clone(),
+                frames: vec![],
+            },
+            last: initial,
+            inputs: vec![],
+        }
+    }
+
+    pub fn record_input(&mut self, player_id: &str, left: bool, right: bool, shoot: bool) {
+        self.inputs.push(ReplayInput {
+            player_id: player_id.to_string(),
+            left,
+            right,
+            shoot,
+        });
+    }
+
+    pub fn record_tick(&mut self, world: WorldState) {
+        self.replay.frames.push(ReplayFrame {
+            inputs: std::mem::take(&mut self.inputs),
+            delta: world.delta_from(&self.last),
+        });
+        self.last = world;
+    }
+
+    /// Whether the recording reached `MAX_REPLAY_SECS` and should be saved.
+    pub fn is_full(&self) -> bool {
+        self.replay.duration_secs() >= MAX_REPLAY_SECS
+    }
+
+    /// Writes the replay on a separate thread so the physics loop is not held up by disk I/O.
+    pub fn save(self) {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let path = self.dir.join(format!(
+            "{}-{}.{}",
+            file_safe(&self.replay.room),
+            secs,
+            REPLAY_EXTENSION
+        ));
+
+        std::thread::spawn(move || {
+            let result = self
+                .replay
+                .encode()
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| {
+                    fs::create_dir_all(&self.dir).map_err(|e| e.to_string())?;
+                    fs::write(&path, bytes).map_err(|e| e.to_string())
+                });
+            match result {
+                Ok(()) => println!("Replay saved to {}", path.display()),
+                Err(e) => println!("Failed to save replay {}: {}", path.display(), e),
+            }
+        });
+    }
+}
+
+/// Room names come from clients; keep them from escaping the replay directory.
+fn file_safe(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use spin_snowball_shared::*;
+
+use crate::config::Config;
+use crate::maps::MapLibrary;
+use crate::ratings::Ratings;
+use crate::{physics_loop, GameState, PeerMap};
+
+pub const DEFAULT_ROOM: &str = "main";
+
+pub struct Room {
+    pub name: String,
+    pub game_state: Arc<Mutex<GameState>>,
+    pub peers: PeerMap,
+}
+
+impl Room {
+    fn new(
+        name: String,
+        map: GameMap,
+        map_name: Option<String>,
+        host_password: Option<String>,
+        config: Arc<Config>,
+        maps: Arc<Mutex<MapLibrary>>,
+        ratings: Arc<Mutex<Ratings>>,
+    ) -> Arc<Self> {
+        let mut game_state = GameState::new(map, config, maps, ratings);
+        game_state.map_name = map_name;
+        game_state.host_password = host_password;
+        game_state.room_name = name.clone();
+
+        let room = Arc::new(Self {
+            name,
+            game_state: Arc::new(Mutex::new(game_state)),
+            peers: Arc::new(Mutex::new(HashMap::new())),
+        });
+
+        // the loop only holds a weak handle, so it stops once the room is dropped
+        tokio::spawn(physics_loop(Arc::downgrade(&room)));
+        room
+    }
+
+    pub fn info(&self) -> RoomInfo {
+        let gs = self.game_state.lock().unwrap();
+        RoomInfo {
+            name: self.name.clone(),
+            map_name: gs.world.map.name.clone(),
+            players: gs.world.players.len() as u32,
+            game_mode: gs.world.game_mode,
+            phase: gs.phase,
+        }
+    }
+
+    /// Empty once nobody is connected nor waiting to resume.
+    pub fn is_empty(&self) -> bool {
+        self.game_state.lock().unwrap().members.is_empty()
+    }
+}
+
+pub struct Rooms {
+    rooms: Mutex<HashMap<String, Arc<Room>>>,
+    default_map: GameMap,
+    /// Library name of the default map, if it is part of the library.
+    default_map_name: Option<String>,
+    config: Arc<Config>,
+    maps: Arc<Mutex<MapLibrary>>,
+    ratings: Arc<Mutex<Ratings>>,
+}
+
+impl Rooms {
+    pub fn new(
+        config: Arc<Config>,
+        maps: Arc<Mutex<MapLibrary>>,
+        ratings: Arc<Mutex<Ratings>>,
+        default_map: GameMap,
+        default_map_name: Option<String>,
+    ) -> Arc<Self> {
+        let rooms = Arc::new(Self {
+            rooms: Mutex::new(HashMap::new()),
+            default_map,
+            default_map_name,
+            config,
+            maps,
+            ratings,
+        });
+        rooms.create(DEFAULT_ROOM, rooms.config.host_password.clone());
+        rooms
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn maps(&self) -> &Mutex<MapLibrary> {
+        &self.maps
+    }
+
+    pub fn ratings(&self) -> &Mutex<Ratings> {
+        &self.ratings
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Room>> {
+        self.rooms.lock().unwrap().get(name).cloned()
+    }
+
+    pub fn default_room(&self) -> Arc<Room> {
+        self.get(DEFAULT_ROOM).unwrap()
+    }
+
+    /// Creates a new room with the default map. Returns `None` if the name is taken.
+    pub fn create(&self, name: &str, host_password: Option<String>) -> Option<Arc<Room>> {
+        let mut rooms = self.rooms.lock().unwrap();
+        if rooms.contains_key(name) {
+            return None;
+        }
+
+        let room = Room::new(
+            name.to_string(),
+            self.default_map.clone(),
+            self.default_map_name.clone(),
+            host_password,
+            self.config.clone(),
+            self.maps.clone(),
+            self.ratings.clone(),
+        );
+        rooms.insert(name.to_string(), room.clone());
+        println!("Room {} created", name);
+        Some(room)
+    }
+
+    /// Runs `enter` on `room` while holding the room list, so the room cannot be
+    /// removed as empty between the caller finding it and a player entering it.
+    /// Returns `None` if the room was removed already.
+    pub fn enter<R>(&self, room: &Arc<Room>, enter: impl FnOnce(&Room) -> R) -> Option<R> {
+        let rooms = self.rooms.lock().unwrap();
+        if !rooms.get(&room.name).is_some_and(|r| Arc::ptr_eq(r, room)) {
+            return None;
+        }
+        Some(enter(room))
+    }
+
+    /// The room and player id of the session `token` belongs to.
+    pub fn find_session(&self, token: &str) -> Option<(Arc<Room>, String)> {
+        let rooms: Vec<Arc<Room>> = self.rooms.lock().unwrap().values().cloned().collect();
+        rooms.into_iter().find_map(|room| {
+            let id = room.game_state.lock().unwrap().session_of(token)?;
+            Some((room, id))
+        })
+    }
+
+    pub fn list(&self) -> Vec<RoomInfo> {
+        let rooms: Vec<Arc<Room>> = self.rooms.lock().unwrap().values().cloned().collect();
+        let mut infos: Vec<RoomInfo> = rooms.iter().map(|r| r.info()).collect();
+        infos.sort_by(|a, b| a.name.cmp(&b.name));
+        infos
+    }
+
+    /// Drops the room if nobody is left in it. The default room is never removed.
+    pub fn remove_if_empty(&self, name: &str) {
+        if name == DEFAULT_ROOM {
+            return;
+        }
+
+        let mut rooms = self.rooms.lock().unwrap();
+        if rooms.get(name).is_some_and(|r| r.is_empty()) {
+            rooms.remove(name);
+            println!("Room {} removed", name);
+        }
+    }
+}
@@ -0,0 +1,416 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rhai::{
+    Array, CallFnOptions, Dynamic, Engine, EvalAltResult, FuncArgs, Map, Scope, AST, FLOAT, INT,
+};
+use spin_snowball_shared::*;
+use spin_snowball_simulation::{GameModeRules, Player, SimulateCollisionResponse, World};
+
+/// Time a script may run per tick, over all of its hooks together.
+const TICK_BUDGET: Duration = Duration::from_millis(5);
+
+/// Hooks a script can define, with the number of parameters each takes.
+const HOOKS: [(&str, usize); 5] = [
+    ("on_match_start", 0),
+    ("on_tick", 1),
+    ("on_snowball_hit", 2),
+    ("on_player_in_hole", 1),
+    ("on_ball_in_goal", 1),
+];
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// A change a script asked for, made once the hook returns.
+enum Request {
+    AwardPoint { team: Team, by: Option<String> },
+    ResetPositions,
+    SetHud(Option<String>),
+}
+
+/// What the API functions see of the world while a hook runs, and what they
+/// asked to change.
+struct Frame {
+    /// Playing players by id.
+    players: Vec<(String, Dynamic)>,
+    ball: Dynamic,
+    scores: [INT; 2],
+    requests: Vec<Request>,
+}
+
+impl Frame {
+    fn empty() -> Self {
+        Self {
+            players: Vec::new(),
+            ball: Dynamic::UNIT,
+            scores: [0, 0],
+            requests: Vec::new(),
+        }
+    }
+
+    fn capture(world: &World) -> Self {
+        let players = world
+            .players
+            .values()
+            .filter_map(|p| match p.status {
+                PlayerStatus::Playing(team) => Some((p.id.clone(), player_map(p, team))),
+                PlayerStatus::Spectator => None,
+            })
+            .collect();
+        let ball = match &world.ball {
+            Some(ball) => {
+                let mut map = Map::new();
+                map.insert("x".into(), Dynamic::from_float(ball.pos.x as FLOAT));
+                map.insert("y".into(), Dynamic::from_float(ball.pos.y as FLOAT));
+                map.insert("vx".into(), Dynamic::from_float(ball.vel.x as FLOAT));
+                map.insert("vy".into(), Dynamic::from_float(ball.vel.y as FLOAT));
+                let last_touch = ball.last_touch.clone().map_or(Dynamic::UNIT, Dynamic::from);
+                map.insert("last_touch".into(), last_touch);
+                Dynamic::from_map(map)
+            }
+            None => Dynamic::UNIT,
+        };
+        let score = |team| world.scores.get(&team).copied().unwrap_or(0) as INT;
+        Self {
+            players,
+            ball,
+            scores: [score(Team::Team1), score(Team::Team2)],
+            requests: Vec::new(),
+        }
+    }
+
+    /// Queues a point for `team` unless the hook already asked for one.
+    fn award_point(&mut self, team: Team, by: Option<String>) {
+        let queued = self
+            .requests
+            .iter()
+            .any(|r| matches!(r, Request::AwardPoint { team: t, .. } if *t == team));
+        if !queued {
+            self.requests.push(Request::AwardPoint { team, by });
+        }
+    }
+}
+
+fn player_map(p: &Player, team: Team) -> Dynamic {
+    let mut map = Map::new();
+    map.insert("id".into(), Dynamic::from(p.id.clone()));
+    map.insert("nick".into(), Dynamic::from(p.nick.clone()));
+    map.insert("team".into(), Dynamic::from_int(team_number(team)));
+    map.insert("x".into(), Dynamic::from_float(p.pos.x as FLOAT));
+    map.insert("y".into(), Dynamic::from_float(p.pos.y as FLOAT));
+    map.insert("vx".into(), Dynamic::from_float(p.vel.x as FLOAT));
+    map.insert("vy".into(), Dynamic::from_float(p.vel.y as FLOAT));
+    map.insert("rotation".into(), Dynamic::from_float(p.rot_deg as FLOAT));
+    Dynamic::from_map(map)
+}
+
+/// Scripts call the teams 1 and 2.
+fn team_number(team: Team) -> INT {
+    match team {
+        Team::Team1 => 1,
+        Team::Team2 => 2,
+    }
+}
+
+fn team_of(number: INT) -> ScriptResult<Team> {
+    match number {
+        1 => Ok(Team::Team1),
+        2 => Ok(Team::Team2),
+        _ => Err(format!("there is no team {}", number).into()),
+    }
+}
+
+/// Rules of the custom game mode, written as a Rhai script.
+///
+/// The script defines any of these functions, which are called when:
+/// - `on_match_start()`: a match starts;
+/// - `on_tick(dt)`: every tick, before anything moves;
+/// - `on_snowball_hit(player, by)`: a snowball of `by` hits `player`;
+/// - `on_player_in_hole(player)`: every tick a player is in a hole;
+/// - `on_ball_in_goal(team)`: the ball is in the goal of `team`.
+///
+/// In them it can call `players()` and `player(id)`, which give maps with
+/// `id`, `nick`, `team`, `x`, `y`, `vx`, `vy` and `rotation`, `ball()`, which
+/// gives `x`, `y`, `vx`, `vy` and `last_touch` or `()` on a map without a
+/// ball, and `score(team)`. Changes go through `award_point(team)`,
+/// `award_point(team, player)`, `reset_positions()`, `set_hud(text)` and
+/// `clear_hud()`. Teams are the numbers 1 and 2, players are their ids. A team
+/// gets at most one point from the script per tick; further ones are dropped.
+///
+/// Code outside the hooks is not run. The script keeps its state in `this`,
+/// an object map that starts empty with every match. All hooks of a tick
+/// share a time budget; a script that runs over it, or fails, is stopped and
+/// the HUD says why. Output of `print` and `debug` is dropped.
+pub struct ScriptRules {
+    engine: Engine,
+    ast: AST,
+    hooks: Vec<&'static str>,
+    state: Dynamic,
+    frame: Arc<Mutex<Frame>>,
+    epoch: Instant,
+    /// End of the current tick's budget, in microseconds since `epoch`.
+    deadline: Arc<AtomicU64>,
+    hud_text: Option<String>,
+    /// Teams the script gave a point to this tick.
+    scored: [bool; 2],
+    /// Why the script was stopped; a stopped script runs no more hooks.
+    error: Option<String>,
+}
+
+impl ScriptRules {
+    /// Compiles `source`, failing if it does not parse or defines none of the hooks.
+    pub fn new(source: &str) -> Result<Self, String> {
+        let frame = Arc::new(Mutex::new(Frame::empty()));
+        let epoch = Instant::now();
+        let deadline = Arc::new(AtomicU64::new(0));
+        let engine = sandboxed_engine(&frame, epoch, &deadline);
+
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+        let mut hooks = Vec::new();
+        for function in ast.iter_functions() {
+            let Some(&(name, params)) = HOOKS.iter().find(|(name, _)| *name == function.name)
+            else {
+                continue;
+            };
+            if function.params.len() != params {
+                return Err(format!(
+                    "{} takes {} parameters, not {}",
+                    name,
+                    params,
+                    function.params.len()
+                ));
+            }
+            hooks.push(name);
+        }
+        if hooks.is_empty() {
+            let names: Vec<&str> = HOOKS.iter().map(|(name, _)| *name).collect();
+            return Err(format!(
+                "the script defines none of the hooks {}",
+                names.join(", ")
+            ));
+        }
+
+        Ok(Self {
+            engine,
+            ast,
+            hooks,
+            state: Dynamic::from_map(Map::new()),
+            frame,
+            epoch,
+            deadline,
+            hud_text: None,
+            scored: [false, false],
+            error: None,
+        })
+    }
+
+    /// Gives the script a fresh time budget and lets it award points again.
+    fn start_budget(&mut self) {
+        self.scored = [false, false];
+        let end = self.epoch.elapsed() + TICK_BUDGET;
+        self.deadline
+            .store(end.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn call(&mut self, world: &mut World, hook: &'static str, args: impl FuncArgs) {
+        if self.error.is_some() || !self.hooks.contains(&hook) {
+            return;
+        }
+        *self.frame.lock().unwrap() = Frame::capture(world);
+
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut self.state);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut Scope::new(),
+            &self.ast,
+            hook,
+            args,
+        );
+
+        let requests = std::mem::take(&mut self.frame.lock().unwrap().requests);
+        for request in requests {
+            match request {
+                Request::AwardPoint { team, by } => {
+                    let scored = &mut self.scored[team_number(team) as usize - 1];
+                    if !*scored {
+                        *scored = true;
+                        self.award_point(world, team, by);
+                    }
+                }
+                Request::ResetPositions => world.reset_positions(),
+                Request::SetHud(text) => self.hud_text = text,
+            }
+        }
+
+        if let Err(e) = result {
+            let reason = match *e {
+                EvalAltResult::ErrorTerminated(..) => format!(
+                    "{} ran over the {} ms a script has per tick",
+                    hook,
+                    TICK_BUDGET.as_millis()
+                ),
+                e => format!("{}: {}", hook, e),
+            };
+            println!("Script stopped: {}", reason);
+            self.error = Some(reason);
+        }
+    }
+}
+
+impl GameModeRules for ScriptRules {
+    fn on_match_start(&mut self, world: &mut World) {
+        self.state = Dynamic::from_map(Map::new());
+        self.hud_text = None;
+        self.start_budget();
+        self.call(world, "on_match_start", ());
+    }
+
+    fn on_tick(&mut self, world: &mut World, dt: f32) {
+        self.start_budget();
+        self.call(world, "on_tick", (dt as FLOAT,));
+    }
+
+    fn on_collisions(&mut self, response: &SimulateCollisionResponse, world: &mut World) {
+        for (player, by) in &response.players_hit_by_snowball {
+            self.call(world, "on_snowball_hit", (player.clone(), by.clone()));
+        }
+        for player in &response.players_in_holes {
+            self.call(world, "on_player_in_hole", (player.clone(),));
+        }
+        if let Some(team) = response.ball_in_goal_of_team {
+            self.call(world, "on_ball_in_goal", (team_number(team),));
+        }
+    }
+
+    fn hud(&self) -> ModeHud {
+        let text = match &self.error {
+            Some(error) => Some(format!("Script stopped: {}", error)),
+            None => self.hud_text.clone(),
+        };
+        ModeHud {
+            holder: None,
+            timer: 0.0,
+            text,
+        }
+    }
+}
+
+/// An engine that cannot run longer than `deadline`, with the game API registered.
+fn sandboxed_engine(
+    frame: &Arc<Mutex<Frame>>,
+    epoch: Instant,
+    deadline: &Arc<AtomicU64>,
+) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_call_levels(32)
+        .set_max_expr_depths(64, 32)
+        .set_max_string_size(4096)
+        .set_max_array_size(4096)
+        .set_max_map_size(1024)
+        .disable_symbol("eval");
+    // any host can load a script, so it must not be able to flood the server log
+    engine.on_print(|_| {});
+    engine.on_debug(|_, _, _| {});
+
+    let deadline = deadline.clone();
+    engine.on_progress(move |operations| {
+        // reading the clock on every operation would slow scripts down for nothing
+        if operations % 64 == 0
+            && epoch.elapsed().as_micros() as u64 > deadline.load(Ordering::Relaxed)
+        {
+            Some(Dynamic::UNIT)
+        } else {
+            None
+        }
+    });
+
+    let f = frame.clone();
+    engine.register_fn("players", move || {
+        let frame = f.lock().unwrap();
+        frame
+            .players
+            .iter()
+            .map(|(_, p)| p.clone())
+            .collect::<Array>()
+    });
+    let f = frame.clone();
+    engine.register_fn("player", move |id: &str| {
+        let frame = f.lock().unwrap();
+        frame
+            .players
+            .iter()
+            .find(|(player_id, _)| player_id == id)
+            .map_or(Dynamic::UNIT, |(_, p)| p.clone())
+    });
+    let f = frame.clone();
+    engine.register_fn("ball", move || f.lock().unwrap().ball.clone());
+    let f = frame.clone();
+    engine.register_fn("score", move |team: INT| -> ScriptResult<INT> {
+        let index = match team_of(team)? {
+            Team::Team1 => 0,
+            Team::Team2 => 1,
+        };
+        Ok(f.lock().unwrap().scores[index])
+    });
+
+    let f = frame.clone();
+    engine.register_fn("award_point", move |team: INT| -> ScriptResult<()> {
+        f.lock().unwrap().award_point(team_of(team)?, None);
+        Ok(())
+    });
+    let f = frame.clone();
+    engine.register_fn(
+        "award_point",
+        move |team: INT, by: &str| -> ScriptResult<()> {
+            let by = Some(by.to_string());
+            f.lock().unwrap().award_point(team_of(team)?, by);
+            Ok(())
+        },
+    );
+    let f = frame.clone();
+    engine.register_fn("reset_positions", move || {
+        f.lock().unwrap().requests.push(Request::ResetPositions);
+    });
+    let f = frame.clone();
+    engine.register_fn("set_hud", move |text: &str| {
+        let request = Request::SetHud(Some(text.to_string()));
+        f.lock().unwrap().requests.push(request);
+    });
+    let f = frame.clone();
+    engine.register_fn("clear_hud", move || {
+        f.lock().unwrap().requests.push(Request::SetHud(None));
+    });
+
+    engine
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spin_snowball_shared::test_util::map;
+
+    #[test]
+    fn points_awarded_in_a_loop_count_once_per_tick() {
+        let source = r#"
+            fn on_tick(dt) {
+                for i in 0..100 {
+                    award_point(1);
+                    award_point(2, "nobody");
+                }
+            }
+        "#;
+        let mut world = World::new(map(Vec::new()));
+        world.set_rules(Box::new(ScriptRules::new(source).unwrap()));
+        for _ in 0..300 {
+            world.step(1.0 / 30.0);
+        }
+
+        assert_eq!(world.hud().text, None);
+        assert_eq!(world.scores[&Team::Team1], 255);
+        assert_eq!(world.scores[&Team::Team2], 255);
+    }
+}
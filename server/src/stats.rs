@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use spin_snowball_shared::*;
+use spin_snowball_simulation::World;
+
+/// A fall into a hole counts as a knockout for whoever hit the player within this time.
+const KNOCKOUT_WINDOW_SECS: f32 = 3.0;
+
+struct Entry {
+    nick: String,
+    team: Team,
+    is_bot: bool,
+    stats: PlayerStats,
+}
+
+/// Statistics of the running match, fed from the world's events every tick.
+#[derive(Default)]
+pub struct MatchStats {
+    players: HashMap<String, Entry>,
+    /// Last player to hit each player, with the match time of the hit.
+    last_hit: HashMap<String, (String, f32)>,
+    /// Order players first played in, so the summary lists them stably.
+    order: Vec<String>,
+}
+
+impl MatchStats {
+    /// Notes who plays for which team; players who leave keep their last entry.
+    pub fn update_roster(&mut self, world: &World, bots: impl Fn(&str) -> bool) {
+        for p in world.players.values() {
+            let PlayerStatus::Playing(team) = p.status else {
+                continue;
+            };
+            match self.players.get_mut(&p.id) {
+                Some(entry) => {
+                    entry.nick.clone_from(&p.nick);
+                    entry.team = team;
+                }
+                None => {
+                    self.order.push(p.id.clone());
+                    self.players.insert(
+                        p.id.clone(),
+                        Entry {
+                            nick: p.nick.clone(),
+                            team,
+                            is_bot: bots(&p.id),
+                            stats: PlayerStats::default(),
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    fn stats_mut(&mut self, id: &str) -> Option<&mut PlayerStats> {
+        self.players.get_mut(id).map(|entry| &mut entry.stats)
+    }
+
+    pub fn record_throw(&mut self, id: &str) {
+        if let Some(stats) = self.stats_mut(id) {
+            stats.snowballs_thrown += 1;
+        }
+    }
+
+    /// Counts one tick's events; `now` is the match time in seconds.
+    pub fn record_events(&mut self, events: &[GameEvent], now: f32) {
+        for event in events {
+            match event {
+                GameEvent::GoalScored { team, by: Some(by) } => {
+                    let Some(entry) = self.players.get_mut(by) else {
+                        continue;
+                    };
+                    if entry.team == *team {
+                        entry.stats.goals += 1;
+                    } else {
+                        entry.stats.own_goals += 1;
+                    }
+                }
+                GameEvent::PlayerHit { player, by } if player != by => {
+                    if let Some(stats) = self.stats_mut(by) {
+                        stats.hits += 1;
+                    }
+                    if let Some(stats) = self.stats_mut(player) {
+                        stats.times_hit += 1;
+                    }
+                    self.last_hit.insert(player.clone(), (by.clone(), now));
+                }
+                GameEvent::PlayerFellInHole { player } => {
+                    let Some((by, at)) = self.last_hit.remove(player) else {
+                        continue;
+                    };
+                    if now - at <= KNOCKOUT_WINDOW_SECS {
+                        if let Some(stats) = self.stats_mut(&by) {
+                            stats.knockouts += 1;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Adds the tick to the flag carrier's or king's time.
+    pub fn record_tick(&mut self, world: &World, dt: f32) {
+        let Some(id) = world.hud().holder else {
+            return;
+        };
+        let game_mode = world.game_mode;
+        let Some(stats) = self.stats_mut(&id) else {
+            return;
+        };
+        match game_mode {
+            GameMode::Ctf | GameMode::Htf => stats.flag_carry_secs += dt,
+            GameMode::KingOfTheHill => stats.hill_secs += dt,
+            _ => {}
+        }
+    }
+
+    pub fn summary(
+        &self,
+        game_mode: GameMode,
+        winner: Option<Team>,
+        scores: (u8, u8),
+        duration_secs: f32,
+    ) -> MatchSummary {
+        MatchSummary {
+            game_mode,
+            winner,
+            scores_team1: scores.0,
+            scores_team2: scores.1,
+            duration_secs,
+            players: self
+                .order
+                .iter()
+                .filter_map(|id| {
+                    let entry = self.players.get(id)?;
+                    Some(PlayerSummary {
+                        id: id.clone(),
+                        nick: entry.nick.clone(),
+                        team: entry.team,
+                        is_bot: entry.is_bot,
+                        stats: entry.stats.clone(),
+                    })
+                })
+                .collect(),
+        }
+    }
+}
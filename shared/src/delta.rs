@@ -0,0 +1,412 @@
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+/// Difference between two `WorldState`s. Entities are listed only when they were
+/// added or changed; the remaining fields are `None` when they did not change.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WorldDelta {
+    pub base_seq: u64,
+    pub seq: u64,
+    pub tick: u64,
+    pub server_time: f64,
+    pub players_added: Vec<PlayerState>,
+    pub players_changed: Vec<PlayerDelta>,
+    pub players_removed: Vec<String>,
+    pub snowballs_changed: Vec<SnowballState>,
+    pub snowballs_removed: Vec<u64>,
+    pub ball: Option<Option<BallState>>,
+    pub scores: Option<(u8, u8)>,
+    pub phase: Option<MatchPhase>,
+    pub time_elapsed: f32,
+    pub paused: Option<bool>,
+    pub team_colors: Option<(ColorDef, ColorDef)>,
+    pub hud: Option<ModeHud>,
+    pub game_mode: Option<GameMode>,
+    pub action_target_time: Option<Option<f32>>,
+}
+
+/// Fields of a player that changed since the base snapshot; unchanged fields are
+/// `None`. The player is named by its position in the base snapshot's player list,
+/// so the id and nick strings are not resent every tick. Player lists are kept
+/// sorted by id on both ends, which keeps those positions in step.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerDelta {
+    pub index: u32,
+    pub nick: Option<String>,
+    pub pos: Option<[f32; 2]>,
+    pub vel: Option<[f32; 2]>,
+    pub rot_deg: Option<f32>,
+    pub status: Option<PlayerStatus>,
+    pub role: Option<PlayerRole>,
+    pub spin_timer: Option<f32>,
+    pub shoot_cooldown_sec: Option<f32>,
+    pub last_input_seq: Option<u32>,
+    pub is_bot: Option<bool>,
+}
+
+impl PlayerDelta {
+    fn between(index: usize, new: &PlayerState, old: &PlayerState) -> PlayerDelta {
+        PlayerDelta {
+            index: index as u32,
+            nick: changed(&new.nick, &old.nick),
+            pos: changed(&new.pos, &old.pos),
+            vel: changed(&new.vel, &old.vel),
+            rot_deg: changed(&new.rot_deg, &old.rot_deg),
+            status: changed(&new.status, &old.status),
+            role: changed(&new.role, &old.role),
+            spin_timer: changed(&new.spin_timer, &old.spin_timer),
+            shoot_cooldown_sec: changed(&new.shoot_cooldown_sec, &old.shoot_cooldown_sec),
+            last_input_seq: changed(&new.last_input_seq, &old.last_input_seq),
+            is_bot: changed(&new.is_bot, &old.is_bot),
+        }
+    }
+
+    fn apply(&self, player: &mut PlayerState) {
+        if let Some(nick) = &self.nick {
+            player.nick = nick.clone();
+        }
+        if let Some(pos) = self.pos {
+            player.pos = pos;
+        }
+        if let Some(vel) = self.vel {
+            player.vel = vel;
+        }
+        if let Some(rot_deg) = self.rot_deg {
+            player.rot_deg = rot_deg;
+        }
+        if let Some(status) = self.status {
+            player.status = status;
+        }
+        if let Some(role) = self.role {
+            player.role = role;
+        }
+        if let Some(spin_timer) = self.spin_timer {
+            player.spin_timer = spin_timer;
+        }
+        if let Some(shoot_cooldown_sec) = self.shoot_cooldown_sec {
+            player.shoot_cooldown_sec = shoot_cooldown_sec;
+        }
+        if let Some(last_input_seq) = self.last_input_seq {
+            player.last_input_seq = last_input_seq;
+        }
+        if let Some(is_bot) = self.is_bot {
+            player.is_bot = is_bot;
+        }
+    }
+}
+
+fn changed<T: PartialEq + Clone>(new: &T, old: &T) -> Option<T> {
+    if new != old {
+        Some(new.clone())
+    } else {
+        None
+    }
+}
+
+impl WorldState {
+    /// Builds the delta that turns `base` into `self`.
+    pub fn delta_from(&self, base: &WorldState) -> WorldDelta {
+        let base_players: BTreeMap<&str, (usize, &PlayerState)> = base
+            .players
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (p.id.as_str(), (i, p)))
+            .collect();
+        let players: BTreeMap<&str, &PlayerState> =
+            self.players.iter().map(|p| (p.id.as_str(), p)).collect();
+
+        let base_snowballs: BTreeMap<u64, &SnowballState> =
+            base.snowballs.iter().map(|s| (s.id, s)).collect();
+        let snowballs: BTreeMap<u64, &SnowballState> =
+            self.snowballs.iter().map(|s| (s.id, s)).collect();
+
+        WorldDelta {
+            base_seq: base.seq,
+            seq: self.seq,
+            tick: self.tick,
+            server_time: self.server_time,
+            players_added: self
+                .players
+                .iter()
+                .filter(|p| !base_players.contains_key(p.id.as_str()))
+                .cloned()
+                .collect(),
+            players_changed: self
+                .players
+                .iter()
+                .filter_map(|p| {
+                    let &(index, old) = base_players.get(p.id.as_str())?;
+                    (p != old).then(|| PlayerDelta::between(index, p, old))
+                })
+                .collect(),
+            players_removed: base
+                .players
+                .iter()
+                .filter(|p| !players.contains_key(p.id.as_str()))
+                .map(|p| p.id.clone())
+                .collect(),
+            snowballs_changed: self
+                .snowballs
+                .iter()
+                .filter(|s| base_snowballs.get(&s.id) != Some(s))
+                .cloned()
+                .collect(),
+            snowballs_removed: base
+                .snowballs
+                .iter()
+                .filter(|s| !snowballs.contains_key(&s.id))
+                .map(|s| s.id)
+                .collect(),
+            ball: changed(&self.ball, &base.ball),
+            scores: changed(
+                &(self.scores_team1, self.scores_team2),
+                &(base.scores_team1, base.scores_team2),
+            ),
+            phase: changed(&self.phase, &base.phase),
+            time_elapsed: self.time_elapsed,
+            paused: changed(&self.paused, &base.paused),
+            team_colors: changed(
+                &(self.team1_color.clone(), self.team2_color.clone()),
+                &(base.team1_color.clone(), base.team2_color.clone()),
+            ),
+            hud: changed(&self.hud, &base.hud),
+            game_mode: changed(&self.game_mode, &base.game_mode),
+            action_target_time: changed(&self.action_target_time, &base.action_target_time),
+        }
+    }
+
+    /// Applies a delta whose `base_seq` is this state's `seq`.
+    pub fn apply_delta(&mut self, delta: &WorldDelta) {
+        self.seq = delta.seq;
+        self.tick = delta.tick;
+        self.server_time = delta.server_time;
+
+        // Indices refer to the base list, so changes go in before anything is removed.
+        for changed in &delta.players_changed {
+            if let Some(p) = self.players.get_mut(changed.index as usize) {
+                changed.apply(p);
+            }
+        }
+        self.players
+            .retain(|p| !delta.players_removed.contains(&p.id));
+        // The server lists players in id order, so joiners go where their id sorts
+        // rather than at the end; otherwise later indices would name the wrong player.
+        for added in &delta.players_added {
+            let at = self.players.partition_point(|p| p.id < added.id);
+            self.players.insert(at, added.clone());
+        }
+
+        self.snowballs
+            .retain(|s| !delta.snowballs_removed.contains(&s.id));
+        for changed in &delta.snowballs_changed {
+            match self.snowballs.iter_mut().find(|s| s.id == changed.id) {
+                Some(s) => *s = changed.clone(),
+                None => self.snowballs.push(changed.clone()),
+            }
+        }
+
+        if let Some(ball) = &delta.ball {
+            self.ball = ball.clone();
+        }
+        if let Some((team1, team2)) = delta.scores {
+            self.scores_team1 = team1;
+            self.scores_team2 = team2;
+        }
+        if let Some(phase) = delta.phase {
+            self.phase = phase;
+        }
+        self.time_elapsed = delta.time_elapsed;
+        if let Some(paused) = delta.paused {
+            self.paused = paused;
+        }
+        if let Some((team1, team2)) = &delta.team_colors {
+            self.team1_color = team1.clone();
+            self.team2_color = team2.clone();
+        }
+        if let Some(hud) = &delta.hud {
+            self.hud = hud.clone();
+        }
+        if let Some(game_mode) = delta.game_mode {
+            self.game_mode = game_mode;
+        }
+        if let Some(target) = delta.action_target_time {
+            self.action_target_time = target;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn player(id: &str, x: f32) -> PlayerState {
+        PlayerState {
+            id: id.into(),
+            nick: id.into(),
+            pos: [x, 100.0],
+            vel: [0.0, 0.0],
+            rot_deg: 0.0,
+            status: PlayerStatus::Playing(Team::Team1),
+            role: PlayerRole::Player,
+            spin_timer: 0.0,
+            shoot_cooldown_sec: 0.0,
+            last_input_seq: 0,
+            is_bot: false,
+        }
+    }
+
+    fn snowball(id: u64, x: f32) -> SnowballState {
+        SnowballState {
+            id,
+            pos: [x, 50.0],
+            vel: [300.0, 0.0],
+            life: 2.0,
+        }
+    }
+
+    fn world(seq: u64) -> WorldState {
+        WorldState {
+            seq,
+            tick: seq,
+            server_time: seq as f64 / 30.0,
+            players: Vec::new(),
+            snowballs: Vec::new(),
+            scores_team1: 0,
+            scores_team2: 0,
+            ball: None,
+            phase: MatchPhase::Lobby,
+            time_elapsed: 0.0,
+            paused: false,
+            team1_color: ColorDef {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 255,
+            },
+            team2_color: ColorDef {
+                r: 0,
+                g: 0,
+                b: 255,
+                a: 255,
+            },
+            hud: ModeHud::default(),
+            game_mode: GameMode::Fight,
+            action_target_time: None,
+        }
+    }
+
+    fn round_trip(base: &WorldState, next: &WorldState) -> WorldDelta {
+        let delta = next.delta_from(base);
+        let mut rebuilt = base.clone();
+        rebuilt.apply_delta(&delta);
+        assert_eq!(&rebuilt, next);
+        delta
+    }
+
+    #[test]
+    fn unchanged_world_sends_nothing_but_the_clock() {
+        let mut base = world(1);
+        base.players = vec![player("a", 10.0), player("b", 20.0)];
+        base.snowballs = vec![snowball(1, 0.0)];
+        let mut next = base.clone();
+        next.seq = 2;
+
+        let delta = round_trip(&base, &next);
+        assert!(delta.players_added.is_empty());
+        assert!(delta.players_changed.is_empty());
+        assert!(delta.snowballs_changed.is_empty());
+        assert_eq!(delta.ball, None);
+        assert_eq!(delta.hud, None);
+    }
+
+    #[test]
+    fn moving_player_sends_only_changed_fields() {
+        let mut base = world(1);
+        base.players = vec![player("a", 10.0), player("b", 20.0)];
+        let mut next = base.clone();
+        next.seq = 2;
+        next.players[1].pos = [25.0, 100.0];
+        next.players[1].last_input_seq = 7;
+
+        let delta = round_trip(&base, &next);
+        assert_eq!(
+            delta.players_changed,
+            vec![PlayerDelta {
+                index: 1,
+                nick: None,
+                pos: Some([25.0, 100.0]),
+                vel: None,
+                rot_deg: None,
+                status: None,
+                role: None,
+                spin_timer: None,
+                shoot_cooldown_sec: None,
+                last_input_seq: Some(7),
+                is_bot: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn players_and_snowballs_come_and_go() {
+        let mut base = world(1);
+        base.players = vec![player("a", 10.0), player("b", 20.0), player("c", 30.0)];
+        base.snowballs = vec![snowball(1, 0.0), snowball(2, 10.0)];
+        let mut next = world(2);
+        // "a" leaves, so "c" changes position in the list while also moving
+        next.players = vec![player("b", 20.0), player("c", 35.0), player("d", 40.0)];
+        next.players[0].nick = "renamed".into();
+        next.snowballs = vec![snowball(2, 20.0), snowball(3, 0.0)];
+
+        let delta = round_trip(&base, &next);
+        assert_eq!(delta.players_removed, vec![String::from("a")]);
+        assert_eq!(delta.players_added.len(), 1);
+        assert_eq!(delta.snowballs_removed, vec![1]);
+    }
+
+    #[test]
+    fn joiner_sorting_first_keeps_later_indices_in_step() {
+        let mut base = world(1);
+        base.players = vec![player("b", 20.0), player("c", 30.0)];
+        let mut next = world(2);
+        next.players = vec![player("a", 10.0), player("b", 20.0), player("c", 30.0)];
+
+        let delta = round_trip(&base, &next);
+        assert_eq!(delta.players_added.len(), 1);
+
+        let mut after = next.clone();
+        after.seq = 3;
+        after.players[2].pos = [35.0, 100.0];
+        let delta = round_trip(&next, &after);
+        assert_eq!(delta.players_changed[0].index, 2);
+    }
+
+    #[test]
+    fn match_state_changes_are_carried() {
+        let mut base = world(1);
+        base.ball = Some(BallState {
+            pos: [0.0, 0.0],
+            vel: [0.0, 0.0],
+        });
+        let mut next = world(2);
+        next.scores_team2 = 1;
+        next.phase = MatchPhase::Playing {
+            score_limit: Some(3),
+            time_limit_secs: None,
+        };
+        next.time_elapsed = 12.5;
+        next.paused = true;
+        next.team1_color.g = 128;
+        next.hud.holder = Some("b".into());
+        next.game_mode = GameMode::Football;
+        next.action_target_time = Some(5.0);
+
+        let delta = round_trip(&base, &next);
+        assert_eq!(delta.ball, Some(None));
+    }
+}
@@ -6,10 +6,19 @@ use alloc::string::String;
 use alloc::vec::Vec;
 use serde::{Deserialize, Serialize};
 
+pub mod delta;
+pub mod replay;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+pub mod validation;
+pub mod wire;
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type")]
 pub enum ClientMessage {
     Input {
+        /// Increases by one with every input sent; echoed back in `PlayerState::last_input_seq`.
+        seq: u32,
         left: bool,
         right: bool,
         shoot: bool,
@@ -20,6 +29,31 @@ pub enum ClientMessage {
     Command {
         cmd: Command,
     },
+    /// Confirms that the snapshot with this `seq` was received, so the server
+    /// can use it as the base of the next delta.
+    Ack {
+        seq: u64,
+    },
+    Chat {
+        channel: ChatChannel,
+        text: String,
+    },
+    /// Reattaches this connection to the player of an earlier connection that
+    /// dropped, giving up the player it started with. `token` is the one from
+    /// `ServerMessage::AssignId`; see also `wire::RESUME_QUERY_KEY`.
+    Resume {
+        token: String,
+    },
+}
+
+/// Longest chat message the server accepts, in characters.
+pub const MAX_CHAT_LEN: usize = 200;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChatChannel {
+    All,
+    /// Only players of the sender's team, or only spectators if the sender is one.
+    Team,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -35,8 +69,9 @@ pub enum Command {
     LoadMap {
         data: String,
     },
+    /// Joins `team`, or the team with fewer players if `None`.
     JoinAsPlayer {
-        team: Team,
+        team: Option<Team>,
     },
     JoinAsSpectator,
     SetNick {
@@ -53,6 +88,108 @@ pub enum Command {
         game_mode: GameMode,
         action_target_time: Option<f32>,
     },
+    CreateRoom {
+        name: String,
+        host_password: Option<String>,
+    },
+    ListRooms,
+    JoinRoom {
+        name: String,
+    },
+    LeaveRoom,
+    ClaimHost {
+        password: String,
+    },
+    SetAdmin {
+        player_id: String,
+        admin: bool,
+    },
+    AddBot {
+        team: Team,
+        difficulty: BotDifficulty,
+    },
+    RemoveBot {
+        player_id: String,
+    },
+    /// Asks for the maps of the server's map library, answered with `ServerMessage::MapList`.
+    ListMaps,
+    /// Loads a map of the library by its `MapInfo::name`.
+    SelectMap {
+        name: String,
+    },
+    /// Asks for the best rated players, answered with `ServerMessage::Leaderboard`.
+    GetLeaderboard,
+    /// Deals everyone on a team out to two new teams of even size, at random
+    /// or so that the teams' ratings add up about the same.
+    ShuffleTeams {
+        by_rating: bool,
+    },
+    /// Switches to the custom game mode played by this script instead of the
+    /// map's; `None` goes back to the map's script.
+    SetScript {
+        source: Option<String>,
+    },
+}
+
+impl Command {
+    /// Name of the command as reported back in `ServerMessage::CommandAck`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Start { .. } => "Start",
+            Command::Stop => "Stop",
+            Command::Pause => "Pause",
+            Command::Resume => "Resume",
+            Command::LoadMap { .. } => "LoadMap",
+            Command::JoinAsPlayer { .. } => "JoinAsPlayer",
+            Command::JoinAsSpectator => "JoinAsSpectator",
+            Command::SetNick { .. } => "SetNick",
+            Command::SetColorDef { .. } => "SetColorDef",
+            Command::SetPhysicsSettings { .. } => "SetPhysicsSettings",
+            Command::SetGameMode { .. } => "SetGameMode",
+            Command::CreateRoom { .. } => "CreateRoom",
+            Command::ListRooms => "ListRooms",
+            Command::JoinRoom { .. } => "JoinRoom",
+            Command::LeaveRoom => "LeaveRoom",
+            Command::ClaimHost { .. } => "ClaimHost",
+            Command::SetAdmin { .. } => "SetAdmin",
+            Command::AddBot { .. } => "AddBot",
+            Command::RemoveBot { .. } => "RemoveBot",
+            Command::ListMaps => "ListMaps",
+            Command::SelectMap { .. } => "SelectMap",
+            Command::GetLeaderboard => "GetLeaderboard",
+            Command::ShuffleTeams { .. } => "ShuffleTeams",
+            Command::SetScript { .. } => "SetScript",
+        }
+    }
+
+    /// Lowest role allowed to issue this command.
+    pub fn required_role(&self) -> PlayerRole {
+        match self {
+            Command::Start { .. }
+            | Command::Stop
+            | Command::Pause
+            | Command::Resume
+            | Command::LoadMap { .. }
+            | Command::SetColorDef { .. }
+            | Command::SetPhysicsSettings { .. }
+            | Command::SetGameMode { .. }
+            | Command::AddBot { .. }
+            | Command::RemoveBot { .. }
+            | Command::SelectMap { .. }
+            | Command::SetScript { .. } => PlayerRole::Admin,
+            Command::SetAdmin { .. } | Command::ShuffleTeams { .. } => PlayerRole::Host,
+            Command::JoinAsPlayer { .. }
+            | Command::JoinAsSpectator
+            | Command::SetNick { .. }
+            | Command::CreateRoom { .. }
+            | Command::ListRooms
+            | Command::JoinRoom { .. }
+            | Command::LeaveRoom
+            | Command::ClaimHost { .. }
+            | Command::ListMaps
+            | Command::GetLeaderboard => PlayerRole::Player,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
@@ -65,6 +202,21 @@ pub enum PlayerStatus {
     Spectator,
     Playing(Team),
 }
+/// Ordered from least to most privileged, so roles can be compared directly.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum PlayerRole {
+    Player,
+    Admin,
+    Host,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum BotDifficulty {
+    Easy,
+    Normal,
+    Hard,
+}
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub enum MatchPhase {
     Lobby,
@@ -79,10 +231,23 @@ pub enum MatchPhase {
 pub enum ServerMessage {
     AssignId {
         id: String,
+        /// Secret that lets a dropped connection resume this player, sent in
+        /// `ClientMessage::Resume` or passed in the handshake URL under
+        /// `wire::RESUME_QUERY_KEY`. Sent again when a resume succeeds.
+        token: String,
+    },
+    /// Sent once after `AssignId`.
+    ServerInfo {
+        name: String,
+        /// Physics ticks per second, which client-side prediction has to match.
+        tick_rate: f32,
     },
     WorldState {
         world: WorldState,
     },
+    WorldDelta {
+        delta: delta::WorldDelta,
+    },
     PhysicsSettings {
         settings: PhysicsSettings,
     },
@@ -92,10 +257,200 @@ pub enum ServerMessage {
     Pong {
         ts: u64,
     },
+    RoomList {
+        rooms: Vec<RoomInfo>,
+    },
+    RoomJoined {
+        name: String,
+    },
+    MapList {
+        maps: Vec<MapInfo>,
+    },
+    GameEvent {
+        event: GameEvent,
+    },
+    Leaderboard {
+        entries: Vec<LeaderboardEntry>,
+    },
+    /// Sent once a match is over, right after `GameEvent::MatchEnded`.
+    MatchSummary {
+        summary: MatchSummary,
+    },
+    Chat {
+        /// Player id of the sender.
+        from: String,
+        nick: String,
+        channel: ChatChannel,
+        text: String,
+    },
+    /// A command or message from this client was rejected.
+    Error {
+        code: ErrorCode,
+        message: String,
+    },
+    /// The command named `command` was carried out.
+    CommandAck {
+        command: String,
+    },
+}
+
+/// Something that happened in a room, so clients don't have to work it out by
+/// comparing snapshots. Players are referred to by id.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum GameEvent {
+    MatchStarted {
+        score_limit: Option<u8>,
+        time_limit_secs: Option<u32>,
+    },
+    MatchPaused,
+    MatchResumed,
+    /// The match reached a limit or was stopped; `winner` is `None` on a draw.
+    MatchEnded {
+        winner: Option<Team>,
+        scores_team1: u8,
+        scores_team2: u8,
+    },
+    /// `team` got a point, in any mode. `by` is the player credited with it, for
+    /// ball modes the last one to touch the ball, which may be an own goal.
+    GoalScored {
+        team: Team,
+        by: Option<String>,
+    },
+    PlayerHit {
+        player: String,
+        /// Who threw the snowball.
+        by: String,
+    },
+    PlayerFellInHole {
+        player: String,
+    },
+    FlagPickedUp {
+        player: String,
+    },
+    FlagDropped {
+        player: String,
+    },
+    FlagCaptured {
+        player: String,
+        team: Team,
+    },
+    /// The hill has a new king, or `None` once it is empty.
+    KingChanged {
+        king: Option<String>,
+    },
+}
+
+/// What one player did during a match.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PlayerStats {
+    pub goals: u32,
+    pub own_goals: u32,
+    pub snowballs_thrown: u32,
+    /// Hits on other players.
+    pub hits: u32,
+    pub times_hit: u32,
+    /// Players who fell into a hole shortly after being hit by this one.
+    pub knockouts: u32,
+    pub flag_carry_secs: f32,
+    pub hill_secs: f32,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerSummary {
+    pub id: String,
+    pub nick: String,
+    /// The team the player was last seen playing for.
+    pub team: Team,
+    pub is_bot: bool,
+    pub stats: PlayerStats,
+}
+
+/// Final result of a match, with everyone who played in it, including
+/// players who left before the end.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MatchSummary {
+    pub game_mode: GameMode,
+    pub winner: Option<Team>,
+    pub scores_team1: u8,
+    pub scores_team2: u8,
+    pub duration_secs: f32,
+    pub players: Vec<PlayerSummary>,
+}
+
+/// Why a command was rejected; `ServerMessage::Error` carries a readable message as well.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorCode {
+    /// The sender's role is not allowed to issue the command.
+    PermissionDenied,
+    /// The command does not fit the current match phase.
+    WrongPhase,
+    /// A match cannot start without anyone on a team.
+    NoPlayers,
+    /// The map could not be parsed or cannot be played.
+    InvalidMap,
+    /// A value in the command is out of range or empty.
+    InvalidArgument,
+    /// The room or player the command refers to does not exist.
+    NotFound,
+    AlreadyExists,
+    WrongPassword,
+    /// The message could not be decoded at all.
+    MalformedMessage,
+    /// The room has reached the server's player limit.
+    RoomFull,
+    /// The session to resume is unknown or its grace period ran out; the
+    /// connection continues as a new player.
+    SessionExpired,
+    /// Too many messages in a short time.
+    RateLimited,
+    /// The team has reached the server's team size limit.
+    TeamFull,
+    /// The script could not be compiled.
+    InvalidScript,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RoomInfo {
+    pub name: String,
+    pub map_name: String,
+    pub players: u32,
+    pub game_mode: GameMode,
+    pub phase: MatchPhase,
+}
+
+/// A player's standing on the server's ladder. Ratings belong to nicks, as
+/// there are no accounts.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LeaderboardEntry {
+    pub nick: String,
+    pub rating: f32,
+    pub matches: u32,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+/// A map of the server's map library.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MapInfo {
+    /// File name without extension, used to select the map.
+    pub name: String,
+    /// The name stored in the map itself.
+    pub title: String,
+    pub width: f32,
+    pub height: f32,
+    /// Game modes the map has everything for.
+    pub recommended_modes: Vec<GameMode>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct WorldState {
+    /// Unique per snapshot across the whole server, used to acknowledge snapshots.
+    pub seq: u64,
+    /// Physics tick of the room the snapshot was taken at.
+    pub tick: u64,
+    /// Seconds since the room started ticking, used to time interpolation.
+    pub server_time: f64,
     pub players: Vec<PlayerState>,
     pub snowballs: Vec<SnowballState>,
     pub scores_team1: u8,
@@ -106,18 +461,29 @@ pub struct WorldState {
     pub paused: bool,
     pub team1_color: ColorDef,
     pub team2_color: ColorDef,
-    pub player_with_active_action: Option<(String, f32)>,
+    pub hud: ModeHud,
     pub game_mode: GameMode,
     pub action_target_time: Option<f32>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// What the HUD shows about the objective of the current game mode.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ModeHud {
+    /// Player holding the objective, e.g. the flag carrier or the king of the hill.
+    pub holder: Option<String>,
+    /// Seconds on the mode's clock, e.g. how long the holder has held on.
+    pub timer: f32,
+    /// Free text, set by the script of the custom mode.
+    pub text: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct BallState {
     pub pos: [f32; 2],
     pub vel: [f32; 2],
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct PlayerState {
     pub id: String,
     pub nick: String,
@@ -125,9 +491,15 @@ pub struct PlayerState {
     pub vel: [f32; 2],
     pub rot_deg: f32,
     pub status: PlayerStatus,
+    pub role: PlayerRole,
+    pub spin_timer: f32,
+    pub shoot_cooldown_sec: f32,
+    /// Sequence number of the last input the server has processed for this player.
+    pub last_input_seq: u32,
+    pub is_bot: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct SnowballState {
     pub id: u64,
     pub pos: [f32; 2],
@@ -192,9 +564,45 @@ pub enum MapObject {
         is_hole: bool,
         mask: Vec<CollisionMaskTag>,
     },
+    /// A filled shape, convex or concave. Bodies bounce off its outline and
+    /// are pushed out if they end up inside.
+    Polygon {
+        /// Corners in order, either way round; the last one connects back to
+        /// the first. Edges must not cross each other.
+        points: Vec<MapPoint>,
+        factor: f32,
+        color: ColorDef,
+        is_hole: bool,
+        mask: Vec<CollisionMaskTag>,
+    },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct MapPoint {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Whether `(x, y)` lies inside the polygon with corners `points`, by the even-odd rule.
+pub fn polygon_contains(points: &[MapPoint], x: f32, y: f32) -> bool {
+    let mut inside = false;
+    let mut prev = match points.last() {
+        Some(p) => *p,
+        None => return false,
+    };
+    for p in points {
+        if (p.y > y) != (prev.y > y) {
+            let crossing_x = prev.x + (y - prev.y) / (p.y - prev.y) * (p.x - prev.x);
+            if x < crossing_x {
+                inside = !inside;
+            }
+        }
+        prev = *p;
+    }
+    inside
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct ColorDef {
     pub r: u8,
@@ -214,6 +622,9 @@ pub struct GameMap {
     pub team2: TeamDef,
     pub ball: Option<BallDef>,
     pub goals: Vec<GoalDef>,
+    /// Rules of the custom game mode on this map, as a Rhai script.
+    #[serde(default)]
+    pub script: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -266,11 +677,49 @@ pub enum GameMode {
     Race,
     HotPotato,
     Shooter,
+    /// Rules come from a script, see `GameMap::script` and `Command::SetScript`.
+    Custom,
+}
+
+impl GameMode {
+    pub const ALL: [GameMode; 9] = [
+        GameMode::Fight,
+        GameMode::Football,
+        GameMode::Ctf,
+        GameMode::Htf,
+        GameMode::KingOfTheHill,
+        GameMode::Race,
+        GameMode::HotPotato,
+        GameMode::Shooter,
+        GameMode::Custom,
+    ];
 }
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TeamDef {
     pub spawn_x: f32,
     pub spawn_y: f32,
+    /// Where the team's players start, one player per point. Without any, and
+    /// for players beyond the last point, players are spread on rings around
+    /// the points, or around `spawn_x`/`spawn_y`.
+    #[serde(default)]
+    pub spawns: Vec<SpawnPoint>,
+    /// Direction players face after spawning, in degrees clockwise from +x.
+    /// Players face up (-90) if no direction is set.
+    #[serde(default)]
+    pub facing_deg: Option<f32>,
+}
+
+/// Distance between the rings of players around a shared spawn point, in player radii.
+pub const SPAWN_RING_SPACING: f32 = 2.5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpawnPoint {
+    pub x: f32,
+    pub y: f32,
+    /// Overrides the team's `facing_deg` for players spawning here.
+    #[serde(default)]
+    pub facing_deg: Option<f32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -0,0 +1,75 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::delta::WorldDelta;
+use crate::wire::{decode_binary, encode_binary};
+use crate::*;
+
+/// Bumped whenever the layout of `Replay` changes.
+pub const REPLAY_VERSION: u32 = 1;
+pub const REPLAY_EXTENSION: &str = "ssreplay";
+
+/// A recorded match: the map and settings it was played with, followed by one
+/// frame per server tick. Frames store the snapshot as a delta against the
+/// previous one, starting from `initial`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Replay {
+    /// Always the first field, so `peek_version` can read it from files whose
+    /// layout differs.
+    pub version: u32,
+    pub room: String,
+    pub map: GameMap,
+    pub game_mode: GameMode,
+    pub action_target_time: Option<f32>,
+    pub tick_hz: f32,
+    pub initial: WorldState,
+    pub frames: Vec<ReplayFrame>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplayFrame {
+    /// Inputs the server applied since the previous frame, in arrival order.
+    pub inputs: Vec<ReplayInput>,
+    pub delta: WorldDelta,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReplayInput {
+    pub player_id: String,
+    pub left: bool,
+    pub right: bool,
+    pub shoot: bool,
+}
+
+impl Replay {
+    pub fn encode(&self) -> Result<Vec<u8>, postcard::Error> {
+        encode_binary(self)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, postcard::Error> {
+        decode_binary(bytes)
+    }
+
+    /// Reads only the version of an encoded replay; check it before `decode`.
+    pub fn peek_version(bytes: &[u8]) -> Result<u32, postcard::Error> {
+        postcard::take_from_bytes::<u32>(bytes).map(|(version, _)| version)
+    }
+
+    /// Length of the recording in seconds.
+    pub fn duration_secs(&self) -> f32 {
+        self.frames.len() as f32 / self.tick_hz
+    }
+
+    /// Rebuilds the full world state of every tick, starting with `initial`.
+    pub fn snapshots(&self) -> Vec<WorldState> {
+        let mut snapshots = Vec::with_capacity(self.frames.len() + 1);
+        let mut world = self.initial.clone();
+        snapshots.push(world.clone());
+        for frame in &self.frames {
+            world.apply_delta(&frame.delta);
+            snapshots.push(world.clone());
+        }
+        snapshots
+    }
+}
@@ -0,0 +1,37 @@
+//! Fixtures for the tests of this crate and of the crates built on it, which
+//! get them through the `test-util` feature.
+
+use alloc::vec::Vec;
+
+use crate::*;
+
+pub fn color() -> ColorDef {
+    ColorDef {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 255,
+    }
+}
+
+/// A 1000x1000 map with default physics and both teams spawning at the origin.
+pub fn map(objects: Vec<MapObject>) -> GameMap {
+    let team = TeamDef {
+        spawn_x: 0.0,
+        spawn_y: 0.0,
+        spawns: Vec::new(),
+        facing_deg: None,
+    };
+    GameMap {
+        name: "test".into(),
+        width: 1000.0,
+        height: 1000.0,
+        objects,
+        physics: PhysicsSettings::default(),
+        team1: team.clone(),
+        team2: team,
+        ball: None,
+        goals: Vec::new(),
+        script: None,
+    }
+}
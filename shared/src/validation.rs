@@ -0,0 +1,648 @@
+//! Semantic checks of a `GameMap` beyond what deserializing it guarantees.
+//!
+//! Errors make a map unplayable and are refused by the server; warnings point
+//! at things that are most likely mistakes but still load.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Clone, Debug)]
+pub struct MapIssue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for MapIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: {}", self.message),
+            Severity::Error => write!(f, "error: {}", self.message),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MapReport {
+    pub issues: Vec<MapIssue>,
+}
+
+impl MapReport {
+    fn error(&mut self, message: String) {
+        self.issues.push(MapIssue {
+            severity: Severity::Error,
+            message,
+        });
+    }
+
+    fn warning(&mut self, message: String) {
+        self.issues.push(MapIssue {
+            severity: Severity::Warning,
+            message,
+        });
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors().next().is_some()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &MapIssue> {
+        self.issues.iter().filter(|i| i.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &MapIssue> {
+        self.issues
+            .iter()
+            .filter(|i| i.severity == Severity::Warning)
+    }
+
+    /// All error messages on one line, for error replies.
+    pub fn error_summary(&self) -> String {
+        self.errors()
+            .map(|i| i.message.as_str())
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    pub fn merge(&mut self, other: MapReport) {
+        self.issues.extend(other.issues);
+    }
+}
+
+/// Most corners a polygon may have. The crossing edge check compares every pair
+/// of edges, so it is kept from running on huge uploaded polygons.
+const MAX_POLYGON_CORNERS: usize = 256;
+
+/// Directions of the first ring of players around a spawn point, six of them
+/// 60 degrees apart, as the simulation places them.
+const FIRST_SPAWN_RING: [(f32, f32); 6] = [
+    (1.0, 0.0),
+    (0.5, 0.866_025_4),
+    (-0.5, 0.866_025_4),
+    (-1.0, 0.0),
+    (-0.5, -0.866_025_4),
+    (0.5, -0.866_025_4),
+];
+
+/// Checks the geometry and physics of a map, independent of the game mode.
+pub fn validate_map(map: &GameMap) -> MapReport {
+    let mut report = MapReport::default();
+
+    // everything below does arithmetic on these, so stop early if they are broken
+    if !all_finite(map) {
+        report.error("the map contains a number that is not finite".into());
+        return report;
+    }
+
+    validate_physics(map, &mut report);
+    if map.width <= 0.0 || map.height <= 0.0 {
+        report.error(format!(
+            "map size {}x{} must be positive",
+            map.width, map.height
+        ));
+        return report;
+    }
+
+    for (index, obj) in map.objects.iter().enumerate() {
+        validate_object(map, index, obj, &mut report);
+    }
+
+    let radius = map.physics.player_radius;
+    for (team_name, team, def) in [
+        ("team1", Team::Team1, &map.team1),
+        ("team2", Team::Team2, &map.team2),
+    ] {
+        let mut spawns = Vec::from([(format!("{} spawn", team_name), (def.spawn_x, def.spawn_y))]);
+        for (i, spawn) in def.spawns.iter().enumerate() {
+            spawns.push((
+                format!("{} spawn point #{}", team_name, i),
+                (spawn.x, spawn.y),
+            ));
+        }
+
+        for (name, pos) in spawns {
+            if !inside_map(map, pos) {
+                report.error(format!(
+                    "{} ({}, {}) is outside the map",
+                    name, pos.0, pos.1
+                ));
+                continue;
+            }
+            for (index, obj) in map.objects.iter().enumerate() {
+                let (is_hole, mask) = hole_and_mask(obj);
+                if (is_hole || matches_player(mask, team)) && overlaps(obj, pos, radius) {
+                    report.error(format!("{} overlaps {}", name, describe(obj, index)));
+                }
+            }
+        }
+
+        // players beyond the spawn points go on rings around them; rings
+        // that are cut by walls, holes or the map edge push them further out
+        let ring_centers: Vec<(String, (f32, f32))> = if def.spawns.is_empty() {
+            Vec::from([(format!("{} spawn", team_name), (def.spawn_x, def.spawn_y))])
+        } else {
+            def.spawns
+                .iter()
+                .enumerate()
+                .map(|(i, s)| (format!("{} spawn point #{}", team_name, i), (s.x, s.y)))
+                .collect()
+        };
+        let spacing = radius * SPAWN_RING_SPACING;
+        for (name, (x, y)) in ring_centers {
+            if !inside_map(map, (x, y)) {
+                continue;
+            }
+            let free = FIRST_SPAWN_RING
+                .iter()
+                .filter(|(dx, dy)| !spawn_blocked(map, team, (x + dx * spacing, y + dy * spacing)))
+                .count();
+            if free < FIRST_SPAWN_RING.len() {
+                report.warning(format!(
+                    "{} has room for only {} of {} players around it, the others spawn further out",
+                    name,
+                    free,
+                    FIRST_SPAWN_RING.len()
+                ));
+            }
+        }
+
+        for (i, a) in def.spawns.iter().enumerate() {
+            for (j, b) in def.spawns.iter().enumerate().skip(i + 1) {
+                let (dx, dy) = (a.x - b.x, a.y - b.y);
+                if dx * dx + dy * dy < (2.0 * radius) * (2.0 * radius) {
+                    report.warning(format!(
+                        "{} spawn points #{} and #{} are too close, players will collide",
+                        team_name, i, j
+                    ));
+                }
+            }
+        }
+    }
+    if map.team1.spawn_x == map.team2.spawn_x && map.team1.spawn_y == map.team2.spawn_y {
+        report.warning("both teams spawn at the same position".into());
+    }
+
+    if let Some(ball) = &map.ball {
+        let pos = (ball.spawn_x, ball.spawn_y);
+        if !inside_map(map, pos) {
+            report.error(format!(
+                "ball spawn ({}, {}) is outside the map",
+                pos.0, pos.1
+            ));
+        } else {
+            for (index, obj) in map.objects.iter().enumerate() {
+                let (is_hole, mask) = hole_and_mask(obj);
+                if !overlaps(obj, pos, map.physics.ball_radius) {
+                    continue;
+                }
+                if is_hole {
+                    report.warning(format!("ball spawn overlaps {}", describe(obj, index)));
+                } else if matches_ball(mask) {
+                    report.error(format!("ball spawn overlaps {}", describe(obj, index)));
+                }
+            }
+        }
+    }
+
+    for (index, goal) in map.goals.iter().enumerate() {
+        if goal.w <= 0.0 || goal.h <= 0.0 {
+            report.error(format!("goal #{} ({:?}) has no area", index, goal.team));
+        } else if goal.x < 0.0
+            || goal.y < 0.0
+            || goal.x + goal.w > map.width
+            || goal.y + goal.h > map.height
+        {
+            report.error(format!(
+                "goal #{} ({:?}) is not inside the {}x{} map",
+                index, goal.team, map.width, map.height
+            ));
+        }
+    }
+
+    report
+}
+
+/// Checks that the map has what `mode` needs to be played.
+pub fn validate_game_mode(map: &GameMap, mode: GameMode) -> MapReport {
+    let mut report = MapReport::default();
+
+    let needs_ball = matches!(
+        mode,
+        GameMode::Football | GameMode::Ctf | GameMode::Htf | GameMode::HotPotato
+    );
+    if needs_ball && map.ball.is_none() {
+        report.error(format!("{:?} selected but map has no ball", mode));
+    }
+
+    if matches!(
+        mode,
+        GameMode::Football | GameMode::Ctf | GameMode::HotPotato
+    ) {
+        if map.goals.is_empty() {
+            report.error(format!("{:?} selected but map has no goals", mode));
+        } else if mode != GameMode::HotPotato {
+            for team in [Team::Team1, Team::Team2] {
+                if !map.goals.iter().any(|g| g.team == team) {
+                    report.warning(format!("{:?} has no goal, it cannot score", team));
+                }
+            }
+        }
+    }
+
+    let has_holes = map.objects.iter().any(|obj| hole_and_mask(obj).0);
+    match mode {
+        GameMode::KingOfTheHill | GameMode::Race if !has_holes => {
+            report.error(format!("{:?} selected but map has no holes", mode));
+        }
+        GameMode::Fight if !has_holes => {
+            report.warning("Fight selected but map has no holes, nobody can score".into());
+        }
+        GameMode::Custom if map.script.is_none() => {
+            report.warning("Custom selected but map has no script, one has to be loaded".into());
+        }
+        _ => {}
+    }
+
+    report
+}
+
+/// Game modes `map` can be played in without any warnings.
+pub fn recommended_modes(map: &GameMap) -> Vec<GameMode> {
+    GameMode::ALL
+        .into_iter()
+        .filter(|&mode| validate_game_mode(map, mode).issues.is_empty())
+        .collect()
+}
+
+fn validate_physics(map: &GameMap, report: &mut MapReport) {
+    let p = &map.physics;
+    let values = [
+        ("player_radius", p.player_radius),
+        ("player_mass", p.player_mass),
+        ("snowball_radius", p.snowball_radius),
+        ("snowball_mass", p.snowball_mass),
+        ("snowball_lifetime_sec", p.snowball_lifetime_sec),
+        ("player_bounciness", p.player_bounciness),
+        ("snowball_bounciness", p.snowball_bounciness),
+        ("ball_radius", p.ball_radius),
+        ("ball_mass", p.ball_mass),
+        ("ball_bounciness", p.ball_bounciness),
+        ("friction_per_frame", p.friction_per_frame),
+        ("recoil_power", p.recoil_power),
+        ("shoot_cooldown_sec", p.shoot_cooldown_sec),
+    ];
+    for (name, value) in values {
+        if value < 0.0 {
+            report.error(format!("physics {} is negative", name));
+        }
+    }
+    for (name, value) in [
+        ("player_radius", p.player_radius),
+        ("player_mass", p.player_mass),
+        ("ball_radius", p.ball_radius),
+        ("ball_mass", p.ball_mass),
+    ] {
+        if value == 0.0 {
+            report.error(format!("physics {} must not be zero", name));
+        }
+    }
+
+    if p.friction_per_frame > 1.0 {
+        report.warning(format!(
+            "friction_per_frame {} speeds everything up every tick",
+            p.friction_per_frame
+        ));
+    }
+    for (name, value) in [
+        ("player_bounciness", p.player_bounciness),
+        ("snowball_bounciness", p.snowball_bounciness),
+        ("ball_bounciness", p.ball_bounciness),
+    ] {
+        if value > 1.0 {
+            report.warning(format!("{} {} adds energy on every bounce", name, value));
+        }
+    }
+
+    if map.width < p.ball_radius * 2.0 || map.height < p.ball_radius * 2.0 {
+        report.error(format!(
+            "map size {}x{} is too small for a ball of radius {}",
+            map.width, map.height, p.ball_radius
+        ));
+    }
+}
+
+fn validate_object(map: &GameMap, index: usize, obj: &MapObject, report: &mut MapReport) {
+    let (min, max) = match obj {
+        MapObject::Circle { x, y, radius, .. } => {
+            if *radius <= 0.0 {
+                report.error(format!("{} has no radius", describe(obj, index)));
+            }
+            ((x - radius, y - radius), (x + radius, y + radius))
+        }
+        MapObject::Rect { x, y, w, h, .. } => {
+            if *w < 0.0 || *h < 0.0 {
+                report.error(format!("{} has a negative size", describe(obj, index)));
+            } else if *w == 0.0 || *h == 0.0 {
+                report.warning(format!("{} has no area", describe(obj, index)));
+            }
+            ((*x, *y), (x + w, y + h))
+        }
+        MapObject::Line { ax, ay, bx, by, .. } => {
+            if ax == bx && ay == by {
+                report.error(format!("{} has zero length", describe(obj, index)));
+            }
+            ((ax.min(*bx), ay.min(*by)), (ax.max(*bx), ay.max(*by)))
+        }
+        MapObject::Polygon { points, .. } => {
+            if points.len() < 3 {
+                report.error(format!("{} needs at least 3 corners", describe(obj, index)));
+            } else if points.len() > MAX_POLYGON_CORNERS {
+                report.error(format!(
+                    "{} has {} corners, more than the {} allowed",
+                    describe(obj, index),
+                    points.len(),
+                    MAX_POLYGON_CORNERS
+                ));
+            } else if twice_area(points) == 0.0 {
+                report.error(format!("{} has no area", describe(obj, index)));
+            } else if has_crossing_edges(points) {
+                report.error(format!(
+                    "{} has crossing edges, so it has no clear inside",
+                    describe(obj, index)
+                ));
+            }
+            let mut min = (f32::INFINITY, f32::INFINITY);
+            let mut max = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+            for p in points {
+                min = (min.0.min(p.x), min.1.min(p.y));
+                max = (max.0.max(p.x), max.1.max(p.y));
+            }
+            (min, max)
+        }
+    };
+
+    if max.0 < 0.0 || max.1 < 0.0 || min.0 > map.width || min.1 > map.height {
+        report.warning(format!(
+            "{} lies completely outside the map",
+            describe(obj, index)
+        ));
+    }
+}
+
+fn all_finite(map: &GameMap) -> bool {
+    let p = &map.physics;
+    let mut values = Vec::from([
+        map.width,
+        map.height,
+        map.team1.spawn_x,
+        map.team1.spawn_y,
+        map.team2.spawn_x,
+        map.team2.spawn_y,
+        p.player_radius,
+        p.player_mass,
+        p.snowball_radius,
+        p.snowball_mass,
+        p.snowball_lifetime_sec,
+        p.player_bounciness,
+        p.snowball_bounciness,
+        p.ball_radius,
+        p.ball_mass,
+        p.ball_bounciness,
+        p.friction_per_frame,
+        p.recoil_power,
+        p.shoot_cooldown_sec,
+    ]);
+    for team in [&map.team1, &map.team2] {
+        values.extend(team.facing_deg);
+        for spawn in &team.spawns {
+            values.extend([spawn.x, spawn.y]);
+            values.extend(spawn.facing_deg);
+        }
+    }
+    if let Some(ball) = &map.ball {
+        values.extend([ball.spawn_x, ball.spawn_y]);
+    }
+    for goal in &map.goals {
+        values.extend([goal.x, goal.y, goal.w, goal.h]);
+    }
+    for obj in &map.objects {
+        match obj {
+            MapObject::Circle {
+                x,
+                y,
+                radius,
+                factor,
+                ..
+            } => values.extend([*x, *y, *radius, *factor]),
+            MapObject::Rect {
+                x, y, w, h, factor, ..
+            } => values.extend([*x, *y, *w, *h, *factor]),
+            MapObject::Line {
+                ax,
+                ay,
+                bx,
+                by,
+                factor,
+                ..
+            } => values.extend([*ax, *ay, *bx, *by, *factor]),
+            MapObject::Polygon { points, factor, .. } => {
+                values.push(*factor);
+                values.extend(points.iter().flat_map(|p| [p.x, p.y]));
+            }
+        }
+    }
+    values.iter().all(|v| v.is_finite())
+}
+
+/// Human-readable name of an object, e.g. "hole circle #3"; the number is its index in `objects`.
+fn describe(obj: &MapObject, index: usize) -> String {
+    let (is_hole, _) = hole_and_mask(obj);
+    let kind = match obj {
+        MapObject::Circle { .. } => "circle",
+        MapObject::Rect { .. } => "rect",
+        MapObject::Line { .. } => "line",
+        MapObject::Polygon { .. } => "polygon",
+    };
+    format!(
+        "{} {} #{}",
+        if is_hole { "hole" } else { "wall" },
+        kind,
+        index
+    )
+}
+
+/// Whether a player of `team` standing at `(x, y)` would not fit there: partly
+/// outside the map, or touching a hole or a wall that stops the team.
+pub fn spawn_blocked(map: &GameMap, team: Team, (x, y): (f32, f32)) -> bool {
+    let r = map.physics.player_radius;
+    if x < r || y < r || x > map.width - r || y > map.height - r {
+        return true;
+    }
+    map.objects.iter().any(|obj| {
+        let (is_hole, mask) = hole_and_mask(obj);
+        (is_hole || matches_player(mask, team)) && overlaps(obj, (x, y), r)
+    })
+}
+
+fn hole_and_mask(obj: &MapObject) -> (bool, &Vec<CollisionMaskTag>) {
+    match obj {
+        MapObject::Circle { is_hole, mask, .. }
+        | MapObject::Rect { is_hole, mask, .. }
+        | MapObject::Line { is_hole, mask, .. }
+        | MapObject::Polygon { is_hole, mask, .. } => (*is_hole, mask),
+    }
+}
+
+fn inside_map(map: &GameMap, (x, y): (f32, f32)) -> bool {
+    x >= 0.0 && y >= 0.0 && x <= map.width && y <= map.height
+}
+
+/// Whether a circle at `(px, py)` with radius `r` touches `obj`, using the same
+/// shapes as the physics.
+fn overlaps(obj: &MapObject, (px, py): (f32, f32), r: f32) -> bool {
+    match obj {
+        MapObject::Circle { x, y, radius, .. } => {
+            dist2(px, py, *x, *y) < (r + radius) * (r + radius)
+        }
+        MapObject::Rect { x, y, w, h, .. } => {
+            if *w < 0.0 || *h < 0.0 {
+                return false;
+            }
+            let cx = px.clamp(*x, x + w);
+            let cy = py.clamp(*y, y + h);
+            dist2(px, py, cx, cy) < r * r
+        }
+        MapObject::Line { ax, ay, bx, by, .. } => {
+            let (dx, dy) = (bx - ax, by - ay);
+            let len2 = dx * dx + dy * dy;
+            let t = if len2 > 0.0 {
+                (((px - ax) * dx + (py - ay) * dy) / len2).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            dist2(px, py, ax + dx * t, ay + dy * t) < r * r
+        }
+        MapObject::Polygon { points, .. } => {
+            polygon_contains(points, px, py)
+                || edges(points).any(|(a, b)| {
+                    let (dx, dy) = (b.x - a.x, b.y - a.y);
+                    let len2 = dx * dx + dy * dy;
+                    let t = if len2 > 0.0 {
+                        (((px - a.x) * dx + (py - a.y) * dy) / len2).clamp(0.0, 1.0)
+                    } else {
+                        0.0
+                    };
+                    dist2(px, py, a.x + dx * t, a.y + dy * t) < r * r
+                })
+        }
+    }
+}
+
+/// Each edge of a polygon, from one corner to the next.
+fn edges(points: &[MapPoint]) -> impl Iterator<Item = (&MapPoint, &MapPoint)> {
+    points.iter().zip(points.iter().cycle().skip(1))
+}
+
+/// Twice the signed area of a polygon; zero when it is flat.
+fn twice_area(points: &[MapPoint]) -> f32 {
+    edges(points).map(|(a, b)| a.x * b.y - b.x * a.y).sum()
+}
+
+fn has_crossing_edges(points: &[MapPoint]) -> bool {
+    let edges: Vec<(&MapPoint, &MapPoint)> = edges(points).collect();
+    let n = edges.len();
+    // neighbouring edges share a corner, so only edges further apart are compared
+    (0..n).any(|i| {
+        (i + 2..n)
+            .filter(|j| !(i == 0 && *j == n - 1))
+            .any(|j| segments_cross(edges[i], edges[j]))
+    })
+}
+
+fn segments_cross((a, b): (&MapPoint, &MapPoint), (c, d): (&MapPoint, &MapPoint)) -> bool {
+    let side = |p: &MapPoint, q: &MapPoint, r: &MapPoint| {
+        (q.x - p.x) * (r.y - p.y) - (q.y - p.y) * (r.x - p.x)
+    };
+    side(a, b, c) * side(a, b, d) < 0.0 && side(c, d, a) * side(c, d, b) < 0.0
+}
+
+fn dist2(ax: f32, ay: f32, bx: f32, by: f32) -> f32 {
+    let dx = ax - bx;
+    let dy = ay - by;
+    dx * dx + dy * dy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    /// The test map with team 1 spawning at `spawn` and team 2 well inside the map.
+    fn map(spawn: (f32, f32)) -> GameMap {
+        let mut map = test_util::map(Vec::new());
+        (map.team1.spawn_x, map.team1.spawn_y) = spawn;
+        (map.team2.spawn_x, map.team2.spawn_y) = (700.0, 500.0);
+        map
+    }
+
+    fn ring_warnings(map: &GameMap) -> usize {
+        validate_map(map)
+            .warnings()
+            .filter(|w| w.message.contains("players around it"))
+            .count()
+    }
+
+    #[test]
+    fn cramped_spawn_ring_is_reported() {
+        assert_eq!(ring_warnings(&map((300.0, 500.0))), 0);
+        // the spawn itself fits, but half of the ring around it is off the map
+        assert_eq!(ring_warnings(&map((20.0, 500.0))), 1);
+    }
+
+    #[test]
+    fn degenerate_polygons_are_errors() {
+        let polygon = |corners: &[(f32, f32)]| {
+            let mut map = map((300.0, 500.0));
+            map.objects.push(MapObject::Polygon {
+                points: corners.iter().map(|&(x, y)| MapPoint { x, y }).collect(),
+                factor: 1.0,
+                color: test_util::color(),
+                is_hole: false,
+                mask: Vec::new(),
+            });
+            validate_map(&map).has_errors()
+        };
+        assert!(!polygon(&[(500.0, 100.0), (600.0, 100.0), (600.0, 200.0)]));
+        assert!(polygon(&[(500.0, 100.0), (600.0, 100.0)]));
+        // all corners on one line
+        assert!(polygon(&[(500.0, 100.0), (550.0, 100.0), (600.0, 100.0)]));
+        // a bow tie
+        assert!(polygon(&[
+            (500.0, 100.0),
+            (600.0, 200.0),
+            (600.0, 100.0),
+            (500.0, 200.0)
+        ]));
+
+        // corners along a parabola make a convex polygon of any size
+        let convex = |corners: usize| -> Vec<(f32, f32)> {
+            (0..corners)
+                .map(|i| {
+                    let x = i as f32;
+                    (100.0 + x, 100.0 + x * x / 256.0)
+                })
+                .collect()
+        };
+        assert!(!polygon(&convex(MAX_POLYGON_CORNERS)));
+        assert!(polygon(&convex(MAX_POLYGON_CORNERS + 1)));
+    }
+}
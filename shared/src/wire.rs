@@ -0,0 +1,295 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::{de::DeserializeOwned, Deserialize, Serialize, Serializer};
+
+use crate::delta::WorldDelta;
+use crate::*;
+
+/// Query parameter the client appends to the WebSocket URL to pick an encoding,
+/// e.g. `ws://host:9001/?wire=binary`.
+pub const WIRE_QUERY_KEY: &str = "wire";
+
+/// Query parameter a reconnecting client appends to the WebSocket URL to get
+/// back the player of its earlier connection, e.g. `?wire=json&resume=TOKEN`
+/// with the token from `ServerMessage::AssignId`. Unlike a later
+/// `ClientMessage::Resume`, it works even when the default room is full.
+pub const RESUME_QUERY_KEY: &str = "resume";
+
+/// Value of `key` in a URL query string such as `wire=binary&foo=bar`.
+pub fn query_param<'a>(query: Option<&'a str>, key: &str) -> Option<&'a str> {
+    query
+        .into_iter()
+        .flat_map(|q| q.split('&'))
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, value)| value)
+}
+
+/// Encoding used for `ClientMessage`/`ServerMessage` on a connection.
+///
+/// JSON travels in text frames and is meant for debugging; the binary encoding
+/// (postcard) travels in binary frames, see `BinaryMessage`. Receivers decode by
+/// frame type, so a peer that ignores the negotiated format is still understood.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WireFormat {
+    #[default]
+    Json,
+    Binary,
+}
+
+impl WireFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WireFormat::Json => "json",
+            WireFormat::Binary => "binary",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "json" => Some(WireFormat::Json),
+            "binary" => Some(WireFormat::Binary),
+            _ => None,
+        }
+    }
+
+    /// Picks the format out of a URL query string such as `wire=binary&foo=bar`.
+    /// Falls back to JSON when the parameter is missing or unknown.
+    pub fn from_query(query: Option<&str>) -> Self {
+        query_param(query, WIRE_QUERY_KEY)
+            .and_then(Self::parse)
+            .unwrap_or_default()
+    }
+}
+
+pub fn encode_binary<T: Serialize>(msg: &T) -> Result<Vec<u8>, postcard::Error> {
+    postcard::to_allocvec(msg)
+}
+
+pub fn decode_binary<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, postcard::Error> {
+    postcard::from_bytes(bytes)
+}
+
+/// A message enum that can travel in binary frames.
+///
+/// The message enums are internally tagged so their JSON reads
+/// `{"type": "Input", ...}`, but postcard cannot decode internally tagged
+/// enums. Binary frames therefore go through the externally tagged mirrors
+/// below, which serde keeps in step with the real enums: a variant missing
+/// from a mirror does not compile.
+pub trait BinaryMessage: Sized {
+    fn to_binary(&self) -> Result<Vec<u8>, postcard::Error>;
+    fn from_binary(bytes: &[u8]) -> Result<Self, postcard::Error>;
+}
+
+macro_rules! binary_message {
+    ($message:ty, $mirror:ident) => {
+        impl BinaryMessage for $message {
+            fn to_binary(&self) -> Result<Vec<u8>, postcard::Error> {
+                struct Mirrored<'a>(&'a $message);
+                impl Serialize for Mirrored<'_> {
+                    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                        $mirror::serialize(self.0, serializer)
+                    }
+                }
+                encode_binary(&Mirrored(self))
+            }
+
+            fn from_binary(bytes: &[u8]) -> Result<Self, postcard::Error> {
+                $mirror::deserialize(&mut postcard::Deserializer::from_bytes(bytes))
+            }
+        }
+    };
+}
+
+binary_message!(ClientMessage, ClientMessageDef);
+binary_message!(ServerMessage, ServerMessageDef);
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "ClientMessage")]
+enum ClientMessageDef {
+    Input {
+        seq: u32,
+        left: bool,
+        right: bool,
+        shoot: bool,
+    },
+    Ping {
+        ts: u64,
+    },
+    Command {
+        #[serde(with = "CommandDef")]
+        cmd: Command,
+    },
+    Ack {
+        seq: u64,
+    },
+    Chat {
+        channel: ChatChannel,
+        text: String,
+    },
+    Resume {
+        token: String,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Command")]
+enum CommandDef {
+    Start {
+        score_limit: Option<u8>,
+        time_limit_secs: Option<u32>,
+    },
+    Stop,
+    Pause,
+    Resume,
+    LoadMap {
+        data: String,
+    },
+    JoinAsPlayer {
+        team: Option<Team>,
+    },
+    JoinAsSpectator,
+    SetNick {
+        nick: String,
+    },
+    SetColorDef {
+        color: ColorDef,
+        team: Team,
+    },
+    SetPhysicsSettings {
+        settings: PhysicsSettings,
+    },
+    SetGameMode {
+        game_mode: GameMode,
+        action_target_time: Option<f32>,
+    },
+    CreateRoom {
+        name: String,
+        host_password: Option<String>,
+    },
+    ListRooms,
+    JoinRoom {
+        name: String,
+    },
+    LeaveRoom,
+    ClaimHost {
+        password: String,
+    },
+    SetAdmin {
+        player_id: String,
+        admin: bool,
+    },
+    AddBot {
+        team: Team,
+        difficulty: BotDifficulty,
+    },
+    RemoveBot {
+        player_id: String,
+    },
+    ListMaps,
+    SelectMap {
+        name: String,
+    },
+    GetLeaderboard,
+    ShuffleTeams {
+        by_rating: bool,
+    },
+    SetScript {
+        source: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "ServerMessage")]
+enum ServerMessageDef {
+    AssignId {
+        id: String,
+        token: String,
+    },
+    ServerInfo {
+        name: String,
+        tick_rate: f32,
+    },
+    WorldState {
+        world: WorldState,
+    },
+    WorldDelta {
+        delta: WorldDelta,
+    },
+    PhysicsSettings {
+        settings: PhysicsSettings,
+    },
+    Map {
+        map: GameMap,
+    },
+    Pong {
+        ts: u64,
+    },
+    RoomList {
+        rooms: Vec<RoomInfo>,
+    },
+    RoomJoined {
+        name: String,
+    },
+    MapList {
+        maps: Vec<MapInfo>,
+    },
+    GameEvent {
+        event: GameEvent,
+    },
+    Leaderboard {
+        entries: Vec<LeaderboardEntry>,
+    },
+    MatchSummary {
+        summary: MatchSummary,
+    },
+    Chat {
+        from: String,
+        nick: String,
+        channel: ChatChannel,
+        text: String,
+    },
+    Error {
+        code: ErrorCode,
+        message: String,
+    },
+    CommandAck {
+        command: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_survive_binary_frames() {
+        let msg = ClientMessage::Command {
+            cmd: Command::AddBot {
+                team: Team::Team2,
+                difficulty: BotDifficulty::Hard,
+            },
+        };
+        let decoded = ClientMessage::from_binary(&msg.to_binary().unwrap()).unwrap();
+        assert!(matches!(
+            decoded,
+            ClientMessage::Command {
+                cmd: Command::AddBot {
+                    team: Team::Team2,
+                    difficulty: BotDifficulty::Hard,
+                },
+            }
+        ));
+
+        let msg = ServerMessage::Error {
+            code: ErrorCode::NotFound,
+            message: "Room x does not exist".into(),
+        };
+        let decoded = ServerMessage::from_binary(&msg.to_binary().unwrap()).unwrap();
+        assert!(matches!(
+            decoded,
+            ServerMessage::Error { code: ErrorCode::NotFound, message } if message == "Room x does not exist"
+        ));
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+
+use glam::Vec2;
+use spin_snowball_shared::*;
+
+/// Side of a cell of the map index. Bodies are much smaller, so a query
+/// touches only a few cells.
+const MAP_CELL_SIZE: f32 = 64.0;
+/// Huge maps get larger cells instead of more of them.
+const MAX_MAP_CELLS_PER_SIDE: f32 = 256.0;
+
+/// Uniform grid over moving bodies, rebuilt every pass. With cells at least
+/// as large as the contact distance, two bodies can only touch if their
+/// cells are neighbours.
+pub(crate) struct Grid {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl Grid {
+    /// A grid for bodies that touch within `contact_distance` of each other.
+    pub(crate) fn new(contact_distance: f32) -> Self {
+        Self {
+            cell_size: contact_distance.max(1.0),
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell(&self, pos: Vec2) -> (i32, i32) {
+        // kept off the ends of i32 so the neighbours in `near` never overflow;
+        // bodies that far out just share the outermost cells
+        let limit = i32::MAX - 1;
+        (
+            ((pos.x / self.cell_size).floor() as i32).clamp(-limit, limit),
+            ((pos.y / self.cell_size).floor() as i32).clamp(-limit, limit),
+        )
+    }
+
+    /// Adds the body `index`; indices are expected to be added in ascending order.
+    pub(crate) fn insert(&mut self, index: usize, pos: Vec2) {
+        let cell = self.cell(pos);
+        self.cells.entry(cell).or_default().push(index);
+    }
+
+    /// Bodies that may touch one at `pos`, in ascending order.
+    pub(crate) fn near(&self, pos: Vec2, out: &mut Vec<usize>) {
+        out.clear();
+        let (cx, cy) = self.cell(pos);
+        for x in cx - 1..=cx + 1 {
+            for y in cy - 1..=cy + 1 {
+                if let Some(cell) = self.cells.get(&(x, y)) {
+                    out.extend_from_slice(cell);
+                }
+            }
+        }
+        out.sort_unstable();
+    }
+}
+
+/// Pairs `(a, b)` with `a < b` of the bodies at `positions` that may touch,
+/// ordered by `b` and then `a`, the order an all-pairs loop would visit them in.
+pub(crate) fn candidate_pairs(positions: &[Vec2], contact_distance: f32) -> Vec<(usize, usize)> {
+    let mut grid = Grid::new(contact_distance);
+    for (i, pos) in positions.iter().enumerate() {
+        grid.insert(i, *pos);
+    }
+
+    let mut pairs = Vec::new();
+    let mut near = Vec::new();
+    for (b, pos) in positions.iter().enumerate() {
+        grid.near(*pos, &mut near);
+        pairs.extend(near.iter().take_while(|a| **a < b).map(|a| (*a, b)));
+    }
+    pairs
+}
+
+/// Static index over the objects of a map, built once when the map is set.
+/// Each object is listed in every cell its bounding box overlaps; the grid
+/// covers the map, and objects reaching past its edges go in the edge cells.
+pub(crate) struct MapIndex {
+    cell_size: f32,
+    cols: i32,
+    rows: i32,
+    cells: Vec<Vec<usize>>,
+}
+
+impl MapIndex {
+    pub(crate) fn new(map: &GameMap) -> Self {
+        let cell_size = MAP_CELL_SIZE.max(map.width.max(map.height) / MAX_MAP_CELLS_PER_SIDE);
+        let cols = ((map.width / cell_size).ceil() as i32).max(1);
+        let rows = ((map.height / cell_size).ceil() as i32).max(1);
+        let mut index = Self {
+            cell_size,
+            cols,
+            rows,
+            cells: vec![Vec::new(); (cols * rows) as usize],
+        };
+        for (i, obj) in map.objects.iter().enumerate() {
+            let (min, max) = bounds(obj);
+            let ((x0, y0), (x1, y1)) = (index.cell(min), index.cell(max));
+            for y in y0..=y1 {
+                for x in x0..=x1 {
+                    index.cells[(y * cols + x) as usize].push(i);
+                }
+            }
+        }
+        index
+    }
+
+    fn cell(&self, pos: Vec2) -> (i32, i32) {
+        // NaN positions end up in cell 0, the sanity check puts those bodies back later
+        (
+            ((pos.x / self.cell_size).floor() as i32).clamp(0, self.cols - 1),
+            ((pos.y / self.cell_size).floor() as i32).clamp(0, self.rows - 1),
+        )
+    }
+
+    /// Objects whose bounding box may overlap the circle at `pos`, in map order.
+    pub(crate) fn query(&self, pos: Vec2, radius: f32, out: &mut Vec<usize>) {
+        out.clear();
+        let (x0, y0) = self.cell(pos - Vec2::splat(radius));
+        let (x1, y1) = self.cell(pos + Vec2::splat(radius));
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                out.extend_from_slice(&self.cells[(y * self.cols + x) as usize]);
+            }
+        }
+        out.sort_unstable();
+        out.dedup();
+    }
+}
+
+/// Corners of the box around `obj`.
+fn bounds(obj: &MapObject) -> (Vec2, Vec2) {
+    match obj {
+        MapObject::Circle { x, y, radius, .. } => {
+            let center = Vec2::new(*x, *y);
+            (center - Vec2::splat(*radius), center + Vec2::splat(*radius))
+        }
+        MapObject::Rect { x, y, w, h, .. } => {
+            let a = Vec2::new(*x, *y);
+            let b = Vec2::new(x + w, y + h);
+            (a.min(b), a.max(b))
+        }
+        MapObject::Line { ax, ay, bx, by, .. } => {
+            let a = Vec2::new(*ax, *ay);
+            let b = Vec2::new(*bx, *by);
+            (a.min(b), a.max(b))
+        }
+        MapObject::Polygon { points, .. } => points.iter().fold(
+            (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+            |(min, max), p| {
+                let p = Vec2::new(p.x, p.y);
+                (min.min(p), max.max(p))
+            },
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spin_snowball_shared::test_util::{color, map};
+
+    /// Deterministic points spread over `size`, without pulling in a random crate.
+    fn scatter(count: usize, size: f32) -> Vec<Vec2> {
+        let mut state = 0x2545_f491_u32;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as f32 / u32::MAX as f32 * size
+        };
+        (0..count).map(|_| Vec2::new(next(), next())).collect()
+    }
+
+    #[test]
+    fn candidate_pairs_match_all_pairs() {
+        let positions = scatter(300, 1000.0);
+        let contact = 36.0;
+        let brute: Vec<(usize, usize)> = (0..positions.len())
+            .flat_map(|b| (0..b).map(move |a| (a, b)))
+            .filter(|&(a, b)| positions[a].distance(positions[b]) < contact)
+            .collect();
+        let found: Vec<(usize, usize)> = candidate_pairs(&positions, contact)
+            .into_iter()
+            .filter(|&(a, b)| positions[a].distance(positions[b]) < contact)
+            .collect();
+        assert!(!brute.is_empty());
+        assert_eq!(found, brute);
+    }
+
+    #[test]
+    fn far_away_bodies_do_not_overflow_the_grid() {
+        let positions = [
+            Vec2::new(f32::MAX, f32::MAX),
+            Vec2::new(f32::MAX, f32::MAX),
+            Vec2::new(f32::MIN, 0.0),
+        ];
+        assert_eq!(candidate_pairs(&positions, 36.0), vec![(0, 1)]);
+    }
+
+    #[test]
+    fn map_index_finds_every_object_in_range() {
+        let corners = scatter(120, 1000.0);
+        let objects: Vec<MapObject> = corners
+            .chunks(3)
+            .enumerate()
+            .map(|(i, c)| match i % 4 {
+                0 => MapObject::Circle {
+                    x: c[0].x,
+                    y: c[0].y,
+                    radius: c[1].x / 20.0,
+                    factor: 1.0,
+                    color: color(),
+                    is_hole: false,
+                    mask: Vec::new(),
+                },
+                1 => MapObject::Rect {
+                    x: c[0].x,
+                    y: c[0].y,
+                    w: c[1].x / 10.0 - 50.0,
+                    h: c[1].y / 10.0,
+                    factor: 1.0,
+                    color: color(),
+                    is_hole: false,
+                    mask: Vec::new(),
+                },
+                2 => MapObject::Line {
+                    ax: c[0].x,
+                    ay: c[0].y,
+                    bx: c[1].x,
+                    by: c[1].y,
+                    factor: 1.0,
+                    color: color(),
+                    is_hole: false,
+                    mask: Vec::new(),
+                },
+                _ => MapObject::Polygon {
+                    points: c.iter().map(|p| MapPoint { x: p.x, y: p.y }).collect(),
+                    factor: 1.0,
+                    color: color(),
+                    is_hole: false,
+                    mask: Vec::new(),
+                },
+            })
+            .collect();
+        let map = map(objects);
+        let index = MapIndex::new(&map);
+
+        let mut found = Vec::new();
+        // queries past the map edges too, where bodies can end up before the sanity check
+        for pos in scatter(500, 1200.0) {
+            let pos = pos - Vec2::splat(100.0);
+            let radius = 18.0;
+            index.query(pos, radius, &mut found);
+            let brute: Vec<usize> = map
+                .objects
+                .iter()
+                .enumerate()
+                .filter(|(_, obj)| {
+                    let (min, max) = bounds(obj);
+                    (pos + radius).cmpge(min).all() && (pos - radius).cmple(max).all()
+                })
+                .map(|(i, _)| i)
+                .collect();
+            assert!(found.windows(2).all(|w| w[0] < w[1]));
+            assert!(
+                brute.iter().all(|i| found.contains(i)),
+                "query at {pos} found {found:?}, expected all of {brute:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! Game simulation shared by the server, client-side prediction and bots.
+//!
+//! `World` holds the moving parts of a match and `World::step` advances them by
+//! one tick. The server drives it at `TICK_HZ`; other callers that run at a
+//! different frame rate can use `FixedTimestep` to step it the same way.
+
+mod broadphase;
+mod physics;
+mod rules;
+mod spawn;
+mod world;
+
+pub use physics::{simulate_collisions, simulate_movement, SimulateCollisionResponse};
+pub use rules::GameModeRules;
+pub use world::{Ball, Player, Snowball, World};
+
+pub const TICK_HZ: f32 = 30.0;
+pub const DT: f32 = 1.0 / TICK_HZ;
+
+/// Turns variable frame times into a whole number of fixed simulation steps.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedTimestep {
+    dt: f32,
+    accumulator: f32,
+}
+
+impl Default for FixedTimestep {
+    fn default() -> Self {
+        Self::new(DT)
+    }
+}
+
+impl FixedTimestep {
+    pub fn new(dt: f32) -> Self {
+        Self {
+            dt,
+            accumulator: 0.0,
+        }
+    }
+
+    pub fn dt(&self) -> f32 {
+        self.dt
+    }
+
+    /// Adds `frame_dt` and returns how many steps of `dt` are now due.
+    pub fn advance(&mut self, frame_dt: f32) -> u32 {
+        self.accumulator += frame_dt;
+        let mut steps = 0;
+        while self.accumulator >= self.dt {
+            self.accumulator -= self.dt;
+            steps += 1;
+        }
+        steps
+    }
+
+    pub fn reset(&mut self) {
+        self.accumulator = 0.0;
+    }
+}
+
@@ -1,14 +1,46 @@
 use glam::Vec2;
 use spin_snowball_shared::*;
 
-use crate::{Ball, GameState};
-
-pub(crate) struct SimulateCollisionResponse {
-    pub(crate) players_in_holes: Vec<String>,
-    pub(crate) snowballs_in_holes: Vec<u64>,
-    pub(crate) ball_in_goal_of_team: Option<Team>,
-    pub(crate) ball_touched_by_player: Option<(String, Team)>,
-    pub(crate) players_hit_by_snowball: Vec<String>
+use crate::broadphase::{candidate_pairs, Grid};
+use crate::world::MAX_SPEED;
+use crate::{Ball, World};
+
+/// Bodies smaller than this are sized as if they had this radius when passes
+/// are counted, so a zero radius cannot ask for endless passes.
+const MIN_PASS_RADIUS: f32 = 1.0;
+
+/// What happened during one `simulate_collisions` pass, for the mode rules to act on.
+#[derive(Default)]
+pub struct SimulateCollisionResponse {
+    pub players_in_holes: Vec<String>,
+    pub snowballs_in_holes: Vec<u64>,
+    pub ball_in_goal_of_team: Option<Team>,
+    pub ball_touched_by_player: Option<(String, Team)>,
+    /// Player hit and the player who threw the snowball.
+    pub players_hit_by_snowball: Vec<(String, String)>
+}
+
+impl SimulateCollisionResponse {
+    /// Adds what a later pass of the same tick reported. A body reported by
+    /// several passes is only listed once, and the first goal counts.
+    pub(crate) fn merge(&mut self, later: SimulateCollisionResponse) {
+        for id in later.players_in_holes {
+            if !self.players_in_holes.contains(&id) {
+                self.players_in_holes.push(id);
+            }
+        }
+        for id in later.snowballs_in_holes {
+            if !self.snowballs_in_holes.contains(&id) {
+                self.snowballs_in_holes.push(id);
+            }
+        }
+        self.ball_in_goal_of_team = self.ball_in_goal_of_team.or(later.ball_in_goal_of_team);
+        if later.ball_touched_by_player.is_some() {
+            self.ball_touched_by_player = later.ball_touched_by_player;
+        }
+        self.players_hit_by_snowball
+            .extend(later.players_hit_by_snowball);
+    }
 }
 
 trait Body {
@@ -84,7 +116,7 @@ impl Body for Ball {
     }
 }
 
-pub(crate) fn simulate_movement(game_state: &mut GameState, dt: f32) {
+pub fn simulate_movement(game_state: &mut World, dt: f32) {
     for (_id, p) in game_state.players.iter_mut() {
         let max_charge_time = 1.0;
         let base_rot_speed = 180.0;     
@@ -112,7 +144,9 @@ pub(crate) fn simulate_movement(game_state: &mut GameState, dt: f32) {
     }
 
     for (_id, s) in game_state.snowballs.iter_mut() {
-        s.pos += s.vel * dt;
+        // snowballs cover twice their velocity each second, which the throw
+        // speeds are tuned for
+        s.pos += s.vel * (2.0 * dt);
     }
 
     if let Some(ball) = &mut game_state.ball {
@@ -124,7 +158,33 @@ pub(crate) fn simulate_movement(game_state: &mut GameState, dt: f32) {
     }
 }
 
-pub fn simulate_collisions(game_state: &mut GameState) -> SimulateCollisionResponse {
+/// How many movement and collision passes `dt` is split into at the current
+/// velocities, so that nothing moves further than its own radius in one pass.
+/// Fast snowballs and the ball then touch every line and body in their way
+/// instead of skipping past them. Speeds count up to `MAX_SPEED`; anything
+/// faster is put back by the sanity check after the tick.
+pub(crate) fn substeps(game_state: &World, dt: f32) -> u32 {
+    let physics = &game_state.map.physics;
+    let radii_per_sec =
+        |speed: f32, radius: f32| speed.clamp(0.0, MAX_SPEED) / radius.max(MIN_PASS_RADIUS);
+    let players = game_state
+        .players
+        .values()
+        .map(|p| radii_per_sec(p.vel.length(), physics.player_radius));
+    let snowballs = game_state
+        .snowballs
+        .values()
+        .map(|s| 2.0 * radii_per_sec(s.vel.length(), physics.snowball_radius));
+    let ball = game_state
+        .ball
+        .iter()
+        .map(|b| radii_per_sec(b.vel.length(), physics.ball_radius));
+    let radii = players.chain(snowballs).chain(ball).fold(0.0, f32::max) * dt;
+    // NaN velocities give 0 here, the sanity checks deal with those bodies
+    (radii.ceil() as u32).max(1)
+}
+
+pub fn simulate_collisions(game_state: &mut World) -> SimulateCollisionResponse {
     let mut response = SimulateCollisionResponse {
         players_in_holes: vec![],
         snowballs_in_holes: vec![],
@@ -140,206 +200,218 @@ pub fn simulate_collisions(game_state: &mut GameState) -> SimulateCollisionRespo
     response
 }
 
-fn simulate_player_player_collisions(game_state: &mut GameState) {
-    // Collect ids to avoid borrowing issues when iterating map
-    let player_ids: Vec<String> = game_state.players.keys().cloned().collect();
-
-    for i in 0..player_ids.len() {
-        for j in (i + 1)..player_ids.len() {
-            let id_a = &player_ids[i];
-            let id_b = &player_ids[j];
-
-            if let [Some(a), Some(b)] = game_state.players.get_disjoint_mut([id_a, id_b]) {
-                resolve_circle_circle(
-                    a,
-                    b,
-                    game_state.map.physics.player_bounciness,
-                    &game_state.map.physics,
-                );
-            }
-        }
+fn simulate_player_player_collisions(game_state: &mut World) {
+    let physics = &game_state.map.physics;
+    // Pairs are visited in id order, which keeps the outcome deterministic
+    let mut players: Vec<&mut crate::Player> = game_state.players.values_mut().collect();
+    let positions: Vec<Vec2> = players.iter().map(|p| p.pos).collect();
+
+    for (a, b) in candidate_pairs(&positions, physics.player_radius * 2.0) {
+        let (before, rest) = players.split_at_mut(b);
+        resolve_circle_circle(
+            &mut *before[a],
+            &mut *rest[0],
+            physics.player_bounciness,
+            physics,
+        );
     }
 }
 
-fn simulate_player_snowball_collisions(game_state: &mut GameState, response: &mut SimulateCollisionResponse) {
-    let player_ids: Vec<String> = game_state.players.keys().cloned().collect();
-    let snow_ids: Vec<u64> = game_state.snowballs.keys().cloned().collect();
-
-    for pid in player_ids.iter() {
-        for sid in snow_ids.iter() {
-            // We need mutable refs to a player and a snowball. They live in different hashmaps,
-            // so two mutable borrows are fine.
-            // However, since we earlier collected ids, they are guaranteed distinct & stable.
-            let player_exists = game_state.players.contains_key(pid);
-            let snow_exists = game_state.snowballs.contains_key(sid);
-            if !player_exists || !snow_exists {
-                continue;
-            }
+fn simulate_player_snowball_collisions(game_state: &mut World, response: &mut SimulateCollisionResponse) {
+    let physics = &game_state.map.physics;
+    let mut snowballs: Vec<&mut crate::Snowball> = game_state.snowballs.values_mut().collect();
+    let mut grid = Grid::new(physics.player_radius + physics.snowball_radius);
+    for (i, s) in snowballs.iter().enumerate() {
+        grid.insert(i, s.pos);
+    }
 
-            // Re-borrow concrete types (can't hold both as &mut dyn Body across scope easily)
-            // Re-check existence then get mutable concrete references:
-            if let (Some(p_mut), Some(s_mut)) = (
-                game_state.players.get_mut(pid),
-                game_state.snowballs.get_mut(sid),
-            ) {
-                if resolve_circle_circle_custom_masses(
-                    p_mut,
-                    s_mut,
-                    game_state.map.physics.snowball_bounciness,
-                    &game_state.map.physics,
-                ) {
-                    response.players_hit_by_snowball.push(p_mut.id.clone());
-                }
+    let mut near = Vec::new();
+    for p in game_state.players.values_mut() {
+        grid.near(p.pos, &mut near);
+        for &i in &near {
+            let s = &mut *snowballs[i];
+            if resolve_circle_circle_custom_masses(p, s, physics.snowball_bounciness, physics)
+                && !s.hit_players.contains(&p.id)
+            {
+                s.hit_players.push(p.id.clone());
+                response
+                    .players_hit_by_snowball
+                    .push((p.id.clone(), s.owner.clone()));
             }
         }
     }
 }
 
-fn simulate_ball_collisions(game_state: &mut GameState, response: &mut SimulateCollisionResponse) {
+fn simulate_ball_collisions(game_state: &mut World, response: &mut SimulateCollisionResponse) {
     let ball = match game_state.ball.as_mut() {
         Some(b) => b,
         None => return,
     };
 
     let physics = &game_state.map.physics;
+    let carrier = game_state.rules.as_ref().and_then(|r| r.ball_carrier());
 
     for p in game_state.players.values_mut() {
-        if let Some((id, _)) = &game_state.player_with_active_action { //TODO remove
-            if p.id == *id {
-                continue;
-            }
+        if carrier == Some(p.id.as_str()) {
+            continue;
         }
         if resolve_circle_circle(p, ball, physics.ball_bounciness, physics) {
             if let PlayerStatus::Playing(team) = p.status {
-                response.ball_touched_by_player = Some((p.id.clone(), team))
+                response.ball_touched_by_player = Some((p.id.clone(), team));
+                ball.last_touch = Some(p.id.clone());
             }
         }
     }
 
     for s in game_state.snowballs.values_mut() {
-        resolve_circle_circle(s, ball, physics.ball_bounciness, physics);
+        if resolve_circle_circle(s, ball, physics.ball_bounciness, physics) {
+            ball.last_touch = Some(s.owner.clone());
+        }
     }
 }
 
-fn simulate_map_collisions(game_state: &mut GameState, response: &mut SimulateCollisionResponse) {
-    for (id, p) in game_state.players.iter_mut() {
-        handle_map_for_body_player(
-            p,
-            id,
-            &game_state.map.objects,
-            &game_state.map.physics,
-            response,
-        );
+fn simulate_map_collisions(game_state: &mut World, response: &mut SimulateCollisionResponse) {
+    let index = &game_state.map_index;
+    let objects = &game_state.map.objects;
+    // bodies are looked up with twice their radius, leaving room for the
+    // pushes of the objects resolved before
+    let mut near = Vec::new();
+
+    for p in game_state.players.values_mut() {
+        index.query(p.pos, game_state.map.physics.player_radius * 2.0, &mut near);
+        handle_map_for_body_player(p, objects, &near, &game_state.map.physics, response);
     }
 
-    let snow_ids: Vec<u64> = game_state.snowballs.keys().cloned().collect();
-    for sid in snow_ids.iter() {
-        // we need the original position for collision checks to avoid mutable borrow issues
-        if let Some(sb_snapshot) = game_state.snowballs.get(sid).map(|s| s.pos) {
-            for obj in &game_state.map.objects {
-                let mask = match obj {
-                    MapObject::Circle { mask, .. } | MapObject::Rect { mask, .. } | MapObject::Line { mask, .. } => mask,
-                };
-                if !matches_snowball(&mask) {
-                    continue;
+    for (sid, sb) in game_state.snowballs.iter_mut() {
+        // collisions are checked against the position before this pass
+        let sb_snapshot = sb.pos;
+        index.query(
+            sb_snapshot,
+            game_state.map.physics.snowball_radius * 2.0,
+            &mut near,
+        );
+        for obj in near.iter().map(|i| &objects[*i]) {
+            let mask = match obj {
+                MapObject::Circle { mask, .. } | MapObject::Rect { mask, .. } | MapObject::Line { mask, .. } | MapObject::Polygon { mask, .. } => mask,
+            };
+            if !matches_snowball(&mask) {
+                continue;
+            }
+            match obj {
+                MapObject::Circle {
+                    x,
+                    y,
+                    radius,
+                    factor,
+                    color: _,
+                    is_hole,
+                    mask: _,
+                } => {
+                    if circle_intersects_circle(
+                        sb_snapshot.x,
+                        sb_snapshot.y,
+                        game_state.map.physics.snowball_radius,
+                        *x,
+                        *y,
+                        *radius,
+                    ) {
+                        if *is_hole {
+                            response.snowballs_in_holes.push(*sid);
+                        } else {
+                            let delta = sb_snapshot - Vec2::new(*x, *y);
+                            let dist = delta.length().max(0.0001);
+                            let n = delta / dist;
+                            sb.pos = Vec2::new(*x, *y)
+                                + n * (*radius + game_state.map.physics.snowball_radius);
+                            sb.vel = sb.vel - 2.0 * sb.vel.dot(n) * n * (*factor);
+                        }
+                    }
                 }
-                match obj {
-                    MapObject::Circle {
-                        x,
-                        y,
-                        radius,
-                        factor,
-                        color: _,
-                        is_hole,
-                        mask: _,
-                    } => {
-                        if circle_intersects_circle(
-                            sb_snapshot.x,
-                            sb_snapshot.y,
-                            game_state.map.physics.snowball_radius,
-                            *x,
-                            *y,
-                            *radius,
-                        ) {
-                            if *is_hole {
-                                response.snowballs_in_holes.push(*sid);
-                            } else if let Some(sbm) = game_state.snowballs.get_mut(sid) {
-                                let delta = sb_snapshot - Vec2::new(*x, *y);
-                                let dist = delta.length().max(0.0001);
-                                let n = delta / dist;
-                                sbm.pos = Vec2::new(*x, *y)
-                                    + n * (*radius + game_state.map.physics.snowball_radius);
-                                sbm.vel = sbm.vel - 2.0 * sbm.vel.dot(n) * n * (*factor);
+                MapObject::Rect {
+                    x,
+                    y,
+                    w,
+                    h,
+                    factor,
+                    color: _,
+                    is_hole,
+                    mask: _,
+                } => {
+                    if circle_intersects_rect(
+                        sb_snapshot.x,
+                        sb_snapshot.y,
+                        game_state.map.physics.snowball_radius,
+                        *x,
+                        *y,
+                        *w,
+                        *h,
+                    ) {
+                        if *is_hole {
+                            response.snowballs_in_holes.push(*sid);
+                        } else {
+                            let cx = sb_snapshot.x.clamp(*x, x + w);
+                            let cy = sb_snapshot.y.clamp(*y, y + h);
+                            let mut n = sb_snapshot - Vec2::new(cx, cy);
+                            if n.length_squared() < 1e-6 {
+                                n = Vec2::new(
+                                    (sb_snapshot.x - (x + w / 2.0)).signum(),
+                                    (sb_snapshot.y - (y + h / 2.0)).signum(),
+                                );
                             }
+                            let n = n.normalize_or_zero();
+                            sb.pos += n * (game_state.map.physics.snowball_radius * 0.5 + 0.5);
+                            sb.vel = sb.vel - 2.0 * sb.vel.dot(n) * n * factor;
                         }
                     }
-                    MapObject::Rect {
-                        x,
-                        y,
-                        w,
-                        h,
-                        factor,
-                        color: _,
-                        is_hole,
-                        mask: _,
-                    } => {
-                        if circle_intersects_rect(
-                            sb_snapshot.x,
-                            sb_snapshot.y,
-                            game_state.map.physics.snowball_radius,
-                            *x,
-                            *y,
-                            *w,
-                            *h,
-                        ) {
-                            if *is_hole {
-                                response.snowballs_in_holes.push(*sid);
-                            } else if let Some(sbm) = game_state.snowballs.get_mut(sid) {
-                                let cx = sb_snapshot.x.clamp(*x, x + w);
-                                let cy = sb_snapshot.y.clamp(*y, y + h);
-                                let mut n = sb_snapshot - Vec2::new(cx, cy);
-                                if n.length_squared() < 1e-6 {
-                                    n = Vec2::new(
-                                        (sb_snapshot.x - (x + w / 2.0)).signum(),
-                                        (sb_snapshot.y - (y + h / 2.0)).signum(),
-                                    );
-                                }
-                                let n = n.normalize_or_zero();
-                                sbm.pos += n * (game_state.map.physics.snowball_radius * 0.5 + 0.5);
-                                sbm.vel = sbm.vel - 2.0 * sbm.vel.dot(n) * n * factor;
-                            }
+                }
+                MapObject::Line {
+                    ax,
+                    ay,
+                    bx,
+                    by,
+                    factor,
+                    color: _,
+                    is_hole,
+                    mask: _,
+                } => {
+                    let a = Vec2::new(*ax, *ay);
+                    let b = Vec2::new(*bx, *by);
+
+                    if let Some(_delta) = circle_intersects_line(
+                        sb_snapshot,
+                        game_state.map.physics.snowball_radius,
+                        a,
+                        b,
+                    ) {
+                        if *is_hole {
+                            response.snowballs_in_holes.push(*sid);
+                        } else {
+                            resolve_circle_line(
+                                sb,
+                                a,
+                                b,
+                                *factor,
+                                &game_state.map.physics,
+                            );
                         }
                     }
-                    MapObject::Line {
-                        ax,
-                        ay,
-                        bx,
-                        by,
-                        factor,
-                        color: _,
-                        is_hole,
-                        mask: _,
-                    } => {
-                        let a = Vec2::new(*ax, *ay);
-                        let b = Vec2::new(*bx, *by);
-
-                        if let Some(_delta) = circle_intersects_line(
-                            sb_snapshot,
-                            game_state.map.physics.snowball_radius,
-                            a,
-                            b,
-                        ) {
-                            if *is_hole {
-                                response.snowballs_in_holes.push(*sid);
-                            } else if let Some(sb) = game_state.snowballs.get_mut(sid) {
-                                resolve_circle_line(
-                                    sb,
-                                    a,
-                                    b,
-                                    *factor,
-                                    &game_state.map.physics,
-                                );
-                            }
+                }
+                MapObject::Polygon {
+                    points,
+                    factor,
+                    color: _,
+                    is_hole,
+                    mask: _,
+                } => {
+                    if circle_intersects_polygon(
+                        sb_snapshot,
+                        game_state.map.physics.snowball_radius,
+                        points,
+                    ) {
+                        if *is_hole {
+                            response.snowballs_in_holes.push(*sid);
+                        } else {
+                            resolve_circle_polygon(sb, points, *factor, &game_state.map.physics);
                         }
                     }
                 }
@@ -348,9 +420,17 @@ fn simulate_map_collisions(game_state: &mut GameState, response: &mut SimulateCo
     }
 
     if let Some(ball) = &mut game_state.ball {
-        for obj in &game_state.map.objects {
+        index.query(
+            ball.pos,
+            game_state.map.physics.ball_radius * 2.0,
+            &mut near,
+        );
+        for obj in near.iter().map(|i| &objects[*i]) {
             let mask = match obj {
-                MapObject::Circle { mask, .. } | MapObject::Rect { mask, .. } | MapObject::Line { mask, .. }  => mask,
+                MapObject::Circle { mask, .. }
+                | MapObject::Rect { mask, .. }
+                | MapObject::Line { mask, .. }
+                | MapObject::Polygon { mask, .. } => mask,
             };
             if !matches_ball(&mask) {
                 continue;
@@ -433,25 +513,33 @@ fn simulate_map_collisions(game_state: &mut GameState, response: &mut SimulateCo
                     let a = Vec2::new(*ax, *ay);
                     let b = Vec2::new(*bx, *by);
 
-                    if let Some(_delta) = circle_intersects_line(
-                        ball.pos,
-                        game_state.map.physics.ball_radius,
-                        a,
-                        b,
-                    ) {
+                    if let Some(_delta) =
+                        circle_intersects_line(ball.pos, game_state.map.physics.ball_radius, a, b)
+                    {
                         if !*is_hole {
-                            resolve_circle_line(
-                                ball,
-                                a,
-                                b,
-                                *factor,
-                                &game_state.map.physics,
-                            );
+                            resolve_circle_line(ball, a, b, *factor, &game_state.map.physics);
                         }
                     }
                 }
+                MapObject::Polygon {
+                    points,
+                    factor,
+                    color: _,
+                    is_hole,
+                    mask: _,
+                } => {
+                    if !*is_hole
+                        && circle_intersects_polygon(
+                            ball.pos,
+                            game_state.map.physics.ball_radius,
+                            points,
+                        )
+                    {
+                        resolve_circle_polygon(ball, points, *factor, &game_state.map.physics);
+                    }
+                }
             }
-            }
+        }
 
         if let Some(ball) = &mut game_state.ball {
             for goal in game_state.map.goals.iter() {
@@ -471,20 +559,21 @@ fn simulate_map_collisions(game_state: &mut GameState, response: &mut SimulateCo
     }
 }
 
+/// Resolves `player` against `candidates`, indices into `objects`.
 fn handle_map_for_body_player(
     player: &mut crate::Player,
-    id: &str,
     objects: &[MapObject],
+    candidates: &[usize],
     physics: &PhysicsSettings,
     response: &mut SimulateCollisionResponse,
 ) {
     let pos = player.pos;
-    for obj in objects {
+    for obj in candidates.iter().map(|i| &objects[*i]) {
         let mask = match obj {
-            MapObject::Circle { mask, .. } | MapObject::Rect { mask, .. } | MapObject::Line { mask, .. }  => mask,
+            MapObject::Circle { mask, .. } | MapObject::Rect { mask, .. } | MapObject::Line { mask, .. } | MapObject::Polygon { mask, .. } => mask,
         };
         let is_hole = match obj {
-            MapObject::Circle { is_hole, .. } | MapObject::Rect { is_hole, .. } | MapObject::Line { is_hole, .. }  => *is_hole,
+            MapObject::Circle { is_hole, .. } | MapObject::Rect { is_hole, .. } | MapObject::Line { is_hole, .. } | MapObject::Polygon { is_hole, .. } => *is_hole,
         };
         let team = match player.status {
             PlayerStatus::Spectator => continue,
@@ -591,6 +680,21 @@ fn handle_map_for_body_player(
                     }
                 }
             }
+            MapObject::Polygon {
+                points,
+                factor,
+                color: _,
+                is_hole,
+                mask: _,
+            } => {
+                if circle_intersects_polygon(player.pos, physics.player_radius, points) {
+                    if *is_hole {
+                        response.players_in_holes.push(player.id.clone());
+                    } else {
+                        resolve_circle_polygon(player, points, *factor, physics);
+                    }
+                }
+            }
         }
     }
 }
@@ -720,4 +824,77 @@ fn resolve_circle_line<B: Body>(
     if vn < 0.0 {
         *body.vel_mut() = v - 2.0 * vn * n * factor;
     }
-}
\ No newline at end of file
+}
+
+/// Closest point on the outline of the polygon `points` to `p`, with the
+/// outward normal of the edge it lies on. `None` for a polygon without edges.
+fn closest_point_on_polygon(p: Vec2, points: &[MapPoint]) -> Option<(Vec2, Vec2)> {
+    // the sign of the area tells which side of each edge is outside
+    let area: f32 = points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| a.x * b.y - b.x * a.y)
+        .sum();
+    let mut best: Option<(Vec2, Vec2)> = None;
+    for (a, b) in points.iter().zip(points.iter().cycle().skip(1)) {
+        let a = Vec2::new(a.x, a.y);
+        let b = Vec2::new(b.x, b.y);
+        if a == b {
+            continue;
+        }
+        let closest = closest_point_on_segment(p, a, b);
+        if best.is_none_or(|(c, _)| p.distance_squared(closest) < p.distance_squared(c)) {
+            let edge = (b - a).normalize();
+            let normal = if area > 0.0 {
+                Vec2::new(edge.y, -edge.x)
+            } else {
+                Vec2::new(-edge.y, edge.x)
+            };
+            best = Some((closest, normal));
+        }
+    }
+    best
+}
+
+#[inline]
+fn circle_intersects_polygon(p: Vec2, r: f32, points: &[MapPoint]) -> bool {
+    polygon_contains(points, p.x, p.y)
+        || closest_point_on_polygon(p, points).is_some_and(|(c, _)| p.distance_squared(c) < r * r)
+}
+
+/// Pushes `body` out of the polygon `points`, also when its center is already
+/// inside, and bounces it off the nearest edge.
+fn resolve_circle_polygon<B: Body>(
+    body: &mut B,
+    points: &[MapPoint],
+    factor: f32,
+    physics: &PhysicsSettings,
+) {
+    let r = body.radius(physics);
+    let pos = body.pos();
+    let Some((closest, edge_normal)) = closest_point_on_polygon(pos, points) else {
+        return;
+    };
+    let inside = polygon_contains(points, pos.x, pos.y);
+    let delta = pos - closest;
+    let dist = delta.length();
+    if !inside && dist >= r {
+        return;
+    }
+
+    let n = if dist <= 0.0001 {
+        edge_normal
+    } else if inside {
+        -delta / dist
+    } else {
+        delta / dist
+    };
+    let penetration = if inside { r + dist } else { r - dist };
+    *body.pos_mut() += n * penetration;
+
+    let v = body.vel();
+    let vn = v.dot(n);
+    if vn < 0.0 {
+        *body.vel_mut() = v - 2.0 * vn * n * factor;
+    }
+}
@@ -0,0 +1,70 @@
+use spin_snowball_shared::*;
+
+use super::{carry_ball, flag_spawn, return_ball, GameModeRules};
+use crate::physics::SimulateCollisionResponse;
+use crate::World;
+
+/// Pick up the flag and bring it into your own goal; any hit sends it back.
+#[derive(Default)]
+pub struct CaptureTheFlag {
+    carrier: Option<String>,
+}
+
+impl GameModeRules for CaptureTheFlag {
+    fn on_collisions(&mut self, response: &SimulateCollisionResponse, world: &mut World) {
+        if let Some((player_id, _)) = &response.ball_touched_by_player {
+            if self.carrier.is_none() {
+                self.carrier = Some(player_id.clone());
+                world.events.push(GameEvent::FlagPickedUp {
+                    player: player_id.clone(),
+                });
+            }
+        }
+
+        let Some(spawn) = flag_spawn(world) else {
+            return;
+        };
+        if !response.players_hit_by_snowball.is_empty() && world.ball.is_some() {
+            if let Some(carrier) = self.carrier.take() {
+                world
+                    .events
+                    .push(GameEvent::FlagDropped { player: carrier });
+                return_ball(world, spawn);
+            }
+        }
+
+        if let (Some(goal_team), Some(_)) = (response.ball_in_goal_of_team, &world.ball) {
+            if let Some(carrier) = self.carrier.clone() {
+                if world.get_team_of_player(&carrier) == Some(goal_team) {
+                    world.events.push(GameEvent::FlagCaptured {
+                        player: carrier.clone(),
+                        team: goal_team,
+                    });
+                    self.award_point(world, goal_team, Some(carrier));
+                    self.carrier = None;
+                    world.reset_positions();
+                }
+            }
+        }
+
+        if let Some(carrier) = &self.carrier {
+            carry_ball(world, carrier);
+        }
+    }
+
+    fn on_reset(&mut self) {
+        self.carrier = None;
+    }
+
+    fn ball_carrier(&self) -> Option<&str> {
+        self.carrier.as_deref()
+    }
+
+    fn hud(&self) -> ModeHud {
+        ModeHud {
+            holder: self.carrier.clone(),
+            timer: 0.0,
+            text: None,
+        }
+    }
+}
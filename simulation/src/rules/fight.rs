@@ -0,0 +1,32 @@
+use spin_snowball_shared::*;
+
+use super::GameModeRules;
+use crate::physics::SimulateCollisionResponse;
+use crate::World;
+
+/// Push the other team into the holes; every fall scores for the other side.
+pub struct Fight;
+
+impl GameModeRules for Fight {
+    fn on_collisions(&mut self, response: &SimulateCollisionResponse, world: &mut World) {
+        for id in response.players_in_holes.iter() {
+            if world.players.contains_key(id) {
+                world
+                    .events
+                    .push(GameEvent::PlayerFellInHole { player: id.clone() });
+                world.reset_positions();
+            }
+            if let Some(team) = world.get_team_of_player(id) {
+                let others: Vec<Team> = world
+                    .scores
+                    .keys()
+                    .filter(|t| **t != team)
+                    .copied()
+                    .collect();
+                for other in others {
+                    self.award_point(world, other, None);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use super::GameModeRules;
+use crate::physics::SimulateCollisionResponse;
+use crate::World;
+
+/// Push the ball into the goal; the last player to touch it gets the goal.
+pub struct Football;
+
+impl GameModeRules for Football {
+    fn on_collisions(&mut self, response: &SimulateCollisionResponse, world: &mut World) {
+        if let Some(scoring_team) = response.ball_in_goal_of_team {
+            let by = world.ball.as_ref().and_then(|b| b.last_touch.clone());
+            self.award_point(world, scoring_team, by);
+            world.reset_positions();
+        }
+    }
+}
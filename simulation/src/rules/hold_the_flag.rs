@@ -0,0 +1,87 @@
+use spin_snowball_shared::*;
+
+use super::{carry_ball, flag_spawn, return_ball, target_time, GameModeRules};
+use crate::physics::SimulateCollisionResponse;
+use crate::World;
+
+/// Hold on to the flag for the target time to score; a hit on the carrier
+/// sends it back.
+#[derive(Default)]
+pub struct HoldTheFlag {
+    carrier: Option<String>,
+    /// Seconds the carrier has held the flag since picking it up or last scoring.
+    held: f32,
+}
+
+impl GameModeRules for HoldTheFlag {
+    fn on_tick(&mut self, world: &mut World, dt: f32) {
+        let Some(carrier) = self.carrier.clone() else {
+            return;
+        };
+        let Some(team) = world.get_team_of_player(&carrier) else {
+            // the carrier left or became a spectator
+            self.carrier = None;
+            world
+                .events
+                .push(GameEvent::FlagDropped { player: carrier });
+            return;
+        };
+        self.held += dt;
+        if self.held >= target_time(world) {
+            self.award_point(world, team, Some(carrier));
+            self.held = 0.0;
+        }
+    }
+
+    fn on_collisions(&mut self, response: &SimulateCollisionResponse, world: &mut World) {
+        if let Some((player_id, _)) = &response.ball_touched_by_player {
+            if self.carrier.is_none() {
+                self.carrier = Some(player_id.clone());
+                self.held = 0.0;
+                world.events.push(GameEvent::FlagPickedUp {
+                    player: player_id.clone(),
+                });
+            }
+        }
+
+        let Some(spawn) = flag_spawn(world) else {
+            return;
+        };
+        if world.ball.is_some() {
+            let carrier_hit = self.carrier.as_ref().is_some_and(|carrier| {
+                response
+                    .players_hit_by_snowball
+                    .iter()
+                    .any(|(hit, _)| hit == carrier)
+            });
+            if carrier_hit {
+                if let Some(carrier) = self.carrier.take() {
+                    world
+                        .events
+                        .push(GameEvent::FlagDropped { player: carrier });
+                    return_ball(world, spawn);
+                }
+            }
+        }
+
+        if let Some(carrier) = &self.carrier {
+            carry_ball(world, carrier);
+        }
+    }
+
+    fn on_reset(&mut self) {
+        self.carrier = None;
+    }
+
+    fn ball_carrier(&self) -> Option<&str> {
+        self.carrier.as_deref()
+    }
+
+    fn hud(&self) -> ModeHud {
+        ModeHud {
+            holder: self.carrier.clone(),
+            timer: self.held,
+            text: None,
+        }
+    }
+}
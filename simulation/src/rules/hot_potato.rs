@@ -0,0 +1,41 @@
+use spin_snowball_shared::*;
+
+use super::{target_time, GameModeRules};
+use crate::physics::SimulateCollisionResponse;
+use crate::World;
+
+/// The goals only count once the fuse has burnt for the target time.
+#[derive(Default)]
+pub struct HotPotato {
+    fuse: f32,
+}
+
+impl GameModeRules for HotPotato {
+    fn on_tick(&mut self, _world: &mut World, dt: f32) {
+        self.fuse += dt;
+    }
+
+    fn on_collisions(&mut self, response: &SimulateCollisionResponse, world: &mut World) {
+        if self.fuse < target_time(world) {
+            return;
+        }
+        if let Some(goal_team) = response.ball_in_goal_of_team {
+            let by = world.ball.as_ref().and_then(|b| b.last_touch.clone());
+            self.award_point(world, goal_team, by);
+            self.fuse = 0.0;
+            world.reset_positions();
+        }
+    }
+
+    fn on_reset(&mut self) {
+        self.fuse = 0.0;
+    }
+
+    fn hud(&self) -> ModeHud {
+        ModeHud {
+            holder: None,
+            timer: self.fuse,
+            text: None,
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use spin_snowball_shared::*;
+
+use super::{target_time, GameModeRules};
+use crate::physics::SimulateCollisionResponse;
+use crate::World;
+
+/// Stay on the hill, the holes of the map, for the target time to score.
+#[derive(Default)]
+pub struct KingOfTheHill {
+    king: Option<String>,
+    /// Seconds the king has been on the hill.
+    held: f32,
+}
+
+impl GameModeRules for KingOfTheHill {
+    fn on_tick(&mut self, world: &mut World, dt: f32) {
+        let Some(king) = self.king.clone() else {
+            return;
+        };
+        let Some(team) = world.get_team_of_player(&king) else {
+            self.king = None;
+            world.events.push(GameEvent::KingChanged { king: None });
+            return;
+        };
+        self.held += dt;
+        if self.held >= target_time(world) {
+            self.award_point(world, team, Some(king));
+            world.events.push(GameEvent::KingChanged { king: None });
+            self.king = None;
+            world.reset_positions();
+        }
+    }
+
+    fn on_collisions(&mut self, response: &SimulateCollisionResponse, world: &mut World) {
+        let old_king = self.king.clone();
+        if let Some(king) = &self.king {
+            if !response.players_in_holes.contains(king) {
+                self.king = None;
+            }
+        }
+
+        if self.king.is_none() {
+            if let Some(player_id) = response.players_in_holes.first() {
+                self.king = Some(player_id.clone());
+                self.held = 0.0;
+            }
+        }
+
+        if self.king != old_king {
+            world.events.push(GameEvent::KingChanged {
+                king: self.king.clone(),
+            });
+        }
+    }
+
+    fn on_reset(&mut self) {
+        self.king = None;
+    }
+
+    fn hud(&self) -> ModeHud {
+        ModeHud {
+            holder: self.king.clone(),
+            timer: self.held,
+            text: None,
+        }
+    }
+}
@@ -0,0 +1,127 @@
+mod capture_the_flag;
+mod custom;
+mod fight;
+mod football;
+mod hold_the_flag;
+mod hot_potato;
+mod king_of_the_hill;
+mod race;
+mod shooter;
+
+use glam::Vec2;
+use spin_snowball_shared::*;
+
+use crate::physics::SimulateCollisionResponse;
+use crate::World;
+
+use capture_the_flag::CaptureTheFlag;
+use custom::Custom;
+use fight::Fight;
+use football::Football;
+use hold_the_flag::HoldTheFlag;
+use hot_potato::HotPotato;
+use king_of_the_hill::KingOfTheHill;
+use race::Race;
+use shooter::Shooter;
+
+/// Scoring and objective rules of one game mode, applied on top of the physics.
+///
+/// Every mode keeps its own state, e.g. who carries the flag. Each tick the
+/// world calls `on_tick`, runs the physics and then calls `on_collisions`.
+/// Hooks get the world without its rules, so a mode that calls
+/// `World::reset_positions` from a hook clears its own state as well.
+pub trait GameModeRules: Send {
+    /// Called when a match starts, after scores and positions are reset.
+    fn on_match_start(&mut self, _world: &mut World) {}
+
+    /// Called every tick before anything moves.
+    fn on_tick(&mut self, _world: &mut World, _dt: f32) {}
+
+    /// Reacts to what the physics reported this tick.
+    fn on_collisions(&mut self, _response: &SimulateCollisionResponse, _world: &mut World) {}
+
+    /// Gives `team` a point, `by` being the player who earned it if there is one.
+    fn award_point(&mut self, world: &mut World, team: Team, by: Option<String>) {
+        let score = world.scores.entry(team).or_insert(0);
+        *score = score.saturating_add(1);
+        world.events.push(GameEvent::GoalScored { team, by });
+    }
+
+    /// Called when everyone is sent back to their spawn from outside the
+    /// rules, e.g. on a map change.
+    fn on_reset(&mut self) {}
+
+    /// Whether the match is decided; by default once a team reaches the score limit.
+    fn is_over(&self, world: &World, score_limit: Option<u8>) -> bool {
+        score_limit.is_some_and(|limit| world.scores.values().any(|s| *s >= limit))
+    }
+
+    /// Player the ball sticks to. The physics lets them overlap it.
+    fn ball_carrier(&self) -> Option<&str> {
+        None
+    }
+
+    /// What the HUD shows about the objective.
+    fn hud(&self) -> ModeHud {
+        ModeHud::default()
+    }
+}
+
+/// Creates the rules of a mode with fresh state.
+type RulesFactory = fn() -> Box<dyn GameModeRules>;
+
+/// The rules every `GameMode` plays by. A new mode only adds its line here.
+const REGISTRY: &[(GameMode, RulesFactory)] = &[
+    (GameMode::Fight, || Box::new(Fight)),
+    (GameMode::Football, || Box::new(Football)),
+    (GameMode::Ctf, || Box::<CaptureTheFlag>::default()),
+    (GameMode::Htf, || Box::<HoldTheFlag>::default()),
+    (GameMode::KingOfTheHill, || Box::<KingOfTheHill>::default()),
+    (GameMode::Race, || Box::new(Race)),
+    (GameMode::HotPotato, || Box::<HotPotato>::default()),
+    (GameMode::Shooter, || Box::new(Shooter)),
+    (GameMode::Custom, || Box::new(Custom)),
+];
+
+/// Fresh rules for `mode`.
+pub fn rules_for(mode: GameMode) -> Box<dyn GameModeRules> {
+    let (_, factory) = REGISTRY
+        .iter()
+        .find(|(m, _)| *m == mode)
+        .expect("every game mode is registered");
+    factory()
+}
+
+/// Seconds the timed modes count to.
+fn target_time(world: &World) -> f32 {
+    world.action_target_time.unwrap_or(10.0)
+}
+
+/// Where the flag goes back to when its carrier is hit. `None` on a map
+/// without a ball, where the flag modes are meaningless.
+fn flag_spawn(world: &World) -> Option<Vec2> {
+    world
+        .map
+        .ball
+        .as_ref()
+        .map(|b| Vec2::new(b.spawn_x, b.spawn_y))
+}
+
+/// Keeps the ball, the flag of the flag modes, on its carrier.
+fn carry_ball(world: &mut World, carrier: &str) {
+    let Some(pos) = world.players.get(carrier).map(|p| p.pos) else {
+        return;
+    };
+    if let Some(ball) = &mut world.ball {
+        ball.pos = pos;
+        ball.vel = Vec2::ZERO;
+    }
+}
+
+/// Returns the flag to its spawn.
+fn return_ball(world: &mut World, spawn: Vec2) {
+    if let Some(ball) = &mut world.ball {
+        ball.pos = spawn;
+        ball.vel = Vec2::ZERO;
+    }
+}
@@ -0,0 +1,17 @@
+use super::GameModeRules;
+use crate::physics::SimulateCollisionResponse;
+use crate::World;
+
+/// First player into a hole scores for their team.
+pub struct Race;
+
+impl GameModeRules for Race {
+    fn on_collisions(&mut self, response: &SimulateCollisionResponse, world: &mut World) {
+        if let Some(player_id) = response.players_in_holes.first() {
+            if let Some(team) = world.get_team_of_player(player_id) {
+                self.award_point(world, team, Some(player_id.clone()));
+                world.reset_positions();
+            }
+        }
+    }
+}
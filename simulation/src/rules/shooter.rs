@@ -0,0 +1,26 @@
+use spin_snowball_shared::*;
+
+use super::GameModeRules;
+use crate::physics::SimulateCollisionResponse;
+use crate::World;
+
+/// Every snowball that hits a player scores for the other team.
+pub struct Shooter;
+
+impl GameModeRules for Shooter {
+    fn on_collisions(&mut self, response: &SimulateCollisionResponse, world: &mut World) {
+        for (hit_player_id, by) in &response.players_hit_by_snowball {
+            if let Some(hit_team) = world.get_team_of_player(hit_player_id) {
+                let others: Vec<Team> = world
+                    .scores
+                    .keys()
+                    .filter(|t| **t != hit_team)
+                    .copied()
+                    .collect();
+                for other in others {
+                    self.award_point(world, other, Some(by.clone()));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,156 @@
+use std::f32::consts::TAU;
+
+use glam::Vec2;
+use spin_snowball_shared::validation::spawn_blocked;
+use spin_snowball_shared::*;
+
+/// Players face up unless the map says otherwise.
+const DEFAULT_FACING_DEG: f32 = -90.0;
+
+/// Where the first `count` players of `team` start and which way they face.
+/// Players are dealt over the team's spawn points in turn; once every point
+/// has a player, the next ones go on rings around the points, spaced so
+/// nobody starts overlapping a teammate. Ring places that are blocked by the
+/// map are skipped; a point without any room left takes its extra players
+/// on the point itself.
+pub(crate) fn spawn_slots(map: &GameMap, team: Team, count: usize) -> Vec<(Vec2, f32)> {
+    let def = team_def(map, team);
+    let team_facing = def.facing_deg.unwrap_or(DEFAULT_FACING_DEG);
+    let points: Vec<(Vec2, f32)> = if def.spawns.is_empty() {
+        vec![(Vec2::new(def.spawn_x, def.spawn_y), team_facing)]
+    } else {
+        def.spawns
+            .iter()
+            .map(|s| (Vec2::new(s.x, s.y), s.facing_deg.unwrap_or(team_facing)))
+            .collect()
+    };
+
+    let spacing = map.physics.player_radius * SPAWN_RING_SPACING;
+    // rings wider than the map cannot hold anyone
+    let max_distance = Vec2::new(map.width, map.height).length();
+    let mut next_slot = vec![0; points.len()];
+    (0..count)
+        .map(|i| {
+            let (center, facing) = points[i % points.len()];
+            let next = &mut next_slot[i % points.len()];
+            loop {
+                let slot = *next;
+                if slot == 0 {
+                    *next += 1;
+                    return (center, facing);
+                }
+                let offset = ring_offset(slot, spacing);
+                if offset.length() > max_distance {
+                    return (center, facing);
+                }
+                *next += 1;
+                let pos = center + offset;
+                if !spawn_blocked(map, team, (pos.x, pos.y)) {
+                    return (pos, facing);
+                }
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn team_def(map: &GameMap, team: Team) -> &TeamDef {
+    match team {
+        Team::Team1 => &map.team1,
+        Team::Team2 => &map.team2,
+    }
+}
+
+/// Offset of the `slot`-th player around one spawn point: the point itself,
+/// then rings `spacing` apart, each holding as many players as fit around it.
+fn ring_offset(slot: usize, spacing: f32) -> Vec2 {
+    if slot == 0 {
+        return Vec2::ZERO;
+    }
+    let mut index = slot - 1;
+    let mut ring = 1;
+    loop {
+        let capacity = (TAU * ring as f32) as usize;
+        if index < capacity {
+            let angle = TAU * index as f32 / capacity as f32;
+            return Vec2::new(angle.cos(), angle.sin()) * spacing * ring as f32;
+        }
+        index -= capacity;
+        ring += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spin_snowball_shared::test_util::{color, map};
+
+    const RADIUS: f32 = 18.0;
+
+    fn map_with_spawns(spawns: &[(f32, f32)], objects: Vec<MapObject>) -> GameMap {
+        let mut map = map(objects);
+        map.physics.player_radius = RADIUS;
+        map.team1 = TeamDef {
+            spawn_x: 300.0,
+            spawn_y: 400.0,
+            spawns: spawns
+                .iter()
+                .map(|&(x, y)| SpawnPoint {
+                    x,
+                    y,
+                    facing_deg: None,
+                })
+                .collect(),
+            facing_deg: Some(0.0),
+        };
+        map
+    }
+
+    fn assert_apart(slots: &[(Vec2, f32)]) {
+        for (i, (a, _)) in slots.iter().enumerate() {
+            for (b, _) in &slots[..i] {
+                assert!(a.distance(*b) >= RADIUS * 2.0, "slots {a} and {b} overlap");
+            }
+        }
+    }
+
+    #[test]
+    fn ring_slots_do_not_overlap() {
+        let slots = spawn_slots(&map_with_spawns(&[], Vec::new()), Team::Team1, 60);
+        assert_eq!(slots.len(), 60);
+        assert_eq!(slots[0].0, Vec2::new(300.0, 400.0));
+        assert_apart(&slots);
+    }
+
+    #[test]
+    fn players_are_dealt_over_spawn_points() {
+        let map = map_with_spawns(&[(100.0, 100.0), (700.0, 100.0)], Vec::new());
+        let slots = spawn_slots(&map, Team::Team1, 40);
+        assert_eq!(slots[0].0, Vec2::new(100.0, 100.0));
+        assert_eq!(slots[1].0, Vec2::new(700.0, 100.0));
+        assert_apart(&slots);
+    }
+
+    #[test]
+    fn blocked_ring_slots_are_skipped() {
+        let hole = MapObject::Circle {
+            x: 400.0,
+            y: 400.0,
+            radius: 50.0,
+            factor: 1.0,
+            color: color(),
+            is_hole: true,
+            mask: Vec::new(),
+        };
+        // the spawn sits close to the top edge, with a hole to its right
+        let map = map_with_spawns(&[(330.0, 30.0), (330.0, 400.0)], vec![hole]);
+        let slots = spawn_slots(&map, Team::Team1, 30);
+        assert_eq!(slots.len(), 30);
+        assert_apart(&slots);
+        for (pos, _) in &slots[2..] {
+            assert!(
+                !spawn_blocked(&map, Team::Team1, (pos.x, pos.y)),
+                "{pos} is blocked"
+            );
+        }
+    }
+}
@@ -0,0 +1,551 @@
+use std::collections::{BTreeMap, HashMap};
+
+use glam::Vec2;
+use spin_snowball_shared::*;
+
+use crate::broadphase::MapIndex;
+use crate::physics::{simulate_collisions, simulate_movement, substeps, SimulateCollisionResponse};
+use crate::rules::{rules_for, GameModeRules};
+use crate::spawn::spawn_slots;
+
+/// Fastest a body may move; the sanity check puts back anything faster and
+/// the collision passes of a tick are sized for bodies up to this speed.
+pub(crate) const MAX_SPEED: f32 = 5000.0;
+
+pub struct Player {
+    pub id: String,
+    pub nick: String,
+    pub pos: Vec2,
+    pub vel: Vec2,
+    pub rot_deg: f32,
+    pub rotating_left: bool,
+    pub rotating_right: bool,
+    pub spin_timer: f32,
+    pub last_shoot_pressed: bool,
+    pub status: PlayerStatus,
+    pub shoot_cooldown_sec: f32,
+}
+
+impl Player {
+    pub fn new(id: String, nick: String) -> Self {
+        Self {
+            id,
+            nick,
+            pos: Vec2::ZERO,
+            vel: Vec2::ZERO,
+            rot_deg: -90.0,
+            rotating_left: false,
+            rotating_right: false,
+            spin_timer: 0.0,
+            last_shoot_pressed: false,
+            status: PlayerStatus::Spectator,
+            shoot_cooldown_sec: 0.0,
+        }
+    }
+}
+
+pub struct Snowball {
+    pub id: u64,
+    /// Id of the player who threw it.
+    pub owner: String,
+    /// Players it has hit so far; a contact lasts a few ticks but counts as one hit.
+    pub hit_players: Vec<String>,
+    pub pos: Vec2,
+    pub vel: Vec2,
+    pub life: f32,
+}
+
+#[derive(Clone)]
+pub struct Ball {
+    pub pos: Vec2,
+    pub vel: Vec2,
+    /// The last player to push the ball, directly or with a snowball.
+    pub last_touch: Option<String>,
+}
+
+/// Everything the simulation needs to advance one tick.
+///
+/// Players and snowballs are kept in ordered maps so that collisions are always
+/// resolved in the same order; the same inputs on the same world give the same result.
+pub struct World {
+    pub players: BTreeMap<String, Player>,
+    pub snowballs: BTreeMap<u64, Snowball>,
+    pub next_snowball_id: u64,
+    /// Replaced with `set_map`, which also rebuilds `map_index`; the physics
+    /// settings can be changed in place.
+    pub map: GameMap,
+    pub scores: HashMap<Team, u8>,
+    pub ball: Option<Ball>,
+    /// Changed with `set_game_mode`, which also swaps the rules.
+    pub game_mode: GameMode,
+    pub action_target_time: Option<f32>,
+    /// What happened since the caller last drained this, e.g. goals and hits.
+    pub events: Vec<GameEvent>,
+    /// Rules of `game_mode`; only `None` while one of their hooks runs.
+    pub(crate) rules: Option<Box<dyn GameModeRules>>,
+    /// Where the objects of `map` are, for the collision checks.
+    pub(crate) map_index: MapIndex,
+}
+
+impl World {
+    pub fn new(map: GameMap) -> Self {
+        let ball = map.ball.as_ref().map(|b| Ball {
+            pos: Vec2::new(b.spawn_x, b.spawn_y),
+            vel: Vec2::ZERO,
+            last_touch: None,
+        });
+
+        Self {
+            players: BTreeMap::new(),
+            snowballs: BTreeMap::new(),
+            next_snowball_id: 1,
+            scores: [(Team::Team1, 0), (Team::Team2, 0)].into(),
+            ball,
+            map_index: MapIndex::new(&map),
+            map,
+            game_mode: GameMode::Fight,
+            action_target_time: Some(10.0),
+            events: Vec::new(),
+            rules: Some(rules_for(GameMode::Fight)),
+        }
+    }
+
+    /// Switches to another mode, whose rules start with fresh state.
+    pub fn set_game_mode(&mut self, game_mode: GameMode) {
+        self.game_mode = game_mode;
+        self.rules = Some(rules_for(game_mode));
+    }
+
+    /// Plays by `rules` instead of the built-in rules of the current mode,
+    /// e.g. those of a script.
+    pub fn set_rules(&mut self, rules: Box<dyn GameModeRules>) {
+        self.rules = Some(rules);
+    }
+
+    /// Runs `f` with the rules taken out of the world, so that both can be borrowed mutably.
+    fn with_rules<R>(&mut self, f: impl FnOnce(&mut dyn GameModeRules, &mut World) -> R) -> R {
+        let mut rules = self.rules.take().expect("rules hooks do not nest");
+        let result = f(rules.as_mut(), self);
+        self.rules = Some(rules);
+        result
+    }
+
+    /// What the HUD shows about the objective of the current mode.
+    pub fn hud(&self) -> ModeHud {
+        self.rules.as_ref().map(|r| r.hud()).unwrap_or_default()
+    }
+
+    /// Whether the mode considers the match decided, given the score limit.
+    pub fn is_over(&self, score_limit: Option<u8>) -> bool {
+        self.rules
+            .as_ref()
+            .is_some_and(|r| r.is_over(self, score_limit))
+    }
+
+    /// Zeroes the scores and sends everyone to their spawn for a new match.
+    pub fn start_match(&mut self) {
+        self.scores = [(Team::Team1, 0), (Team::Team2, 0)].into();
+        self.reset_positions();
+        self.with_rules(|rules, world| rules.on_match_start(world));
+    }
+
+    pub fn get_team_of_player(&self, player_id: &str) -> Option<Team> {
+        self.players.get(player_id).and_then(|x| {
+            if let PlayerStatus::Playing(x) = x.status {
+                Some(x)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Applies one input of a playing player. Rotation keys are held until the next
+    /// input; a snowball is fired when `shoot` goes from false to true.
+    pub fn apply_input(&mut self, id: &str, left: bool, right: bool, shoot: bool) {
+        if let Some(p) = self.players.get_mut(id) {
+            if let PlayerStatus::Playing(_) = p.status {
+                p.rotating_left = left;
+                p.rotating_right = right;
+
+                if p.shoot_cooldown_sec > 0.0 {
+                    p.last_shoot_pressed = true;
+                    return;
+                }
+
+                // Edge-detect the shoot button on server side:
+                // only spawn a snowball when shoot transitions from false -> true
+                if shoot && !p.last_shoot_pressed {
+                    // --- Charge ---
+                    let max_charge = 1.0;
+                    let charge = p.spin_timer.min(max_charge);
+                    let charge_t = (charge / max_charge).clamp(0.0, 1.0);
+
+                    // --- Snowball speed ---
+                    let base_speed = 300.0;
+                    let max_extra_speed = 700.0;
+                    let snowball_speed = base_speed + max_extra_speed * charge_t;
+
+                    // --- Direction ---
+                    let r = p.rot_deg.to_radians();
+                    let dir = Vec2::new(r.cos(), r.sin());
+
+                    // --- Spawn ---
+                    let spawn_pos = p.pos + dir * 26.0;
+
+                    let id = self.next_snowball_id;
+                    self.next_snowball_id += 1;
+                    self.snowballs.insert(
+                        id,
+                        Snowball {
+                            id,
+                            owner: p.id.clone(),
+                            hit_players: Vec::new(),
+                            pos: spawn_pos,
+                            vel: dir * snowball_speed,
+                            life: self.map.physics.snowball_lifetime_sec,
+                        },
+                    );
+
+                    // --- Recoil (momentum-based) ---
+                    let snowball_mass = self.map.physics.snowball_mass;
+                    let player_mass = self.map.physics.player_mass;
+
+                    let recoil_velocity = (snowball_mass * snowball_speed / player_mass)
+                        * self.map.physics.recoil_power;
+
+                    p.vel -= dir * recoil_velocity;
+
+                    // --- Reset ---
+                    p.spin_timer = 0.0;
+                    p.shoot_cooldown_sec = self.map.physics.shoot_cooldown_sec;
+                    p.last_shoot_pressed = true;
+                } else if !shoot {
+                    p.last_shoot_pressed = false;
+                }
+            }
+        }
+    }
+
+    /// Advances the world by one tick of a running match: timers, mode rules,
+    /// movement and collisions. Movement and collisions run in several passes
+    /// when something is fast. Returns the collisions so callers can react to them.
+    pub fn step(&mut self, dt: f32) -> SimulateCollisionResponse {
+        self.logic_step(dt);
+        self.with_rules(|rules, world| rules.on_tick(world, dt));
+        let response = self.move_and_collide(dt);
+
+        for (player, by) in &response.players_hit_by_snowball {
+            self.events.push(GameEvent::PlayerHit {
+                player: player.clone(),
+                by: by.clone(),
+            });
+        }
+        self.with_rules(|rules, world| rules.on_collisions(&response, world));
+        response
+    }
+
+    /// Advances timers, movement and collisions without the mode rules, e.g. to
+    /// predict the own player on a client while the rules only run on the server.
+    pub fn step_physics(&mut self, dt: f32) -> SimulateCollisionResponse {
+        self.logic_step(dt);
+        self.move_and_collide(dt)
+    }
+
+    /// Moves everything by `dt` in as many passes as the fastest body needs.
+    /// Collisions can speed bodies up, so the passes for the rest of the tick
+    /// are counted again after each one.
+    fn move_and_collide(&mut self, dt: f32) -> SimulateCollisionResponse {
+        let mut response = SimulateCollisionResponse::default();
+        let mut remaining = dt;
+        loop {
+            let passes = substeps(self, remaining);
+            let pass_dt = remaining / passes as f32;
+            simulate_movement(self, pass_dt);
+            let pass = simulate_collisions(self);
+            for sid in pass.snowballs_in_holes.iter() {
+                self.snowballs.remove(sid);
+            }
+            response.merge(pass);
+            if passes == 1 {
+                return response;
+            }
+            remaining -= pass_dt;
+        }
+    }
+
+    fn logic_step(&mut self, dt: f32) {
+        for p in self.players.values_mut() {
+            if p.shoot_cooldown_sec > 0.0 {
+                p.shoot_cooldown_sec -= dt;
+            }
+
+            if p.rotating_left || p.rotating_right {
+                p.spin_timer += dt;
+            }
+        }
+        let mut dead = Vec::new();
+        for (&id, sb) in self.snowballs.iter_mut() {
+            sb.vel *= 0.995;
+            sb.life -= dt;
+            if sb.life <= 0.0 {
+                dead.push(id);
+            }
+        }
+        for id in dead {
+            self.snowballs.remove(&id);
+        }
+    }
+
+    fn place_player(p: &mut Player, (pos, facing_deg): (Vec2, f32)) {
+        p.pos = pos;
+        p.vel = Vec2::ZERO;
+        p.rot_deg = facing_deg;
+    }
+
+    /// Switches to another map, bringing the ball in line with it and sending
+    /// everyone back to their spawn.
+    pub fn set_map(&mut self, map: GameMap) {
+        self.ball = map.ball.as_ref().map(|b| Ball {
+            pos: Vec2::new(b.spawn_x, b.spawn_y),
+            vel: Vec2::ZERO,
+            last_touch: None,
+        });
+        self.map_index = MapIndex::new(&map);
+        self.map = map;
+        self.reset_positions();
+    }
+
+    /// Puts a single player on a free spawn of their team, e.g. when joining a running match.
+    pub fn respawn(&mut self, id: &str) {
+        let Some(PlayerStatus::Playing(team)) = self.players.get(id).map(|p| p.status) else {
+            return;
+        };
+        let radius = self.map.physics.player_radius;
+        let teammates: Vec<Vec2> = self
+            .players
+            .values()
+            .filter(|p| p.id != id && p.status == PlayerStatus::Playing(team))
+            .map(|p| p.pos)
+            .collect();
+
+        let slots = spawn_slots(&self.map, team, teammates.len() + 1);
+        let free = slots
+            .iter()
+            .find(|(pos, _)| teammates.iter().all(|t| t.distance(*pos) >= radius * 2.0))
+            .unwrap_or(&slots[teammates.len()]);
+        if let Some(p) = self.players.get_mut(id) {
+            Self::place_player(p, *free);
+        }
+    }
+
+    /// Sends everyone and the ball back to their spawn and clears the mode's
+    /// round state, e.g. who holds the flag.
+    pub fn reset_positions(&mut self) {
+        for team in [Team::Team1, Team::Team2] {
+            let mut team_players: Vec<&mut Player> = self
+                .players
+                .values_mut()
+                .filter(|p| p.status == PlayerStatus::Playing(team))
+                .collect();
+            let slots = spawn_slots(&self.map, team, team_players.len());
+            for (p, slot) in team_players.iter_mut().zip(slots) {
+                Self::place_player(p, slot);
+            }
+        }
+
+        self.snowballs = BTreeMap::new();
+        if let Some(rules) = &mut self.rules {
+            rules.on_reset();
+        }
+        if let Some(x) = self.map.ball.clone() {
+            if let Some(ball) = &mut self.ball {
+                ball.pos = Vec2::new(x.spawn_x, x.spawn_y);
+                ball.vel = Vec2::ZERO;
+                ball.last_touch = None;
+            }
+        }
+    }
+
+    /// Puts players and the ball back on their spawn if they ended up somewhere
+    /// the physics can't recover from, and drops such snowballs.
+    pub fn sanity_check(&mut self) {
+        let lost: Vec<String> = self
+            .players
+            .values_mut()
+            .filter_map(|p| sanity_check_player(p, &self.map).then(|| p.id.clone()))
+            .collect();
+        for id in lost {
+            self.respawn(&id);
+        }
+
+        // snowballs are not worth putting back, lost ones just melt
+        self.snowballs.retain(|_, s| {
+            !vec2_invalid(s.pos) && !vec2_invalid(s.vel) && s.vel.length() <= MAX_SPEED
+        });
+
+        if let Some(ball) = &mut self.ball {
+            sanity_check_ball(ball, &self.map);
+        }
+    }
+}
+
+#[inline]
+fn vec2_invalid(v: Vec2) -> bool {
+    !v.x.is_finite() || !v.y.is_finite()
+}
+
+#[inline]
+fn out_of_bounds(pos: Vec2, map: &GameMap) -> bool {
+    pos.x < 0.0 || pos.y < 0.0 || pos.x > map.width || pos.y > map.height
+}
+
+/// Stops a player that ended up somewhere the physics can't recover from and
+/// returns whether it has to be put back on a spawn.
+fn sanity_check_player(player: &mut Player, map: &GameMap) -> bool {
+    if vec2_invalid(player.pos)
+        || vec2_invalid(player.vel)
+        || player.vel.length() > MAX_SPEED
+        || out_of_bounds(player.pos, map)
+    {
+        player.vel = Vec2::ZERO;
+        player.spin_timer = 0.0;
+        player.rotating_left = false;
+        player.rotating_right = false;
+        return true;
+    }
+
+    false
+}
+
+fn sanity_check_ball(ball: &mut Ball, map: &GameMap) -> bool {
+    if vec2_invalid(ball.pos)
+        || vec2_invalid(ball.vel)
+        || ball.vel.length() > MAX_SPEED
+        || out_of_bounds(ball.pos, map)
+    {
+        if let Some(ball_def) = &map.ball {
+            ball.pos = Vec2::new(ball_def.spawn_x, ball_def.spawn_y);
+        } else {
+            ball.pos = Vec2::ZERO;
+        }
+
+        ball.vel = Vec2::ZERO;
+        return true;
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DT;
+    use spin_snowball_shared::test_util::{color, map};
+
+    fn wall(x: f32) -> MapObject {
+        MapObject::Line {
+            ax: x,
+            ay: 0.0,
+            bx: x,
+            by: 1000.0,
+            factor: 1.0,
+            color: color(),
+            is_hole: false,
+            mask: vec![CollisionMaskTag::Snowball],
+        }
+    }
+
+    #[test]
+    fn fast_snowball_bounces_off_thin_line() {
+        for speed in [1000.0, MAX_SPEED] {
+            let mut world = World::new(map(vec![wall(500.0)]));
+            world.snowballs.insert(
+                1,
+                Snowball {
+                    id: 1,
+                    owner: "p".into(),
+                    hit_players: Vec::new(),
+                    pos: Vec2::new(430.0, 500.0),
+                    vel: Vec2::new(speed, 0.0),
+                    life: 3.0,
+                },
+            );
+
+            for _ in 0..3 {
+                world.step(DT);
+                let s = &world.snowballs[&1];
+                assert!(
+                    s.pos.x < 500.0,
+                    "snowball at {} went through the line to {}",
+                    speed,
+                    s.pos
+                );
+            }
+            assert!(world.snowballs[&1].vel.x < 0.0);
+        }
+    }
+
+    #[test]
+    fn snowball_sped_up_during_a_tick_still_collides() {
+        // the bouncy post throws the slow snowball back fast enough to jump
+        // over the hole line within the same tick, unless passes are recounted
+        let post = MapObject::Circle {
+            x: 520.0,
+            y: 500.0,
+            radius: 10.0,
+            factor: 5.0,
+            color: color(),
+            is_hole: false,
+            mask: vec![CollisionMaskTag::Snowball],
+        };
+        let hole = MapObject::Line {
+            ax: 457.0,
+            ay: 0.0,
+            bx: 457.0,
+            by: 1000.0,
+            factor: 1.0,
+            color: color(),
+            is_hole: true,
+            mask: vec![CollisionMaskTag::Snowball],
+        };
+        let mut world = World::new(map(vec![hole, post]));
+        world.snowballs.insert(
+            1,
+            Snowball {
+                id: 1,
+                owner: "p".into(),
+                hit_players: Vec::new(),
+                pos: Vec2::new(490.0, 500.0),
+                vel: Vec2::new(300.0, 0.0),
+                life: 3.0,
+            },
+        );
+
+        for _ in 0..3 {
+            world.step(DT);
+        }
+        assert!(world.snowballs.is_empty());
+    }
+
+    #[test]
+    fn lost_player_goes_back_to_a_spawn_point() {
+        let mut map = map(Vec::new());
+        map.team1.spawns = vec![SpawnPoint {
+            x: 200.0,
+            y: 300.0,
+            facing_deg: Some(45.0),
+        }];
+        let mut world = World::new(map);
+        let mut player = Player::new("p".into(), "p".into());
+        player.status = PlayerStatus::Playing(Team::Team1);
+        player.pos = Vec2::new(f32::NAN, 10.0);
+        player.vel = Vec2::new(100.0, 0.0);
+        world.players.insert("p".into(), player);
+
+        world.sanity_check();
+        let p = &world.players["p"];
+        assert_eq!(p.pos, Vec2::new(200.0, 300.0));
+        assert_eq!(p.vel, Vec2::ZERO);
+        assert_eq!(p.rot_deg, 45.0);
+    }
+}